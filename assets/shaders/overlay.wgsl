@@ -0,0 +1,19 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+@group(1) @binding(0)
+var array_texture: texture_2d_array<f32>;
+@group(1) @binding(1)
+var array_texture_sampler: sampler;
+@group(1) @binding(2)
+var<uniform> array_texture_layer : f32;
+
+struct FragmentInput {
+    #import bevy_pbr::mesh_vertex_output
+};
+
+// Unlit, the overlay's alpha blends it over the lit surface beneath
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    return textureSample(array_texture, array_texture_sampler, in.uv, i32(array_texture_layer));
+}
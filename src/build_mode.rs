@@ -0,0 +1,323 @@
+use bevy::{
+    prelude::*,
+    render::mesh::Indices,
+    render::render_resource::PrimitiveTopology,
+};
+
+use super::character_controller::KinematicCharacterController;
+use super::player::PlayerCamera;
+use super::player_input::PlayerInput;
+use super::voxel::prelude::*;
+
+/// Block placement and removal for tweaking the vorld in game, toggled with B
+pub struct BuildMode {
+    pub is_active: bool,
+    /// Maximum distance from the camera at which blocks can be targeted
+    pub reach: f32,
+    /// Blocks available to place, in hotbar order
+    pub hotbar: Vec<BlockIds>,
+    pub selected_slot: usize,
+}
+
+impl BuildMode {
+    pub fn get_selected_block(&self) -> BlockIds {
+        self.hotbar[self.selected_slot]
+    }
+
+    /// Every placeable block in the block registry, in id order
+    pub fn get_placeable_blocks() -> Vec<BlockIds> {
+        (0..=u8::MAX)
+            .filter(|id| get_block_properties(*id).is_placeable)
+            .filter_map(BlockIds::from_id)
+            .collect()
+    }
+}
+
+#[derive(Component)]
+pub struct BuildHighlight;
+
+/// Tint over the face of the highlighted voxel a block would be placed against
+#[derive(Component)]
+pub struct BuildFaceHighlight;
+
+#[derive(Component)]
+pub struct BuildCrosshair;
+
+#[derive(Component)]
+pub struct Hotbar;
+
+pub struct BuildModePlugin;
+
+impl Plugin for BuildModePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BuildMode {
+            is_active: false,
+            reach: 6.0,
+            hotbar: BuildMode::get_placeable_blocks(),
+            selected_slot: 0,
+        });
+        app.add_startup_system(setup)
+            .add_system(update_build_mode)
+            .add_system(build.after(update_build_mode))
+            .add_system(update_hotbar.after(update_build_mode));
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn_bundle(PbrBundle {
+        mesh: meshes.add(build_wireframe_cube_mesh(1.01)),
+        material: materials.add(StandardMaterial {
+            base_color: Color::WHITE,
+            unlit: true,
+            ..default()
+        }),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }).insert(BuildHighlight);
+
+    commands.spawn_bundle(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE))),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgba(1.0, 1.0, 1.0, 0.2),
+            unlit: true,
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        }),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }).insert(BuildFaceHighlight);
+
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { left: Val::Percent(50.0), top: Val::Percent(50.0), ..default() },
+            margin: UiRect { left: Val::Px(-2.0), top: Val::Px(-2.0), ..default() },
+            size: Size::new(Val::Px(4.0), Val::Px(4.0)),
+            ..default()
+        },
+        color: UiColor(Color::WHITE),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }).insert(BuildCrosshair);
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands.spawn_bundle(TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { left: Val::Px(10.0), bottom: Val::Px(10.0), ..default() },
+            ..default()
+        },
+        text: Text::from_section("", TextStyle { font, font_size: 20.0, color: Color::WHITE }),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }).insert(Hotbar);
+}
+
+/// Line list mesh of the edges of a cube of the given size, with its minimum corner at the origin
+fn build_wireframe_cube_mesh(size: f32) -> Mesh {
+    let offset = 0.5 * (size - 1.0);
+    let positions: Vec<[f32; 3]> = (0..8)
+        .map(|i| [
+            (i & 1) as f32 * size - offset,
+            ((i >> 1) & 1) as f32 * size - offset,
+            ((i >> 2) & 1) as f32 * size - offset,
+        ])
+        .collect();
+    // Each edge joins corners whose indices differ by a single bit
+    let mut indices = Vec::new();
+    for i in 0..8u32 {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                indices.extend([i, i | bit]);
+            }
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+pub fn update_build_mode(
+    mut build_mode: ResMut<BuildMode>,
+    mut player_input: ResMut<PlayerInput>,
+    mut crosshair_query: Query<&mut Visibility, (With<BuildCrosshair>, Without<Hotbar>)>,
+    mut hotbar_query: Query<&mut Visibility, (With<Hotbar>, Without<BuildCrosshair>)>,
+) {
+    if player_input.toggle_build_mode_requested {
+        player_input.toggle_build_mode_requested = false;
+        build_mode.is_active = !build_mode.is_active;
+        // Don't carry clicks made in one mode over to the other
+        player_input.shoot_requested = false;
+        player_input.place_requested = false;
+
+        for mut visibility in crosshair_query.iter_mut() {
+            visibility.is_visible = build_mode.is_active;
+        }
+        for mut visibility in hotbar_query.iter_mut() {
+            visibility.is_visible = build_mode.is_active;
+        }
+    }
+
+    if !build_mode.is_active {
+        player_input.place_requested = false;
+        return;
+    }
+
+    let slot_count = build_mode.hotbar.len();
+    if let Some(slot) = player_input.hotbar_slot_requested {
+        if slot < slot_count {
+            build_mode.selected_slot = slot;
+        }
+    }
+    if player_input.hotbar_scroll > 0.0 {
+        build_mode.selected_slot = (build_mode.selected_slot + slot_count - 1) % slot_count;
+    } else if player_input.hotbar_scroll < 0.0 {
+        build_mode.selected_slot = (build_mode.selected_slot + 1) % slot_count;
+    }
+}
+
+/// Highlights the voxel under the crosshair and tints the face targeted, left click removes it and right click places the selected block against the face targeted
+#[allow(clippy::type_complexity)]
+pub fn build(
+    mut vorld: ResMut<Vorld>,
+    build_mode: Res<BuildMode>,
+    mut player_input: ResMut<PlayerInput>,
+    camera_query: Query<&GlobalTransform, With<PlayerCamera>>,
+    character_query: Query<(&Transform, &KinematicCharacterController)>,
+    mut highlight_query: Query<(&mut Transform, &mut Visibility), (With<BuildHighlight>, Without<BuildFaceHighlight>, Without<KinematicCharacterController>)>,
+    mut face_highlight_query: Query<(&mut Transform, &mut Visibility), (With<BuildFaceHighlight>, Without<BuildHighlight>, Without<KinematicCharacterController>)>,
+) {
+    let hit = match (build_mode.is_active, camera_query.iter().next()) {
+        (true, Some(camera_transform)) => vorld.raycast(
+            camera_transform.translation(),
+            camera_transform.forward(),
+            build_mode.reach,
+            |id| {
+                let properties = get_block_properties(id);
+                properties.is_meshed || properties.has_block_entity
+            },
+        ),
+        _ => None,
+    };
+
+    for (mut transform, mut visibility) in highlight_query.iter_mut() {
+        if let Some(hit) = hit {
+            transform.translation = hit.position.as_vec3();
+        }
+        if visibility.is_visible != hit.is_some() {
+            visibility.is_visible = hit.is_some();
+        }
+    }
+
+    // There's no face to tint when the camera starts inside the voxel hit
+    let highlighted_face = hit.filter(|hit| hit.normal != IVec3::ZERO);
+    for (mut transform, mut visibility) in face_highlight_query.iter_mut() {
+        if let Some(hit) = highlighted_face {
+            let normal = hit.normal.as_vec3();
+            // Lift the tint slightly off the face so it isn't hidden by it
+            transform.translation = hit.position.as_vec3() + Vec3::splat(0.5) + 0.505 * normal;
+            transform.rotation = Quat::from_rotation_arc(Vec3::Z, normal);
+        }
+        if visibility.is_visible != highlighted_face.is_some() {
+            visibility.is_visible = highlighted_face.is_some();
+        }
+    }
+
+    if !build_mode.is_active {
+        return;
+    }
+    let remove_requested = player_input.shoot_requested;
+    let place_requested = player_input.place_requested;
+    player_input.shoot_requested = false;
+    player_input.place_requested = false;
+
+    if let Some(hit) = hit {
+        if remove_requested {
+            // Blocks which can't be damaged, e.g. bedrock, can't be removed either
+            if get_block_properties(hit.id).hardness.is_some() {
+                vorld.add_voxel(BlockIds::Air as u8, hit.position.x, hit.position.y, hit.position.z);
+            }
+        } else if place_requested && hit.normal != IVec3::ZERO {
+            let position = hit.position + hit.normal;
+            let overlaps_character = character_query.iter().any(|(character_transform, controller)| {
+                overlaps_capsule(position, character_transform.translation, controller)
+            });
+            if overlaps_character {
+                debug!("Refusing to place block at {} as it overlaps a character", position);
+            } else {
+                vorld.add_voxel(build_mode.get_selected_block() as u8, position.x, position.y, position.z);
+            }
+        }
+    }
+}
+
+/// Checks the voxel at position against a character's capsule, as used for movement in character_controller
+fn overlaps_capsule(position: IVec3, character_translation: Vec3, controller: &KinematicCharacterController) -> bool {
+    let radius = controller.collision_config.collider_radius;
+    let half_height = controller.get_half_height();
+    let center = character_translation + half_height * Vec3::Y;
+    let half_segment = half_height - controller.collision_config.skin_depth - radius;
+    let min = position.as_vec3();
+    let max = min + Vec3::ONE;
+
+    // The capsule's segment is vertical, so the closest points are found independently in x/z and y
+    let closest_xz = Vec2::new(center.x, center.z).clamp(Vec2::new(min.x, min.z), Vec2::new(max.x, max.z));
+    let distance_xz = closest_xz.distance(Vec2::new(center.x, center.z));
+    let distance_y = (min.y - (center.y + half_segment)).max((center.y - half_segment) - max.y).max(0.0);
+    distance_xz * distance_xz + distance_y * distance_y < radius * radius
+}
+
+fn update_hotbar(
+    build_mode: Res<BuildMode>,
+    mut hotbar_query: Query<&mut Text, With<Hotbar>>,
+) {
+    if !build_mode.is_changed() {
+        return;
+    }
+    for mut text in hotbar_query.iter_mut() {
+        let style = text.sections[0].style.clone();
+        text.sections = build_mode.hotbar.iter().enumerate().map(|(slot, block)| {
+            let is_selected = slot == build_mode.selected_slot;
+            TextSection::new(
+                format!("{} {:?}   ", slot + 1, block),
+                TextStyle {
+                    color: if is_selected { Color::YELLOW } else { Color::WHITE },
+                    ..style.clone()
+                },
+            )
+        }).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotbar_offers_placeable_blocks_from_registry() {
+        let hotbar = BuildMode::get_placeable_blocks();
+        assert_eq!(hotbar.first(), Some(&BlockIds::Grass));
+        assert!(hotbar.contains(&BlockIds::DoorClosed));
+        assert!(hotbar.contains(&BlockIds::Ladder));
+        assert!(hotbar.contains(&BlockIds::Lava));
+        assert!(hotbar.contains(&BlockIds::Screen));
+        assert!(!hotbar.contains(&BlockIds::Air));
+        assert!(!hotbar.contains(&BlockIds::DoorOpen));
+        assert!(!hotbar.contains(&BlockIds::Bedrock));
+        // Every id the registry knows about maps back to itself
+        for id in 0..=u8::MAX {
+            if let Some(block) = BlockIds::from_id(id) {
+                assert_eq!(block as u8, id);
+            }
+        }
+    }
+}
@@ -0,0 +1,1063 @@
+use bevy::{prelude::*, time::{FixedTimestep, FixedTimesteps}};
+use bevy_rapier3d::prelude::*;
+
+use super::named_collision_groups::*;
+use super::voxel::collision::{self, VoxelSweep};
+use super::voxel::prelude::*;
+use super::water::{self, WaterVolume};
+
+/// Characters are moved at a fixed rate so movement doesn't depend on frame rate
+pub const TIMESTEP: f32 = 1.0 / 60.0;
+const TIMESTEP_LABEL: &str = "character_controller";
+
+/// Looking further down than this while climbing makes forward input climb down rather than up
+const CLIMB_DOWN_PITCH: f32 = std::f32::consts::FRAC_PI_6;
+
+/// Fraction of the character's height which must be underwater for them to swim
+const MIN_SWIM_SUBMERSION: f32 = 0.5;
+/// Fraction of the character's height underwater when floating at the surface
+const FLOAT_SUBMERSION: f32 = 0.6;
+
+/// Stage after CoreStage::Update in which characters are moved, run TIMESTEP apart
+#[derive(StageLabel)]
+pub struct CharacterControllerStage;
+
+#[derive(Clone)]
+pub struct CharacterMovementConfig {
+    pub acceleration: f32,
+    pub air_acceleration: f32,
+    pub max_run_speed: f32,
+    pub max_sprint_speed: f32,
+    pub max_walk_speed: f32,
+    pub max_air_movement_speed: f32,
+    pub stop_speed: f32,
+    pub jump_delta_v: f32,
+    pub crouch_jump_delta_v: f32,
+    /// Time in seconds after walking off a ledge during which a jump can still be made
+    pub coyote_time: f32,
+    /// Time in seconds a jump requested before landing is kept for, so it is made on landing
+    pub jump_buffer_time: f32,
+    /// Number of extra jumps which can be made before landing again
+    pub max_air_jumps: u32,
+    pub climb_speed: f32,
+    pub swim_speed: f32,
+    /// Rate per second at which velocity in water approaches the desired swimming velocity
+    pub swim_drag: f32,
+    /// Speed at which a fully submerged character rises towards the surface when not swimming
+    pub buoyancy: f32,
+    pub acceleration_due_to_gravity: f32,
+}
+
+#[derive(Clone)]
+pub struct CharacterCollisionConfig {
+    pub standing_half_height: f32,
+    pub crouched_half_height: f32,
+    pub skin_depth: f32,
+    pub collider_radius: f32,
+    /// Tallest ledge a grounded character walks up onto without jumping
+    pub max_step_height: f32,
+    /// Steepest ground in radians which can be stood on, steeper ground is slid down
+    pub max_slope_angle: f32,
+    /// Furthest a grounded character is pulled down to stay on the ground, e.g. when walking down a slope
+    pub ground_snap_distance: f32,
+    /// Collide with the Vorld's voxels directly using voxel::collision, rather than Rapier shape casts against the chunk meshes
+    pub use_voxel_collision: bool,
+}
+
+impl CharacterCollisionConfig {
+    /// Minimum y component of the normal of ground which can be stood on
+    pub fn get_min_walkable_normal_y(&self) -> f32 {
+        self.max_slope_angle.cos()
+    }
+}
+
+/// Capsule shaped character moved by sweeping against the terrain rather than by the physics simulation
+/// The entity's translation is at the bottom of the capsule, and its rotation sets the directions movement input is relative to
+#[derive(Component)]
+pub struct KinematicCharacterController {
+    pub movement_config: CharacterMovementConfig,
+    pub collision_config: CharacterCollisionConfig,
+    pub velocity: Vec3,
+    pub is_grounded: bool,
+    /// Normal of the ground the character is standing on, Vec3::Y when not grounded
+    pub ground_normal: Vec3,
+    pub is_crouched: bool,
+    pub is_sprinting: bool,
+    /// Whether the character is on a climbable block, e.g. a ladder, and so isn't affected by gravity
+    pub is_climbing: bool,
+    /// Whether enough of the character is underwater that they swim rather than walk
+    pub is_swimming: bool,
+    pub is_head_underwater: bool,
+    /// Time in seconds since the character was last grounded, or since it last jumped if more recent
+    pub time_since_grounded: f32,
+    /// Time in seconds left in which to make the last requested jump
+    pub jump_buffer_timer: f32,
+    pub air_jumps_made: u32,
+    /// Downward speed the character hit the ground at if it landed during the last move
+    pub landing_speed: Option<f32>,
+    /// Height stepped up onto a ledge during the last move, so cameras can smooth it out
+    pub step_height: f32,
+    /// Translation before the last move, None until the character has moved
+    pub previous_translation: Option<Vec3>,
+}
+
+impl KinematicCharacterController {
+    pub fn new(movement_config: CharacterMovementConfig, collision_config: CharacterCollisionConfig) -> Self {
+        Self {
+            movement_config,
+            collision_config,
+            velocity: Vec3::ZERO,
+            is_grounded: false,
+            ground_normal: Vec3::Y,
+            is_crouched: false,
+            is_sprinting: false,
+            is_climbing: false,
+            is_swimming: false,
+            is_head_underwater: false,
+            time_since_grounded: f32::MAX,
+            jump_buffer_timer: 0.0,
+            air_jumps_made: 0,
+            landing_speed: None,
+            step_height: 0.0,
+            previous_translation: None,
+        }
+    }
+
+    pub fn get_half_height(&self) -> f32 {
+        match self.is_crouched {
+            false => self.collision_config.standing_half_height,
+            true => self.collision_config.crouched_half_height,
+        }
+    }
+
+    /// Box around the capsule, for collision with voxel::collision
+    pub fn get_aabb(&self, translation: Vec3, half_height: f32) -> (Vec3, Vec3) {
+        let radius = self.collision_config.collider_radius;
+        let skin_depth = self.collision_config.skin_depth;
+        (
+            translation + Vec3::new(-radius, skin_depth, -radius),
+            translation + Vec3::new(radius, 2.0 * half_height - skin_depth, radius),
+        )
+    }
+
+    /// Translation to draw the character at, between its previous and current translation by interpolation_fraction
+    pub fn get_interpolated_translation(&self, translation: Vec3, interpolation_fraction: f32) -> Vec3 {
+        match self.previous_translation {
+            Some(previous_translation) => previous_translation.lerp(translation, interpolation_fraction),
+            None => translation,
+        }
+    }
+
+    /// Capsule collider for the given half height, shrunk by the skin depth
+    fn get_shape(&self, half_height: f32) -> Collider {
+        let config = &self.collision_config;
+        Collider::capsule_y(half_height - config.skin_depth - config.collider_radius, config.collider_radius)
+    }
+}
+
+/// Movement requested of a KinematicCharacterController this frame, written by whatever drives the character
+#[derive(Component, Default)]
+pub struct CharacterInput {
+    /// Desired movement direction in the character's local space, only x and z are used
+    pub movement_direction: Vec3,
+    /// Cleared by the controller once the request has been buffered
+    pub jump_requested: bool,
+    pub crouch_requested: bool,
+    pub sprint_requested: bool,
+    pub walk_requested: bool,
+    /// Angle in radians the character is looking above the horizontal, sets the direction of climbing
+    pub look_pitch: f32,
+}
+
+/// Limits how long a character with a KinematicCharacterController can sprint for
+/// Drains while sprinting and regenerates otherwise, once emptied sprinting is blocked until it recovers to recovery_threshold
+#[derive(Component)]
+pub struct Stamina {
+    pub max_stamina: f32,
+    pub current_stamina: f32,
+    /// Stamina used per second of sprinting
+    pub drain_rate: f32,
+    /// Stamina regained per second when not sprinting
+    pub regeneration_rate: f32,
+    pub recovery_threshold: f32,
+    pub is_exhausted: bool,
+}
+
+impl Stamina {
+    pub fn new(max_stamina: f32, drain_rate: f32, regeneration_rate: f32, recovery_threshold: f32) -> Self {
+        Self {
+            max_stamina,
+            current_stamina: max_stamina,
+            drain_rate,
+            regeneration_rate,
+            recovery_threshold,
+            is_exhausted: false,
+        }
+    }
+
+    /// Proportion of stamina remaining, 0 -> 1, for display
+    #[allow(dead_code)]
+    pub fn get_fraction(&self) -> f32 {
+        match self.max_stamina > 0.0 {
+            true => self.current_stamina / self.max_stamina,
+            false => 0.0,
+        }
+    }
+
+    fn update(&mut self, is_sprinting: bool, time_delta: f32) {
+        if is_sprinting {
+            self.current_stamina = (self.current_stamina - self.drain_rate * time_delta).max(0.0);
+            self.is_exhausted |= self.current_stamina == 0.0;
+        } else {
+            self.current_stamina = (self.current_stamina + self.regeneration_rate * time_delta).min(self.max_stamina);
+            self.is_exhausted &= self.current_stamina < self.recovery_threshold;
+        }
+    }
+}
+
+pub struct CharacterControllerPlugin;
+
+impl Plugin for CharacterControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_stage_after(
+            CoreStage::Update,
+            CharacterControllerStage,
+            SystemStage::parallel()
+                .with_run_criteria(FixedTimestep::step(TIMESTEP as f64).with_label(TIMESTEP_LABEL))
+                .with_system(move_characters),
+        );
+    }
+}
+
+/// How far between the last two moves characters should be drawn, 0 -> 1, as frames don't line up with moves
+pub fn get_interpolation_fraction(fixed_timesteps: &FixedTimesteps) -> f32 {
+    fixed_timesteps.get(TIMESTEP_LABEL).map_or(1.0, |state| (state.overstep_percentage() as f32).min(1.0))
+}
+
+pub fn move_characters(
+    rapier_context: Res<RapierContext>,
+    vorld: Res<Vorld>,
+    water_query: Query<&WaterVolume>,
+    mut character_query: Query<(&mut Transform, &mut KinematicCharacterController, &mut CharacterInput, Option<&mut Stamina>)>,
+) {
+    let time_delta = TIMESTEP;
+    let terrain_filter = QueryFilter::only_fixed().groups(InteractionGroups::new(NamedCollisionGroups::Everything as u32, NamedCollisionGroups::Terrain as u32));
+    let water_volumes: Vec<&WaterVolume> = water_query.iter().collect();
+
+    for (mut transform, mut controller, mut input, mut stamina) in character_query.iter_mut() {
+        let can_sprint = stamina.as_ref().is_none_or(|stamina| !stamina.is_exhausted);
+        let is_moving = input.movement_direction.length_squared() > 0.0;
+        // Keep sprinting through jumps, but only start sprinting from the ground
+        controller.is_sprinting = input.sprint_requested && can_sprint && is_moving && !controller.is_crouched
+            && !controller.is_climbing && !controller.is_swimming
+            && (controller.is_grounded || controller.is_sprinting);
+
+        controller.previous_translation = Some(transform.translation);
+        move_character(&mut transform, &mut controller, &mut input, &rapier_context, &vorld, &water_volumes, terrain_filter, time_delta);
+
+        if let Some(stamina) = stamina.as_mut() {
+            stamina.update(controller.is_sprinting, time_delta);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn move_character(
+    transform: &mut Transform,
+    controller: &mut KinematicCharacterController,
+    input: &mut CharacterInput,
+    rapier_context: &RapierContext,
+    vorld: &Vorld,
+    water_volumes: &[&WaterVolume],
+    terrain_filter: QueryFilter,
+    time_delta: f32,
+) {
+    let movement_config = controller.movement_config.clone();
+    let collision_config = controller.collision_config.clone();
+
+    // Determine crouch / uncrouch
+    if !controller.is_crouched && input.crouch_requested {
+        controller.is_crouched = true;
+    } else if controller.is_crouched && !input.crouch_requested {
+        // Ensure there is space to stand up!
+        let standing_half_height = collision_config.standing_half_height;
+        controller.is_crouched = if collision_config.use_voxel_collision {
+            let (min, max) = controller.get_aabb(transform.translation, standing_half_height);
+            !collision::is_aabb_clear(vorld, min, max)
+        } else {
+            let mut is_blocked = false;
+            rapier_context.intersections_with_shape(
+                transform.translation + standing_half_height * Vec3::Y,
+                Quat::IDENTITY,
+                &controller.get_shape(standing_half_height),
+                terrain_filter,
+                |_| {
+                    is_blocked = true;
+                    false
+                }
+            );
+            is_blocked
+        };
+    }
+    let half_height = controller.get_half_height();
+    let shape = controller.get_shape(half_height);
+
+    // Climb whilst overlapping a climbable block, but not while still rising from a jump off it
+    let (min, max) = controller.get_aabb(transform.translation, half_height);
+    let is_touching_climbable = collision::is_aabb_overlapping(vorld, min, max, |id| get_block_properties(id).is_climbable);
+    controller.is_climbing = is_touching_climbable && (controller.is_climbing || controller.velocity.y <= 0.0);
+
+    // The head is taken to be the centre of the top of the capsule
+    let height = 2.0 * half_height;
+    let submersion = (water::get_water_depth(water_volumes.iter().copied(), transform.translation) / height).min(1.0);
+    let head = transform.translation + (height - collision_config.collider_radius) * Vec3::Y;
+    controller.is_swimming = submersion >= MIN_SWIM_SUBMERSION;
+    controller.is_head_underwater = water::get_water_depth(water_volumes.iter().copied(), head) > 0.0;
+
+    let local_x = transform.local_x();
+    let local_z = transform.local_z();
+    
+    // Project to x/z plane, target velocity is projected onto the ground below once calculated
+    let local_x = Vec3::new(local_x.x, 0.0, local_x.z).normalize();
+    let local_z = Vec3::new(local_z.x, 0.0, local_z.z).normalize();
+
+    // Transform movement input into world_space 
+    let input_vector = input.movement_direction.x * local_x + input.movement_direction.z * local_z;
+
+    if !controller.is_grounded && !controller.is_swimming && controller.velocity.length_squared() > 0.0 {
+        // Apply Drag 
+        let air_speed = controller.velocity.length();
+        let drag_delta_v = air_speed * air_speed * 1.225 * time_delta / 200.0;
+        // Assumes in air and mass of 100kg, drag coefficient of ~1 and surface area ~1
+        
+        if air_speed < drag_delta_v { // Happens at around air_speed of 99 m/s
+            controller.velocity = Vec3::ZERO;
+            // If we wanted to support drag at extremely high speeds properly would need to average drag across the frame, rather than instanteous maximum
+        } else {
+            controller.velocity *= (air_speed - drag_delta_v) / air_speed;
+        }
+    }
+
+    let xz_velocity = Vec3::new(controller.velocity.x, 0.0, controller.velocity.z);
+
+    // Swim in the direction being looked, rather than along the ground
+    let swim_velocity = match controller.is_swimming {
+        true => {
+            let pitch = input.look_pitch;
+            let swim_z = pitch.cos() * local_z - pitch.sin() * Vec3::Y;
+            let swim_input = input.movement_direction.x * local_x + input.movement_direction.z * swim_z;
+            calculate_target_swim_velocity(&movement_config, controller.velocity, swim_input, submersion, time_delta)
+        },
+        false => Vec3::ZERO,
+    };
+
+    let mut target_velocity = xz_velocity;
+    if controller.is_climbing {
+        target_velocity = movement_config.climb_speed * input_vector;
+    } else if controller.is_swimming {
+        target_velocity = Vec3::new(swim_velocity.x, 0.0, swim_velocity.z);
+    } else if controller.is_grounded {
+        let max_movement_speed = if controller.is_sprinting {
+            movement_config.max_sprint_speed
+        } else if input.walk_requested {
+            movement_config.max_walk_speed
+        } else {
+            movement_config.max_run_speed
+        };
+        calculate_target_grounded_velocity(&movement_config, max_movement_speed, xz_velocity, input, &mut target_velocity, time_delta, input_vector);
+    } else {
+        calculate_target_air_velocity(xz_velocity, &movement_config, time_delta, input_vector, &mut target_velocity);
+    }
+
+    // Walk along the slope of the ground rather than into or off it
+    if controller.is_grounded && !controller.is_climbing && !controller.is_swimming {
+        let normal = controller.ground_normal;
+        let speed = target_velocity.length();
+        target_velocity = (target_velocity - target_velocity.dot(normal) * normal).normalize_or_zero() * speed;
+    }
+
+    let start_translation = transform.translation;
+
+    // Handle requested y-movement / movement due to gravity
+    let is_jumping = update_jump(controller, input, time_delta);
+    let vertical_velocity = match is_jumping {
+        // Jumps replace the vertical velocity rather than adding to it, so an air jump also stops a fall
+        true => {
+            controller.is_climbing = false;
+            match controller.is_crouched {
+                true => movement_config.crouch_jump_delta_v,
+                false => movement_config.jump_delta_v,
+            }
+        },
+        false if controller.is_climbing => get_climb_velocity(&movement_config, input),
+        false if controller.is_swimming => swim_velocity.y,
+        // Vertical velocity from walking up or down slopes isn't kept once grounded
+        false => {
+            let base_velocity = if controller.is_grounded { 0.0 } else { controller.velocity.y };
+            base_velocity - movement_config.acceleration_due_to_gravity * time_delta
+        },
+    };
+
+    let was_grounded = controller.is_grounded;
+    let can_step = was_grounded && !controller.is_climbing && collision_config.max_step_height > 0.0;
+    let mut step_height = 0.0;
+    if collision_config.use_voxel_collision {
+        let movement = Vec3::new(target_velocity.x, vertical_velocity, target_velocity.z) * time_delta;
+        let (min, max) = controller.get_aabb(transform.translation, half_height);
+        let mut sweep = collision::sweep_aabb(vorld, min, max, movement, collision_config.skin_depth);
+        if can_step && sweep.contact_normals.iter().any(|normal| normal.y == 0) {
+            if let Some(step) = step_up_voxels(vorld, min, max, movement, &collision_config) {
+                if get_horizontal_length(step.movement) > get_horizontal_length(sweep.movement) {
+                    step_height = step.movement.y;
+                    sweep = step;
+                }
+            }
+        }
+        transform.translation += sweep.movement;
+        controller.is_grounded = sweep.is_grounded;
+        controller.ground_normal = Vec3::Y;
+    } else {
+        let is_blocked = move_and_slide(transform, target_velocity, rapier_context, half_height, &shape, time_delta, collision_config.skin_depth, terrain_filter);
+        if can_step && is_blocked {
+            if let Some(landing) = step_up(start_translation, target_velocity, rapier_context, half_height, &shape, time_delta, &collision_config, terrain_filter) {
+                if get_horizontal_length(landing - start_translation) > get_horizontal_length(transform.translation - start_translation) {
+                    step_height = landing.y - start_translation.y;
+                    transform.translation = landing;
+                }
+            }
+        }
+
+        let direction = match vertical_velocity > 0.0 {
+            true => Vec3::Y,
+            false => Vec3::NEG_Y,
+        };
+
+        move_y(vertical_velocity, transform, rapier_context, half_height, direction, &shape, time_delta, &collision_config, terrain_filter, controller);
+    }
+
+    // Stay on the ground when walking down slopes or off small drops, rather than briefly going airborne
+    if was_grounded && !is_jumping && !controller.is_climbing && !controller.is_swimming && !controller.is_grounded && collision_config.ground_snap_distance > 0.0 {
+        snap_to_ground(transform, controller, rapier_context, vorld, half_height, &shape, terrain_filter);
+    }
+
+    // Water breaks the fall, so landing on the bottom while swimming doesn't count
+    let has_landed = !was_grounded && controller.is_grounded && !controller.is_swimming && vertical_velocity < 0.0;
+    controller.landing_speed = has_landed.then_some(-vertical_velocity);
+
+    // Leave the step out of the velocity, else stepping up would launch the character into the air
+    controller.step_height = step_height;
+    controller.velocity = (transform.translation - start_translation - step_height * Vec3::Y) / time_delta;
+}
+
+/// Buffers any jump request and returns whether the character should jump this frame
+/// Jumps can be made when grounded, climbing or swimming at the surface, within coyote_time of leaving the ground,
+/// or in the air while air jumps remain
+fn update_jump(controller: &mut KinematicCharacterController, input: &mut CharacterInput, time_delta: f32) -> bool {
+    let movement_config = &controller.movement_config;
+    let is_at_surface = controller.is_swimming && !controller.is_head_underwater;
+    if controller.is_grounded || controller.is_climbing || is_at_surface {
+        controller.time_since_grounded = 0.0;
+        controller.air_jumps_made = 0;
+    } else {
+        controller.time_since_grounded += time_delta;
+    }
+
+    if input.jump_requested {
+        input.jump_requested = false;
+        // Always allow the jump this frame, even with no buffer time
+        controller.jump_buffer_timer = movement_config.jump_buffer_time.max(time_delta);
+    }
+    if controller.jump_buffer_timer <= 0.0 {
+        return false;
+    }
+
+    let can_ground_jump = controller.is_grounded || controller.is_climbing || is_at_surface || controller.time_since_grounded <= movement_config.coyote_time;
+    let can_air_jump = controller.air_jumps_made < movement_config.max_air_jumps;
+    if can_ground_jump || can_air_jump {
+        if !can_ground_jump {
+            controller.air_jumps_made += 1;
+        }
+        // Prevent a second coyote jump
+        controller.time_since_grounded = f32::MAX;
+        controller.jump_buffer_timer = 0.0;
+        true
+    } else {
+        controller.jump_buffer_timer -= time_delta;
+        false
+    }
+}
+
+/// Vertical velocity while climbing, forward input climbs up unless looking down past CLIMB_DOWN_PITCH
+fn get_climb_velocity(movement_config: &CharacterMovementConfig, input: &CharacterInput) -> f32 {
+    let forward_input = -input.movement_direction.z;
+    let climb_direction = match input.look_pitch < -CLIMB_DOWN_PITCH {
+        true => -1.0,
+        false => 1.0,
+    };
+    movement_config.climb_speed * forward_input * climb_direction
+}
+
+/// Velocity in water approaches swim_speed in the direction of swim_input, plus buoyancy which lifts the character
+/// until they float with FLOAT_SUBMERSION of their height underwater
+fn calculate_target_swim_velocity(
+    movement_config: &CharacterMovementConfig,
+    velocity: Vec3,
+    swim_input: Vec3,
+    submersion: f32,
+    time_delta: f32,
+) -> Vec3 {
+    let lift = movement_config.buoyancy * (submersion - FLOAT_SUBMERSION) / (1.0 - FLOAT_SUBMERSION);
+    let desired_velocity = movement_config.swim_speed * swim_input + lift * Vec3::Y;
+    velocity + (desired_velocity - velocity) * (1.0 - (-movement_config.swim_drag * time_delta).exp())
+}
+
+/// Moves the character down onto walkable ground within the ground snap distance, if there is any
+fn snap_to_ground(
+    transform: &mut Transform,
+    controller: &mut KinematicCharacterController,
+    rapier_context: &RapierContext,
+    vorld: &Vorld,
+    half_height: f32,
+    shape: &Collider,
+    collision_filter: QueryFilter,
+) {
+    let config = &controller.collision_config;
+    let snap_distance = config.ground_snap_distance;
+    if config.use_voxel_collision {
+        let (min, max) = controller.get_aabb(transform.translation, half_height);
+        if let Some(drop) = collision::sweep_aabb_axis(vorld, min, max, 1, -snap_distance, config.skin_depth) {
+            transform.translation.y += drop;
+            controller.is_grounded = true;
+        }
+    } else if let Some((_, hit)) = rapier_context.cast_shape(
+        transform.translation + half_height * Vec3::Y,
+        Quat::IDENTITY,
+        Vec3::NEG_Y,
+        shape,
+        snap_distance + config.skin_depth,
+        collision_filter,
+    ) {
+        if hit.normal1.y >= config.get_min_walkable_normal_y() {
+            transform.translation.y -= hit.toi - config.skin_depth;
+            controller.is_grounded = true;
+            controller.ground_normal = hit.normal1;
+        }
+    }
+}
+
+fn get_horizontal_length(vector: Vec3) -> f32 {
+    Vec2::new(vector.x, vector.z).length()
+}
+
+/// Attempts to climb a ledge by moving up by at most max_step_height, then horizontally, then back down onto the ledge
+/// Returns where the character lands, None if there's no walkable ground to land on above where it started
+#[allow(clippy::too_many_arguments)]
+fn step_up(
+    start_translation: Vec3,
+    target_velocity: Vec3,
+    rapier_context: &RapierContext,
+    half_height: f32,
+    shape: &Collider,
+    time_delta: f32,
+    collision_config: &CharacterCollisionConfig,
+    collision_filter: QueryFilter,
+) -> Option<Vec3> {
+    let skin_depth = collision_config.skin_depth;
+    let max_step_height = collision_config.max_step_height;
+    let shape_offset = half_height * Vec3::Y;
+
+    let up = match rapier_context.cast_shape(start_translation + shape_offset, Quat::IDENTITY, Vec3::Y, shape, max_step_height + skin_depth, collision_filter) {
+        Some((_, hit)) => (hit.toi - skin_depth).max(0.0),
+        None => max_step_height,
+    };
+    let mut stepped_transform = Transform::from_translation(start_translation + up * Vec3::Y);
+    move_and_slide(&mut stepped_transform, target_velocity, rapier_context, half_height, shape, time_delta, skin_depth, collision_filter);
+
+    let (_, hit) = rapier_context.cast_shape(stepped_transform.translation + shape_offset, Quat::IDENTITY, Vec3::NEG_Y, shape, up + skin_depth, collision_filter)?;
+    let drop = hit.toi - skin_depth;
+    (hit.normal1.y >= collision_config.get_min_walkable_normal_y() && drop < up - skin_depth).then(|| stepped_transform.translation - drop * Vec3::Y)
+}
+
+/// As step_up, for voxel collision, returns the movement made by the whole step
+fn step_up_voxels(vorld: &Vorld, min: Vec3, max: Vec3, movement: Vec3, collision_config: &CharacterCollisionConfig) -> Option<VoxelSweep> {
+    let skin_depth = collision_config.skin_depth;
+    let max_step_height = collision_config.max_step_height;
+    let up = collision::sweep_aabb_axis(vorld, min, max, 1, max_step_height, skin_depth).unwrap_or(max_step_height);
+    let raise = up * Vec3::Y;
+    let horizontal = collision::sweep_aabb(vorld, min + raise, max + raise, Vec3::new(movement.x, 0.0, movement.z), skin_depth);
+    let moved = raise + horizontal.movement;
+    // Only blocked if there is ground above where the step started, voxel tops are always walkable
+    let down = collision::sweep_aabb_axis(vorld, min + moved, max + moved, 1, -up, skin_depth)?;
+
+    let mut contact_normals = horizontal.contact_normals;
+    contact_normals.push(IVec3::Y);
+    Some(VoxelSweep {
+        movement: moved + down * Vec3::Y,
+        contact_normals,
+        is_grounded: true,
+    })
+}
+
+fn calculate_target_grounded_velocity(
+    movement_config: &CharacterMovementConfig,
+    max_movement_speed: f32,
+    xz_velocity: Vec3,
+    input: &CharacterInput,
+    target_velocity: &mut Vec3,
+    time_delta: f32,
+    input_vector: Vec3
+) {
+    let max_movement_speed_sqr = max_movement_speed * max_movement_speed;
+    let speed_sqr = xz_velocity.length_squared();
+    let is_sliding = speed_sqr > max_movement_speed_sqr + 0.001;
+    let any_input = input.movement_direction.length_squared() > 0.0;
+    if is_sliding {
+        // Apply linear slowing force
+        // Proportional to v can quickly result at velocity being negated at high speeds 
+        *target_velocity *= 1.0 - (5.0 * time_delta).min(1.0);
+
+        // Only allow deceleration if moving faster than max movment speed
+        if xz_velocity.x.is_sign_positive() != input_vector.x.is_sign_positive() {
+            target_velocity.x += movement_config.acceleration * time_delta * input_vector.x;
+        }
+        if xz_velocity.z.is_sign_positive() != input_vector.z.is_sign_positive() {
+            target_velocity.z += movement_config.acceleration * time_delta * input_vector.z;
+        }
+    } else if any_input {
+        // Apply slow if input in opposite direction to velocity for faster change of direction
+        if xz_velocity.x.is_sign_positive() != input_vector.x.is_sign_positive() {
+            target_velocity.x *= 1.0 - (2.5 * speed_sqr.sqrt() * time_delta).min(1.0);
+        } 
+        if xz_velocity.z.is_sign_positive() != input_vector.z.is_sign_positive() {
+            target_velocity.z *= 1.0 - (2.5 * speed_sqr.sqrt() * time_delta).min(1.0);
+        }
+        *target_velocity += movement_config.acceleration * time_delta * input_vector;
+    
+        if target_velocity.length_squared() > max_movement_speed_sqr {
+            *target_velocity = max_movement_speed * target_velocity.normalize();
+        }
+    } else {
+        if speed_sqr < movement_config.stop_speed * movement_config.stop_speed {
+            *target_velocity = Vec3::ZERO;
+        } else {
+            *target_velocity *= (2.5 * speed_sqr.sqrt() * time_delta).min(1.0)
+        }
+    }
+}
+
+fn calculate_target_air_velocity(
+    xz_velocity: Vec3,
+    movement_config: &CharacterMovementConfig,
+    time_delta: f32,
+    input_vector: Vec3,
+    target_velocity: &mut Vec3
+) {
+    let target_x = xz_velocity.x + movement_config.air_acceleration * time_delta * input_vector.x;
+    let target_z = xz_velocity.z + movement_config.air_acceleration * time_delta * input_vector.z;
+    let max_air_movement_speed_sqr = movement_config.max_air_movement_speed * movement_config.max_air_movement_speed;
+    let target_air_speed_sqr = target_x * target_x + target_z * target_z;
+    let can_accelerate = target_air_speed_sqr < max_air_movement_speed_sqr;
+    if can_accelerate || target_x.abs() < xz_velocity.x.abs() {
+        target_velocity.x = target_x;
+    }
+    if can_accelerate || target_z.abs() < xz_velocity.z.abs() {
+        target_velocity.z = target_z;
+    }
+    if !(target_velocity.x == target_x && target_velocity.z == target_z) {
+        // Must be above max air movement speed, and not trying to decelerate in both axes
+        let redirect_threshold_speed_sqr = (movement_config.max_run_speed * movement_config.max_run_speed).max(max_air_movement_speed_sqr);
+        let current_air_speed_sqr = target_velocity.length_squared(); 
+        if current_air_speed_sqr < redirect_threshold_speed_sqr {
+            // allow redirection of the direction of air movement if below redirect threshold
+            *target_velocity = (current_air_speed_sqr.sqrt() / target_air_speed_sqr.sqrt()) * Vec3::new(target_x, 0.0, target_z);
+        }
+    }
+}
+
+/// Moves the shape by velocity for time_delta, sliding along up to two surfaces it collides with, returns whether anything was hit
+/// The shape is positioned half_height above the translation
+#[allow(clippy::too_many_arguments)]
+pub fn move_and_slide(
+    transform: &mut Transform,
+    target_velocity: Vec3,
+    rapier_context: &RapierContext,
+    half_height: f32,
+    shape: &Collider,
+    time_delta: f32,
+    skin_depth: f32,
+    collision_filter: QueryFilter,
+) -> bool {
+    let mut is_blocked = false;
+    let mut started_overlapping = false;
+    let reset_position = transform.translation;
+    // Character Controller Move
+    if target_velocity.length_squared() > 0.0 {
+        let velocity_direction = target_velocity.normalize();
+        let velocity_magnitude = target_velocity.length();
+
+        if let Some((_, hit)) = rapier_context.cast_shape(
+            transform.translation + half_height * Vec3::Y,
+            Quat::IDENTITY,
+            velocity_direction,
+            shape,
+            time_delta * velocity_magnitude + skin_depth,
+            collision_filter,
+        ) {
+            is_blocked = true;
+            if hit.toi == 0.0 {
+                // Already overlapping - should only happen if teleported or spawned inside collider
+                warn!("Started movement already overlapping");
+                transform.translation += target_velocity * time_delta;
+                started_overlapping = true;
+            } else {
+                // Desired movement collides, attempt to slide along surface
+                // NOTE: Casting in velocity direction means time of impact is in fact distance to impact
+                let close_distance = hit.toi - skin_depth;
+                transform.translation += velocity_direction * close_distance;
+                // ^^ This can be negative and will attempt to move the camera away before sliding along the surface
+
+                let stop_time = close_distance / velocity_magnitude;
+                let time_remainder = time_delta - stop_time;
+                let velocity_remainder = target_velocity * time_remainder / time_delta;
+                let slide_velocity =
+                    velocity_remainder - velocity_remainder.dot(hit.normal1) * hit.normal1;
+                let slide_velocity_direction = slide_velocity.normalize();
+                let slide_velocity_magnitude = slide_velocity.length();
+
+                if let Some((_, second_hit)) = rapier_context.cast_shape(
+                    transform.translation + half_height * Vec3::Y,
+                    Quat::IDENTITY,
+                    slide_velocity_direction,
+                    shape,
+                    time_remainder * slide_velocity_magnitude + skin_depth,
+                    collision_filter,
+                ) {
+                    // slide also collides, attempt to one further slide in direction perpenticular to both hit normals
+                    let second_slide_direction = hit.normal1.cross(second_hit.normal1);
+
+                    let close_distance = second_hit.toi - skin_depth;
+                    transform.translation += slide_velocity_direction * close_distance;
+
+                    let time_delta = time_remainder;
+                    let stop_time = close_distance / slide_velocity_magnitude;
+                    let time_remainder = time_delta - stop_time;
+                    let velocity_remainder = slide_velocity * time_remainder / time_delta;
+                    let slide_velocity =
+                        velocity_remainder.dot(second_slide_direction) * second_slide_direction;
+
+                    if rapier_context
+                        .cast_shape(
+                            transform.translation + half_height * Vec3::Y,
+                            Quat::IDENTITY,
+                            slide_velocity.normalize(),
+                            shape,
+                            time_remainder * slide_velocity.length() + skin_depth,
+                            collision_filter,
+                        )
+                        .is_none()
+                    {
+                        // Only move if there are no collisions only second slide axis, else only move up to second contact
+                        transform.translation += slide_velocity * time_remainder;
+                    }
+                } else {
+                    transform.translation += slide_velocity * time_remainder;
+                }
+            }
+        } else {
+            transform.translation += target_velocity * time_delta;
+        }
+
+        if !started_overlapping {
+            rapier_context.intersections_with_shape(
+                transform.translation + half_height * Vec3::Y,
+                Quat::IDENTITY,
+                shape,
+                collision_filter,
+                |_| {
+                    // cast_shape sometimes lies about there being no collision due to float precision issues,
+                    // so check for intersections and if found restore to starting position
+                    warn!("Camera shape found to intersect world collider after movement, restoring to last valid position");
+                    transform.translation = reset_position;
+                    false
+                }
+            );
+        }
+    }
+    is_blocked
+}
+
+#[allow(clippy::too_many_arguments)]
+fn move_y(
+    vertical_velocity: f32,
+    transform: &mut Transform,
+    rapier_context: &RapierContext,
+    half_height: f32,
+    direction: Vec3,
+    shape: &Collider,
+    time_delta: f32,
+    collision_config: &CharacterCollisionConfig,
+    collision_filter: QueryFilter,
+    controller: &mut KinematicCharacterController
+) {
+    let skin_depth = collision_config.skin_depth;
+    if vertical_velocity.abs() > 0.0 {
+        let reset_position = transform.translation;
+        if let Some((_, hit)) = rapier_context.cast_shape(
+            transform.translation + half_height * Vec3::Y,
+            Quat::IDENTITY,
+            direction,
+            shape,
+            time_delta * vertical_velocity.abs() + skin_depth,
+            collision_filter,
+        ) {
+            let close_distance = hit.toi - skin_depth;
+            transform.translation += direction * close_distance;
+            let is_falling = vertical_velocity < 0.0;
+            controller.is_grounded = is_falling && hit.normal1.y >= collision_config.get_min_walkable_normal_y();
+            controller.ground_normal = if controller.is_grounded { hit.normal1 } else { Vec3::Y };
+            if is_falling && !controller.is_grounded {
+                // Too steep to stand on, slide down it with the rest of the fall
+                let remainder = direction * (time_delta * vertical_velocity.abs() - close_distance);
+                let slide_velocity = (remainder - remainder.dot(hit.normal1) * hit.normal1) / time_delta;
+                move_and_slide(transform, slide_velocity, rapier_context, half_height, shape, time_delta, skin_depth, collision_filter);
+            }
+        } else {
+            transform.translation += direction * vertical_velocity.abs() * time_delta;
+            controller.is_grounded = false;
+            controller.ground_normal = Vec3::Y;
+        }
+
+        rapier_context.intersections_with_shape(
+            transform.translation + half_height * Vec3::Y,
+            Quat::IDENTITY,
+            shape,
+            collision_filter,
+            |_| {
+                // cast_shape sometimes lies about there being no collision due to float precision issues,
+                // so check for intersections and if found restore to starting position 
+                // NOTE: Have not seen this in the wild with pure vertical movement, yet
+                warn!("Camera shape found to intersect world collider after vertical movement, restoring to last valid position");
+                transform.translation = reset_position;
+                false
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_rapier3d::rapier::prelude::{ColliderBuilder, InteractionGroups, Vector};
+    use std::time::Duration;
+
+    use crate::player::{self, Player};
+    use crate::player_input::PlayerInput;
+
+    /// Stone floor with its top at y 0 and a stone pillar top at y 4 to walk off, with matching rapier colliders
+    /// The player's controller is used with its max_air_jumps and collision swapped for the test's
+    fn build_app(translation: Vec3, max_air_jumps: u32, use_voxel_collision: bool) -> (App, Entity) {
+        let mut vorld = Vorld::new();
+        vorld.fill_region(IVec3::new(-4, -1, -4), IVec3::new(4, -1, 4), BlockIds::Stone as u8);
+        vorld.add_voxel(BlockIds::Stone as u8, 0, 3, 0);
+
+        let mut rapier_context = RapierContext::default();
+        let boxes = [(Vec3::new(0.5, -0.5, 0.5), Vec3::new(4.5, 0.5, 4.5)), (Vec3::new(0.5, 3.5, 0.5), Vec3::splat(0.5))];
+        for (center, half_extents) in boxes {
+            let collider = ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+                .translation(Vector::new(center.x, center.y, center.z))
+                .collision_groups(InteractionGroups::new(NamedCollisionGroups::Terrain as u32, NamedCollisionGroups::Everything as u32))
+                .build();
+            rapier_context.colliders.insert(collider);
+        }
+        update_query_pipeline(&mut rapier_context);
+
+        let mut controller = player::build_player_controller();
+        controller.movement_config.max_air_jumps = max_air_jumps;
+        controller.collision_config.use_voxel_collision = use_voxel_collision;
+
+        let mut time = Time::default();
+        time.update_with_instant(time.startup());
+        let mut app = App::new();
+        app.insert_resource(vorld)
+            .insert_resource(rapier_context)
+            .insert_resource(time)
+            .init_resource::<FixedTimesteps>()
+            .init_resource::<PlayerInput>()
+            .add_plugin(CharacterControllerPlugin)
+            .add_system(player::update_character_input);
+        let entity = app.world.spawn()
+            .insert(Transform::from_translation(translation))
+            .insert(Player)
+            .insert(controller)
+            .insert(CharacterInput::default())
+            .id();
+        (app, entity)
+    }
+
+    fn update_query_pipeline(context: &mut RapierContext) {
+        context.query_pipeline.update(&context.islands, &context.bodies, &context.colliders);
+    }
+
+    fn get_controller(app: &App, entity: Entity) -> &KinematicCharacterController {
+        app.world.get::<KinematicCharacterController>(entity).unwrap()
+    }
+
+    /// Advances time by one TIMESTEP per update, so each update makes exactly one move
+    fn step(app: &mut App, moves: usize) {
+        // Rounded up so rounding never leaves the accumulated time just short of a move
+        let move_duration = Duration::from_nanos((TIMESTEP as f64 * 1e9).ceil() as u64);
+        for _ in 0..moves {
+            let mut time = app.world.resource_mut::<Time>();
+            let instant = time.last_update().unwrap() + move_duration;
+            time.update_with_instant(instant);
+            app.update();
+        }
+    }
+
+    fn request_jump(app: &mut App) {
+        app.world.resource_mut::<PlayerInput>().jump_requested = true;
+    }
+
+    /// Steps until the character lands, panics if it takes longer than max_moves
+    fn step_until_grounded(app: &mut App, entity: Entity, max_moves: usize) {
+        for _ in 0..max_moves {
+            step(app, 1);
+            if get_controller(app, entity).is_grounded {
+                return;
+            }
+        }
+        panic!("Character didn't land within {} moves", max_moves);
+    }
+
+    fn is_jumping(app: &App, entity: Entity) -> bool {
+        let controller = get_controller(app, entity);
+        !controller.is_grounded && controller.velocity.y > 0.0
+    }
+
+    /// Stands on the pillar, then removes it so the character falls as if it had walked off a ledge
+    fn drop_off_pillar(app: &mut App, entity: Entity) {
+        step(app, 1);
+        assert!(get_controller(app, entity).is_grounded);
+
+        app.world.resource_mut::<Vorld>().add_voxel(BlockIds::Air as u8, 0, 3, 0);
+        let mut rapier_context = app.world.resource_mut::<RapierContext>();
+        let context = &mut *rapier_context;
+        let pillar = context.colliders.iter().find(|(_, collider)| collider.translation().y > 0.0).map(|(handle, _)| handle).unwrap();
+        context.colliders.remove(pillar, &mut context.islands, &mut context.bodies, false);
+        update_query_pipeline(context);
+
+        step(app, 1);
+        assert!(!get_controller(app, entity).is_grounded);
+    }
+
+    #[test]
+    fn jumps_from_ground() {
+        for use_voxel_collision in [true, false] {
+            let (mut app, entity) = build_app(Vec3::new(0.5, 0.0, 0.5), 0, use_voxel_collision);
+            step(&mut app, 1);
+            assert!(get_controller(&app, entity).is_grounded);
+
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(is_jumping(&app, entity));
+            assert!((get_controller(&app, entity).velocity.y - 7.5).abs() < 1e-3);
+            assert!(!app.world.resource::<PlayerInput>().jump_requested);
+            assert!(!app.world.get::<CharacterInput>(entity).unwrap().jump_requested);
+        }
+    }
+
+    #[test]
+    fn coyote_jumps_within_coyote_time() {
+        for use_voxel_collision in [true, false] {
+            let (mut app, entity) = build_app(Vec3::new(0.5, 4.0, 0.5), 0, use_voxel_collision);
+            drop_off_pillar(&mut app, entity);
+            // 0.05s off the ground, plus the move the jump is made in
+            step(&mut app, 3);
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(is_jumping(&app, entity));
+            assert_eq!(get_controller(&app, entity).air_jumps_made, 0);
+        }
+    }
+
+    #[test]
+    fn no_coyote_jump_after_coyote_time() {
+        for use_voxel_collision in [true, false] {
+            let (mut app, entity) = build_app(Vec3::new(0.5, 4.0, 0.5), 0, use_voxel_collision);
+            drop_off_pillar(&mut app, entity);
+            step(&mut app, 6);
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(!is_jumping(&app, entity));
+            assert!(get_controller(&app, entity).velocity.y < 0.0);
+            // Still buffered in case of landing
+            assert!(get_controller(&app, entity).jump_buffer_timer > 0.0);
+        }
+    }
+
+    #[test]
+    fn buffered_jump_fires_on_landing() {
+        for use_voxel_collision in [true, false] {
+            // About 6 moves above the floor, well within the buffer time
+            let (mut app, entity) = build_app(Vec3::new(0.5, 0.1, 0.5), 0, use_voxel_collision);
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(!is_jumping(&app, entity));
+
+            step_until_grounded(&mut app, entity, 10);
+            step(&mut app, 1);
+            assert!(is_jumping(&app, entity));
+            assert!(get_controller(&app, entity).jump_buffer_timer <= 0.0);
+        }
+    }
+
+    #[test]
+    fn buffered_jump_expires() {
+        for use_voxel_collision in [true, false] {
+            // About 19 moves above the floor, longer than the buffer time
+            let (mut app, entity) = build_app(Vec3::new(0.5, 1.0, 0.5), 0, use_voxel_collision);
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(!is_jumping(&app, entity));
+
+            step_until_grounded(&mut app, entity, 30);
+            step(&mut app, 1);
+            assert!(!is_jumping(&app, entity));
+            assert!(get_controller(&app, entity).is_grounded);
+        }
+    }
+
+    #[test]
+    fn air_jumps_are_limited_until_landing() {
+        for use_voxel_collision in [true, false] {
+            let (mut app, entity) = build_app(Vec3::new(2.5, 2.0, 2.5), 1, use_voxel_collision);
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(is_jumping(&app, entity));
+            assert_eq!(get_controller(&app, entity).air_jumps_made, 1);
+
+            // Out of air jumps, so the request is only buffered
+            step(&mut app, 30);
+            assert!(get_controller(&app, entity).velocity.y < 0.0);
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(get_controller(&app, entity).velocity.y < 0.0);
+            assert_eq!(get_controller(&app, entity).air_jumps_made, 1);
+
+            // Landing gives the air jump back, so a jump off the ground can be followed by another in the air
+            step_until_grounded(&mut app, entity, 120);
+            step(&mut app, 10);
+            assert_eq!(get_controller(&app, entity).air_jumps_made, 0);
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(is_jumping(&app, entity));
+            assert_eq!(get_controller(&app, entity).air_jumps_made, 0);
+
+            step(&mut app, 10);
+            request_jump(&mut app);
+            step(&mut app, 1);
+            assert!(is_jumping(&app, entity));
+            assert_eq!(get_controller(&app, entity).air_jumps_made, 1);
+        }
+    }
+}
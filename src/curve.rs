@@ -0,0 +1,97 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::Deserialize;
+
+/// Piecewise linear mapping between two values, loaded from a .curve.ron file
+#[derive(Deserialize, Debug, Clone, TypeUuid)]
+#[uuid = "9a4e2d71-3c58-4f0b-8e6a-2d7c1b5f9e30"]
+pub struct Curve {
+    /// (x, y) points in order of increasing x
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Curve {
+    /// Value of the curve at x, clamped to the first and last points, zero if there are no points
+    pub fn sample(&self, x: f32) -> f32 {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return 0.0,
+        };
+        if x <= first.0 {
+            return first.1;
+        }
+        for window in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (window[0], window[1]);
+            if x <= x1 {
+                let t = if x1 > x0 { (x - x0) / (x1 - x0) } else { 1.0 };
+                return y0 + t * (y1 - y0);
+            }
+        }
+        last.1
+    }
+}
+
+#[derive(Default)]
+pub struct CurveLoader;
+
+impl AssetLoader for CurveLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let curve = ron::de::from_bytes::<Curve>(bytes)?;
+            if curve.points.windows(2).any(|window| window[1].0 < window[0].0) {
+                warn!("Curve {:?} points are not in order of increasing x", load_context.path());
+            }
+            load_context.set_default_asset(LoadedAsset::new(curve));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["curve.ron"]
+    }
+}
+
+pub struct CurvePlugin;
+
+impl Plugin for CurvePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Curve>()
+            .init_asset_loader::<CurveLoader>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_between_points_and_clamps_outside_them() {
+        let curve = Curve { points: vec![(1.0, 10.0), (3.0, 20.0), (4.0, 0.0)] };
+        assert_eq!(curve.sample(-5.0), 10.0);
+        assert_eq!(curve.sample(1.0), 10.0);
+        assert_eq!(curve.sample(2.0), 15.0);
+        assert_eq!(curve.sample(3.0), 20.0);
+        assert_eq!(curve.sample(3.25), 15.0);
+        assert_eq!(curve.sample(4.0), 0.0);
+        assert_eq!(curve.sample(100.0), 0.0);
+
+        // A single point is flat
+        let curve = Curve { points: vec![(2.0, 5.0)] };
+        assert_eq!(curve.sample(0.0), 5.0);
+        assert_eq!(curve.sample(4.0), 5.0);
+    }
+
+    #[test]
+    fn empty_curve_samples_zero() {
+        let curve = Curve { points: Vec::new() };
+        assert_eq!(curve.sample(0.0), 0.0);
+        assert_eq!(curve.sample(-1.0), 0.0);
+    }
+}
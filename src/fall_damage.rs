@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+
+use super::character_controller::{self, CharacterControllerStage, KinematicCharacterController};
+use super::curve::Curve;
+use super::health::DealDamageEvent;
+
+/// Damages a character with a KinematicCharacterController and Health when it lands too fast
+#[derive(Component)]
+pub struct FallDamage {
+    /// Maps landing speed in m/s to damage
+    pub curve: Handle<Curve>,
+    /// Landings slower than this never cause damage
+    pub min_landing_speed: f32,
+    /// Multiplier applied to damage when landing crouched
+    pub crouch_damage_scale: f32,
+}
+
+pub struct FallDamagePlugin;
+
+impl Plugin for FallDamagePlugin {
+    fn build(&self, app: &mut App) {
+        // Landing speed is only set for the move it happens in, so check it after every move
+        app.add_system_to_stage(CharacterControllerStage, apply_fall_damage.after(character_controller::move_characters));
+    }
+}
+
+fn apply_fall_damage(
+    curves: Res<Assets<Curve>>,
+    mut damage_events: EventWriter<DealDamageEvent>,
+    character_query: Query<(Entity, &FallDamage, &KinematicCharacterController)>,
+) {
+    for (entity, fall_damage, controller) in character_query.iter() {
+        let landing_speed = match controller.landing_speed {
+            Some(speed) if speed > fall_damage.min_landing_speed => speed,
+            _ => continue,
+        };
+        let curve = match curves.get(&fall_damage.curve) {
+            Some(curve) => curve,
+            None => continue,
+        };
+        let mut damage = curve.sample(landing_speed);
+        if controller.is_crouched {
+            damage *= fall_damage.crouch_damage_scale;
+        }
+        let damage = damage.round() as u32;
+        if damage > 0 {
+            damage_events.send(DealDamageEvent { entity, damage });
+        }
+    }
+}
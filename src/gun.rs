@@ -1,10 +1,11 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
+use super::build_mode::{self, BuildMode};
 use super::lifetime::*;
 use super::named_collision_groups::*;
 use super::player_input::PlayerInput;
-use super::projectile::Projectile;
+use super::projectile::{GridProjectile, Projectile, ProjectileConfig};
 
 pub struct BulletMeshMaterial {
     mesh: Handle<Mesh>,
@@ -19,7 +20,8 @@ pub struct GunPlugin;
 impl Plugin for GunPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(setup);
-        app.add_system(shoot);
+        // update_build_mode and build clear shoot_requested while build mode is active, so shoot must run after them
+        app.add_system(shoot.after(build_mode::update_build_mode).after(build_mode::build));
     }
 }
 
@@ -40,32 +42,43 @@ fn setup(
 fn shoot(
     mut commands: Commands,
     bullet_assets: Res<BulletMeshMaterial>,
+    build_mode: Res<BuildMode>,
+    projectile_config: Res<ProjectileConfig>,
     mut player_input: ResMut<PlayerInput>,
     transform_query: Query<&GlobalTransform, With<Muzzle>>,
 ) {
-    
+    if build_mode.is_active {
+        // Clicks are used to remove blocks
+        return;
+    }
+
     if player_input.shoot_requested {
         player_input.shoot_requested = false;
         if let Some(global_transform) = transform_query.iter().last() {
-            commands.spawn()
-                .insert_bundle(PbrBundle {
+            let velocity = 500.0 * global_transform.forward();
+            let mut bullet = commands.spawn();
+            bullet.insert_bundle(PbrBundle {
                     mesh: bullet_assets.mesh.clone(),
                     material: bullet_assets.material.clone(),
                     transform: Transform::identity().with_translation(global_transform.translation()),
                     ..default()
                 })
                 .insert(Projectile { damage: 4 })
-                .insert(Lifetime{ time_remaining: 5.0 })
-                .insert(RigidBody::Dynamic)
-                .insert(Ccd::enabled())
-                .insert(Velocity {
-                    linvel: 500.0 * global_transform.forward(),
-                    angvel: Vec3::ZERO,
-                })
-                .insert(Collider::ball(0.01))
-                .insert(CollisionGroups::new(NamedCollisionGroups::Projectile as u32, NamedCollisionGroups::Everything as u32))
-                .insert(ColliderMassProperties::Mass(0.1))
-                .insert(ActiveEvents::COLLISION_EVENTS);
+                .insert(Lifetime{ time_remaining: 5.0 });
+            if projectile_config.use_grid_traversal {
+                bullet.insert(GridProjectile { velocity });
+            } else {
+                bullet.insert(RigidBody::Dynamic)
+                    .insert(Ccd::enabled())
+                    .insert(Velocity {
+                        linvel: velocity,
+                        angvel: Vec3::ZERO,
+                    })
+                    .insert(Collider::ball(0.01))
+                    .insert(CollisionGroups::new(NamedCollisionGroups::Projectile as u32, NamedCollisionGroups::Everything as u32))
+                    .insert(ColliderMassProperties::Mass(0.1))
+                    .insert(ActiveEvents::COLLISION_EVENTS);
+            }
         }
     }
 }
@@ -8,19 +8,34 @@ use super::projectile::*;
 pub struct Health {
     pub max_health: u32,
     pub current_health: u32,
+    /// Whether the entity is despawned when its health reaches zero, else it's left for DeathEvent readers to handle, e.g. by respawning
+    pub despawn_on_death: bool,
 }
 
+#[allow(dead_code)]
 pub struct TakeDamageEvent {
     pub entity: Entity, 
     pub damage_taken: u32,
     pub damage_inflicted: u32,
 }
 
+/// Damages the entity's Health, for damage which doesn't come from a projectile, e.g. drowning
+pub struct DealDamageEvent {
+    pub entity: Entity,
+    pub damage: u32,
+}
+
+/// Sent when an entity's health reaches zero
+pub struct DeathEvent {
+    pub entity: Entity,
+}
+
 impl Health {
     pub fn new(health: u32) -> Self {
         Self {
             max_health: health,
             current_health: health,
+            despawn_on_death: true,
         }
     }
 }
@@ -30,7 +45,10 @@ pub struct HealthPlugin;
 impl Plugin for HealthPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<TakeDamageEvent>();
-        app.add_system(handle_projectile_impact.after(projectile::detect_projectile_impact));
+        app.add_event::<DealDamageEvent>();
+        app.add_event::<DeathEvent>();
+        app.add_system(handle_projectile_impact.after(projectile::detect_projectile_impact).after(projectile::advance_grid_projectiles));
+        app.add_system(handle_deal_damage);
     }
 }
 
@@ -38,28 +56,48 @@ fn handle_projectile_impact(
     mut commands: Commands,
     mut projectile_event_reader: EventReader<ProjectileImpactEvent>,
     mut take_damage_event_writer: EventWriter<TakeDamageEvent>,
+    mut death_event_writer: EventWriter<DeathEvent>,
     collider_parent_query: Query<Option<&Parent>, With<Collider>>,
     mut health_query: Query<(Entity, &mut Health)>,
 ) {
     for event in projectile_event_reader.iter() {
-        if let Ok(parent_option) = collider_parent_query.get(event.hit_entity) {
-            if let Ok((entity, health)) = health_query.get_mut(event.hit_entity) {
-                inflict_damage(health, entity, &mut commands, event.projectile.damage, &mut take_damage_event_writer);
+        let hit_entity = match event.hit_entity {
+            Some(entity) => entity,
+            None => continue,
+        };
+        if let Ok(parent_option) = collider_parent_query.get(hit_entity) {
+            if let Ok((entity, health)) = health_query.get_mut(hit_entity) {
+                inflict_damage(health, entity, &mut commands, event.projectile.damage, &mut take_damage_event_writer, &mut death_event_writer);
             } else  if let Some(parent) = parent_option {
                 if let Ok((entity, health)) = health_query.get_mut(parent.get()) {
-                    inflict_damage(health, entity, &mut commands, event.projectile.damage, &mut take_damage_event_writer);
+                    inflict_damage(health, entity, &mut commands, event.projectile.damage, &mut take_damage_event_writer, &mut death_event_writer);
                 }
             }
         }
     }
 }
 
+fn handle_deal_damage(
+    mut commands: Commands,
+    mut deal_damage_event_reader: EventReader<DealDamageEvent>,
+    mut take_damage_event_writer: EventWriter<TakeDamageEvent>,
+    mut death_event_writer: EventWriter<DeathEvent>,
+    mut health_query: Query<(Entity, &mut Health)>,
+) {
+    for event in deal_damage_event_reader.iter() {
+        if let Ok((entity, health)) = health_query.get_mut(event.entity) {
+            inflict_damage(health, entity, &mut commands, event.damage, &mut take_damage_event_writer, &mut death_event_writer);
+        }
+    }
+}
+
 fn inflict_damage(
     mut health: Mut<Health>, 
     hit_entity: Entity,
     commands: &mut Commands,
     damage: u32,
     take_damage_event_writer: &mut EventWriter<TakeDamageEvent>,
+    death_event_writer: &mut EventWriter<DeathEvent>,
 ) {
     let previous_health = health.current_health;
     health.current_health -= damage.min(health.current_health);
@@ -68,7 +106,10 @@ fn inflict_damage(
         damage_taken: previous_health - health.current_health,
         damage_inflicted: damage
     });
-    if health.current_health <= 0 { // May want to handle this in response to TakeDamageEvent?
-        commands.entity(hit_entity).despawn_recursive();
+    if health.current_health == 0 && previous_health > 0 {
+        death_event_writer.send(DeathEvent { entity: hit_entity });
+        if health.despawn_on_death { // May want to handle this in response to DeathEvent?
+            commands.entity(hit_entity).despawn_recursive();
+        }
     }
 }
\ No newline at end of file
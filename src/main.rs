@@ -1,14 +1,20 @@
-use bevy::{prelude::*, app::PluginGroupBuilder};
+use bevy::{prelude::*, app::PluginGroupBuilder, transform::TransformSystem};
 use bevy_hanabi::*;
 use bevy_rapier3d::prelude::*;
 
+mod build_mode;
+mod character_controller;
+mod curve;
+mod fall_damage;
 mod gun;
 mod health;
 mod hit_flash;
 mod player_input;
 mod lifetime;
 mod mesher;
+mod minimap;
 mod named_collision_groups;
+mod navigation;
 mod npc_spawner;
 mod player;
 mod projectile;
@@ -16,11 +22,36 @@ mod scene_spawner;
 mod smoothed_follow;
 mod utils;
 mod voxel;
+mod water;
 mod zombie;
 
 fn main() {
+    // Choose the startup vorld with e.g. `cargo run -- --vorld generated`, the test arena is used by default
+    let args: Vec<String> = std::env::args().collect();
+    let get_arg = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|i| args.get(i + 1));
+    let startup_vorld = match get_arg("--vorld") {
+        Some(name) => match voxel::StartupVorld::from_name(name) {
+            Some(startup_vorld) => startup_vorld,
+            None => {
+                eprintln!("Unknown vorld {}, expected one of arena, chunk_test, controller_test or generated", name);
+                return;
+            },
+        },
+        None => voxel::StartupVorld::default(),
+    };
+
+    // Headless map dump, e.g. `cargo run -- --dump-map map.png`
+    if let Some(path) = get_arg("--dump-map") {
+        match voxel::dump_map(startup_vorld, path) {
+            Ok(()) => println!("Wrote map of the vorld to {}", path),
+            Err(error) => eprintln!("Failed to write map to {}: {}", path, error),
+        }
+        return;
+    }
+
     App::new()
         .insert_resource(Msaa { samples: 4 })
+        .insert_resource(startup_vorld)
         .add_plugins(DefaultPlugins)
         .add_plugin(HanabiPlugin)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
@@ -41,8 +72,15 @@ impl PluginGroup for VorldPlugins {
         group.add(npc_spawner::NpcSpawnerPlugin);
         group.add(scene_spawner::SceneSpawnerPlugin);
         group.add(gun::GunPlugin);
+        group.add(character_controller::CharacterControllerPlugin);
+        group.add(curve::CurvePlugin);
+        group.add(fall_damage::FallDamagePlugin);
         group.add(player::PlayerPlugin);
+        group.add(build_mode::BuildModePlugin);
+        group.add(minimap::MinimapPlugin);
+        group.add(water::WaterPlugin);
         group.add(hit_flash::HitFlashPlugin);
+        group.add(navigation::NavigationPlugin);
         group.add(zombie::NpcAiPlugin);
     }
 }
@@ -59,7 +97,7 @@ impl Plugin for GamePlugin {
 
         // Simple systems
         app.add_system(lifetime::update);
-        app.add_system(smoothed_follow::follow.after(player::update_look));
+        app.add_system_to_stage(CoreStage::PostUpdate, smoothed_follow::follow.after(player::update_look).before(TransformSystem::TransformPropagate));
     }
 }
 
@@ -4,52 +4,60 @@ use bevy::{
     render::mesh::Indices,
     render::{mesh::Mesh, render_resource::PrimitiveTopology},
 };
-use std::{collections::HashMap, convert::TryInto};
+use std::{collections::HashMap, convert::TryInto, sync::Arc};
+use crate::voxel::biome::{get_tint_colour, TintMode};
 use crate::voxel::direction::Direction;
 
+type TileRequest = (Direction, (usize, usize, usize), TintMode);
+
+/// Position and uv of each face's vertices, four per face in Direction order
+// One could argue that forward should be -z and invert left and right,
+// as cameras look in the negative z direction and it's more intuative to think of a camera as looking 'forward'.
+const FACE_VERTICES: [([f32; 3], [f32; 2]); 24] = [
+    // forward
+    ([0.0, 0.0, 1.0], [0.0, 1.0]),
+    ([1.0, 0.0, 1.0], [1.0, 1.0]),
+    ([1.0, 1.0, 1.0], [1.0, 0.0]),
+    ([0.0, 1.0, 1.0], [0.0, 0.0]),
+    // back
+    ([0.0, 0.0, 0.0], [1.0, 1.0]),
+    ([0.0, 1.0, 0.0], [1.0, 0.0]),
+    ([1.0, 1.0, 0.0], [0.0, 0.0]),
+    ([1.0, 0.0, 0.0], [0.0, 1.0]),
+    // up
+    ([0.0, 1.0, 0.0], [0.0, 0.0]),
+    ([0.0, 1.0, 1.0], [0.0, 1.0]),
+    ([1.0, 1.0, 1.0], [1.0, 1.0]),
+    ([1.0, 1.0, 0.0], [1.0, 0.0]),
+    // down
+    ([0.0, 0.0, 0.0], [1.0, 0.0]),
+    ([1.0, 0.0, 0.0], [0.0, 0.0]),
+    ([1.0, 0.0, 1.0], [0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [1.0, 1.0]),
+    // right
+    ([1.0, 0.0, 0.0], [1.0, 1.0]),
+    ([1.0, 1.0, 0.0], [1.0, 0.0]),
+    ([1.0, 1.0, 1.0], [0.0, 0.0]),
+    ([1.0, 0.0, 1.0], [0.0, 1.0]),
+    // left
+    ([0.0, 0.0, 0.0], [0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [1.0, 1.0]),
+    ([0.0, 1.0, 1.0], [1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0]),
+];
+
+#[allow(clippy::too_many_arguments)]
 fn insert_tile(
     positions: &mut Vec<[f32; 3]>,
     normals: &mut Vec<[f32; 3]>,
     uvs: &mut Vec<[f32; 2]>,
+    colours: &mut Vec<[f32; 4]>,
     indices: &mut Vec<u32>,
     direction: Direction,
     position: (usize, usize, usize),
+    tint: TintMode,
+    vorld_slice: &VorldSlice,
 ) {
-    // One could argue that forward should be -z and invert left and right,
-    // as cameras look in the negative z direction and it's more intuative to think of a camera as looking 'forward'.
-    let vertices = [
-        // forward
-        ([0.0, 0.0, 1.0], [0.0, 1.0]),
-        ([1.0, 0.0, 1.0], [1.0, 1.0]),
-        ([1.0, 1.0, 1.0], [1.0, 0.0]),
-        ([0.0, 1.0, 1.0], [0.0, 0.0]),
-        // back
-        ([0.0, 0.0, 0.0], [1.0, 1.0]),
-        ([0.0, 1.0, 0.0], [1.0, 0.0]),
-        ([1.0, 1.0, 0.0], [0.0, 0.0]),
-        ([1.0, 0.0, 0.0], [0.0, 1.0]),
-        // up
-        ([0.0, 1.0, 0.0], [0.0, 0.0]),
-        ([0.0, 1.0, 1.0], [0.0, 1.0]),
-        ([1.0, 1.0, 1.0], [1.0, 1.0]),
-        ([1.0, 1.0, 0.0], [1.0, 0.0]),
-        // down
-        ([0.0, 0.0, 0.0], [1.0, 0.0]),
-        ([1.0, 0.0, 0.0], [0.0, 0.0]),
-        ([1.0, 0.0, 1.0], [0.0, 1.0]),
-        ([0.0, 0.0, 1.0], [1.0, 1.0]),
-        // right
-        ([1.0, 0.0, 0.0], [1.0, 1.0]),
-        ([1.0, 1.0, 0.0], [1.0, 0.0]),
-        ([1.0, 1.0, 1.0], [0.0, 0.0]),
-        ([1.0, 0.0, 1.0], [0.0, 1.0]),
-        // left
-        ([0.0, 0.0, 0.0], [0.0, 1.0]),
-        ([0.0, 0.0, 1.0], [1.0, 1.0]),
-        ([0.0, 1.0, 1.0], [1.0, 0.0]),
-        ([0.0, 1.0, 0.0], [0.0, 0.0]),
-    ];
-
     for _ in 0..4 {
         normals.push(match direction {
             Direction::Forward => [0.0, 0.0, 1.0],
@@ -65,8 +73,12 @@ fn insert_tile(
     let position_offset = Vec3::new(position.0 as f32, position.1 as f32, position.2 as f32);
     let index_offset = direction as usize * 4;
     for i in 0..4 {
-        positions.push((position_offset + Vec3::from(vertices[i + index_offset].0)).to_array());
-        uvs.push(vertices[i + index_offset].1);
+        let vertex_position = position_offset + Vec3::from(FACE_VERTICES[i + index_offset].0);
+        positions.push(vertex_position.to_array());
+        uvs.push(FACE_VERTICES[i + index_offset].1);
+        // Sample climate at the column corner the vertex sits on so tints blend smoothly between columns
+        let corner = vertex_position.x as usize + (CHUNK_SIZE + 1) * vertex_position.z as usize;
+        colours.push(get_tint_colour(tint, &vorld_slice.climate[corner]));
     }
 
     let quad_indices: Vec<u32> = vec![0, 1, 2, 0, 2, 3];
@@ -77,22 +89,28 @@ fn insert_tile(
 
 fn request_tile(
     look_up: &[[u32; 6]; 256],
+    tint_look_up: &[[TintMode; 6]; 256],
     voxel: u8,
     direction: Direction,
     position: (usize, usize, usize),
-    tile_requests: &mut HashMap<u32, Vec<(Direction, (usize, usize, usize))>>,
+    tile_requests: &mut HashMap<u32, Vec<TileRequest>>,
 ) {
     let tile_id = look_up[voxel as usize][direction as usize];
+    let tint = tint_look_up[voxel as usize][direction as usize];
     if let Some(positions) = tile_requests.get_mut(&tile_id) {
-        positions.push((direction, position));
+        positions.push((direction, position, tint));
     } else {
-        tile_requests.insert(tile_id, Vec::from([(direction, position)]));
+        tile_requests.insert(tile_id, Vec::from([(direction, position, tint)]));
     }
 }
 
-fn is_adjacent_block_clear(chunk_option: &Option<Chunk>, x: usize, y: usize, z: usize) -> bool {
+fn is_clear(voxel: u8) -> bool {
+    !get_block_properties(voxel).is_meshed
+}
+
+fn is_adjacent_block_clear(chunk_option: &Option<Arc<Chunk>>, x: usize, y: usize, z: usize) -> bool {
     if let Some(chunk) = chunk_option {
-        return chunk.get_voxel(x, y, z) == 0;
+        return is_clear(chunk.get_voxel(x, y, z));
     }
     true
 }
@@ -102,46 +120,49 @@ fn is_adjacent_block_clear(chunk_option: &Option<Chunk>, x: usize, y: usize, z:
 pub fn build_chunk_meshes(
     vorld_slice: VorldSlice,
     look_up: [[u32; 6]; 256],
+    tint_look_up: [[TintMode; 6]; 256],
 ) -> Vec<(u32, Mesh)> {
-    // Build map of tiles required with direction and position
-    let mut tile_requests: HashMap<u32, Vec<(Direction, (usize, usize, usize))>> =
-        HashMap::new();
-    let chunk = vorld_slice.chunk;
-    for i in 0..chunk.voxels.len() {
-        let voxel = chunk.voxels[i];
-        if voxel != 0 {
+    // Build map of tiles required with direction, position and tint
+    let mut tile_requests: HashMap<u32, Vec<TileRequest>> = HashMap::new();
+    if vorld_slice.chunk.get_uniform_id() == Some(BlockIds::Air as u8) {
+        return Vec::new();
+    }
+    let voxels = vorld_slice.chunk.to_array();
+    for i in 0..voxels.len() {
+        let voxel = voxels[i];
+        if get_block_properties(voxel).is_meshed {
             let position = Chunk::get_block_position(i);
             let (x, y, z) = position;
 
             if (x == 0 && is_adjacent_block_clear(&vorld_slice.left_chunk, CHUNK_SIZE - 1, y, z))
-                || (x != 0 && chunk.voxels[i - 1] == 0)
+                || (x != 0 && is_clear(voxels[i - 1]))
             {
-                request_tile(&look_up, voxel, Direction::Left, position, &mut tile_requests)
+                request_tile(&look_up, &tint_look_up, voxel, Direction::Left, position, &mut tile_requests)
             }
             if (y == 0 && is_adjacent_block_clear(&vorld_slice.down_chunk, x, CHUNK_SIZE - 1, z))
-                || (y != 0 && chunk.voxels[i - CHUNK_SIZE * CHUNK_SIZE] == 0)
+                || (y != 0 && is_clear(voxels[i - CHUNK_SIZE * CHUNK_SIZE]))
             {
-                request_tile(&look_up, voxel, Direction::Down, position, &mut tile_requests)
+                request_tile(&look_up, &tint_look_up, voxel, Direction::Down, position, &mut tile_requests)
             }
             if (z == 0 && is_adjacent_block_clear(&vorld_slice.back_chunk, x, y, CHUNK_SIZE - 1))
-                || (z != 0 && chunk.voxels[i - CHUNK_SIZE] == 0)
+                || (z != 0 && is_clear(voxels[i - CHUNK_SIZE]))
             {
-                request_tile(&look_up, voxel, Direction::Back, position, &mut tile_requests)
+                request_tile(&look_up, &tint_look_up, voxel, Direction::Back, position, &mut tile_requests)
             }
             if (x == 15 && is_adjacent_block_clear(&vorld_slice.right_chunk, 0, y, z))
-                || (x != 15 && chunk.voxels[i + 1] == 0)
+                || (x != 15 && is_clear(voxels[i + 1]))
             {
-                request_tile(&look_up, voxel, Direction::Right, position, &mut tile_requests)
+                request_tile(&look_up, &tint_look_up, voxel, Direction::Right, position, &mut tile_requests)
             }
             if (y == 15 && is_adjacent_block_clear(&vorld_slice.up_chunk, x, 0, z))
-                || (y != 15 && chunk.voxels[i + CHUNK_SIZE * CHUNK_SIZE] == 0)
+                || (y != 15 && is_clear(voxels[i + CHUNK_SIZE * CHUNK_SIZE]))
             {
-                request_tile(&look_up, voxel, Direction::Up, position, &mut tile_requests)
+                request_tile(&look_up, &tint_look_up, voxel, Direction::Up, position, &mut tile_requests)
             }
             if (z == 15 && is_adjacent_block_clear(&vorld_slice.forward_chunk, x, y, 0))
-                || (z != 15 && chunk.voxels[i + CHUNK_SIZE] == 0)
+                || (z != 15 && is_clear(voxels[i + CHUNK_SIZE]))
             {
-                request_tile(&look_up, voxel, Direction::Forward, position, &mut tile_requests)
+                request_tile(&look_up, &tint_look_up, voxel, Direction::Forward, position, &mut tile_requests)
             }
         }
     }
@@ -152,6 +173,7 @@ pub fn build_chunk_meshes(
         let mut positions: Vec<[f32; 3]> = Vec::new();
         let mut normals: Vec<[f32; 3]> = Vec::new();
         let mut uvs: Vec<[f32; 2]> = Vec::new();
+        let mut colours: Vec<[f32; 4]> = Vec::new();
         let mut indices: Vec<u32> = Vec::new();
 
         let requests = &tile_requests[tile_id];
@@ -160,9 +182,12 @@ pub fn build_chunk_meshes(
                 &mut positions,
                 &mut normals,
                 &mut uvs,
+                &mut colours,
                 &mut indices,
                 request.0,
                 request.1,
+                request.2,
+                &vorld_slice,
             );
         }
 
@@ -170,9 +195,38 @@ pub fn build_chunk_meshes(
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colours);
         mesh.set_indices(Some(Indices::U32(indices)));
         meshes.push((*tile_id, mesh));
     }
 
     meshes
 }
+
+/// Builds a mesh of the given faces of a unit voxel, pushed out along their normals by offset
+/// Used to draw overlays, such as damage cracks, over chunk meshes
+pub fn build_voxel_overlay_mesh(faces: &[Direction], offset: f32) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    for direction in faces {
+        let normal = direction.offset().as_vec3();
+        let n: u32 = positions.len().try_into().unwrap();
+        let index_offset = *direction as usize * 4;
+        for (position, uv) in FACE_VERTICES[index_offset..index_offset + 4].iter() {
+            positions.push((Vec3::from(*position) + offset * normal).to_array());
+            normals.push(normal.to_array());
+            uvs.push(*uv);
+        }
+        indices.extend([0, 1, 2, 0, 2, 3].iter().map(|i| n + i));
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
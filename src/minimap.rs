@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+use super::npc_spawner::Npc;
+use super::player::Player;
+use super::voxel::map;
+use super::voxel::prelude::*;
+
+pub struct MinimapConfig {
+    /// Number of columns shown either side of the player
+    pub radius: i32,
+    /// Width and height of the minimap on screen in pixels
+    pub size: f32,
+    /// Minimum time in seconds between re-rendering the map for changes to the vorld
+    pub refresh_interval: f32,
+}
+
+/// Top down map of the vorld around the player, in the top right of the screen
+struct MinimapState {
+    image: Handle<Image>,
+    /// Column the map was last rendered around
+    center: Option<IVec2>,
+    refresh_timer: Timer,
+}
+
+#[derive(Component)]
+struct Minimap;
+
+/// Dot on the minimap showing the position of the target entity
+#[derive(Component)]
+struct MinimapMarker {
+    target: Entity,
+}
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        let config = MinimapConfig {
+            radius: 32,
+            size: 192.0,
+            refresh_interval: 0.25,
+        };
+        app.insert_resource(MinimapState {
+            image: Handle::default(),
+            center: None,
+            refresh_timer: Timer::from_seconds(config.refresh_interval, false),
+        });
+        app.insert_resource(config);
+        app.add_startup_system(setup)
+            .add_system(update_minimap_image)
+            .add_system(update_minimap_markers.after(update_minimap_image));
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    config: Res<MinimapConfig>,
+    mut state: ResMut<MinimapState>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    state.image = images.add(Image::default());
+    commands.spawn_bundle(ImageBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { right: Val::Px(10.0), top: Val::Px(10.0), ..default() },
+            size: Size::new(Val::Px(config.size), Val::Px(config.size)),
+            ..default()
+        },
+        image: UiImage(state.image.clone()),
+        ..default()
+    }).insert(Minimap);
+}
+
+/// Re-renders the map when the player moves to another column, or when the vorld changes
+fn update_minimap_image(
+    time: Res<Time>,
+    vorld: Res<Vorld>,
+    config: Res<MinimapConfig>,
+    mut state: ResMut<MinimapState>,
+    mut images: ResMut<Assets<Image>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    state.refresh_timer.tick(time.delta());
+    let center = match player_query.iter().next() {
+        Some(transform) => transform.translation.floor().as_ivec3(),
+        None => return,
+    };
+    let center = IVec2::new(center.x, center.z);
+
+    let has_moved = state.center != Some(center);
+    let is_refresh_due = vorld.is_changed() && state.refresh_timer.finished();
+    if !has_moved && !is_refresh_due {
+        return;
+    }
+    state.center = Some(center);
+    state.refresh_timer.reset();
+
+    let radius = IVec2::splat(config.radius);
+    if let Some(image) = images.get_mut(&state.image) {
+        *image = map::render_top_down(&vorld, center - radius, center + radius);
+    }
+}
+
+/// Keeps a marker on the map for the player and each npc
+#[allow(clippy::type_complexity)]
+fn update_minimap_markers(
+    mut commands: Commands,
+    config: Res<MinimapConfig>,
+    state: Res<MinimapState>,
+    minimap_query: Query<Entity, With<Minimap>>,
+    target_query: Query<(Entity, &GlobalTransform, Option<&Player>), Or<(With<Player>, With<Npc>)>>,
+    mut marker_query: Query<(Entity, &MinimapMarker, &mut Style, &mut Visibility)>,
+) {
+    let (minimap_entity, center) = match (minimap_query.iter().next(), state.center) {
+        (Some(entity), Some(center)) => (entity, center),
+        _ => return,
+    };
+    let marker_size = 4.0;
+    let map_min = (center - IVec2::splat(config.radius)).as_vec2();
+    let pixels_per_column = config.size / (2 * config.radius + 1) as f32;
+
+    let mut marked_targets = HashSet::new();
+    for (entity, marker, mut style, mut visibility) in marker_query.iter_mut() {
+        let translation = match target_query.get(marker.target) {
+            Ok((_, transform, _)) => transform.translation(),
+            Err(_) => {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            },
+        };
+        marked_targets.insert(marker.target);
+
+        let position = (Vec2::new(translation.x, translation.z) - map_min) * pixels_per_column;
+        let is_on_map = position.cmple(Vec2::splat(config.size)).all() && position.cmpge(Vec2::ZERO).all();
+        if visibility.is_visible != is_on_map {
+            visibility.is_visible = is_on_map;
+        }
+        let left = Val::Px(position.x - 0.5 * marker_size);
+        let top = Val::Px(position.y - 0.5 * marker_size);
+        if style.position.left != left || style.position.top != top {
+            style.position.left = left;
+            style.position.top = top;
+        }
+    }
+
+    for (target, _, player) in target_query.iter() {
+        if marked_targets.contains(&target) {
+            continue;
+        }
+        let color = if player.is_some() { Color::YELLOW } else { Color::RED };
+        let marker = commands.spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Px(marker_size), Val::Px(marker_size)),
+                ..default()
+            },
+            color: UiColor(color),
+            // Hidden until positioned
+            visibility: Visibility { is_visible: false },
+            ..default()
+        }).insert(MinimapMarker { target }).id();
+        commands.entity(minimap_entity).add_child(marker);
+    }
+}
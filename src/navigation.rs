@@ -0,0 +1,385 @@
+use bevy::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use super::voxel::prelude::*;
+
+/// Movement capabilities of the agents the navigation grid is built for, in voxels
+pub struct NavConfig {
+    /// Number of air voxels required above the ground, including the one the agent stands in
+    pub headroom: i32,
+    /// Maximum height an agent can step up without jumping
+    pub max_step_up: i32,
+    /// Maximum height an agent will drop down
+    pub max_drop: i32,
+    /// Half width of the agent, used when smoothing paths so corners aren't clipped
+    pub agent_radius: f32,
+    /// Upper limit on cells expanded by a single path query
+    pub max_search_nodes: usize,
+}
+
+/// Walkable cells derived from the Vorld, a walkable cell is air with a solid voxel below and enough headroom
+#[derive(Default)]
+pub struct NavGrid {
+    /// Walkable cells (world space voxel positions) keyed on chunk
+    walkable: HashMap<IVec3, HashSet<IVec3>>,
+    /// Revisions of the chunk, the chunk below and the chunk above when walkable cells were last calculated
+    revisions: HashMap<IVec3, [u32; 3]>,
+    /// Revision of the whole vorld when walkable cells were last updated, so unrelated changes to the vorld are skipped
+    vorld_revision: Option<u32>,
+}
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(NavConfig {
+            headroom: 2,
+            max_step_up: 1,
+            max_drop: 3,
+            agent_radius: 0.25,
+            max_search_nodes: 8192,
+        });
+        app.insert_resource(NavGrid::default());
+        app.add_system(update_nav_grid);
+    }
+}
+
+fn is_solid(vorld: &Vorld, cell: IVec3) -> bool {
+    get_block_properties(vorld.get_voxel(cell.x, cell.y, cell.z)).is_solid
+}
+
+impl NavGrid {
+    fn get_revisions(vorld: &Vorld, chunk_key: IVec3) -> [u32; 3] {
+        [
+            vorld.get_chunk_revision(&chunk_key),
+            vorld.get_chunk_revision(&(chunk_key + IVec3::NEG_Y)),
+            vorld.get_chunk_revision(&(chunk_key + IVec3::Y)),
+        ]
+    }
+
+    fn calculate_walkable(vorld: &Vorld, config: &NavConfig, chunk_key: IVec3) -> HashSet<IVec3> {
+        let mut walkable = HashSet::new();
+        let origin = chunk_key * CHUNK_SIZE_I32;
+        for y in 0..CHUNK_SIZE_I32 {
+            for z in 0..CHUNK_SIZE_I32 {
+                for x in 0..CHUNK_SIZE_I32 {
+                    let cell = origin + IVec3::new(x, y, z);
+                    if NavGrid::is_cell_walkable(vorld, config, cell) {
+                        walkable.insert(cell);
+                    }
+                }
+            }
+        }
+        walkable
+    }
+
+    fn is_cell_walkable(vorld: &Vorld, config: &NavConfig, cell: IVec3) -> bool {
+        is_solid(vorld, cell + IVec3::NEG_Y)
+            && (0..config.headroom).all(|h| !is_solid(vorld, cell + h * IVec3::Y))
+    }
+
+    /// Recalculates walkable cells for any chunks whose voxels, or voxels they depend on, have changed
+    /// returns the number of chunks updated
+    pub fn update(&mut self, vorld: &Vorld, config: &NavConfig) -> usize {
+        if self.vorld_revision == Some(vorld.get_revision()) {
+            return 0;
+        }
+        self.vorld_revision = Some(vorld.get_revision());
+
+        // Cells standing on the top layer of a chunk belong to the chunk above, which might not exist yet
+        let mut chunk_keys: HashSet<IVec3> = vorld.chunks.keys().copied().collect();
+        chunk_keys.extend(vorld.chunks.keys().map(|key| *key + IVec3::Y));
+
+        self.walkable.retain(|key, _| chunk_keys.contains(key));
+        self.revisions.retain(|key, _| chunk_keys.contains(key));
+
+        let mut updated = 0;
+        for chunk_key in chunk_keys {
+            let revisions = Self::get_revisions(vorld, chunk_key);
+            if self.revisions.get(&chunk_key) != Some(&revisions) {
+                let walkable = Self::calculate_walkable(vorld, config, chunk_key);
+                self.walkable.insert(chunk_key, walkable);
+                self.revisions.insert(chunk_key, revisions);
+                updated += 1;
+            }
+        }
+        updated
+    }
+
+    pub fn is_walkable(&self, cell: IVec3) -> bool {
+        let chunk_key = Vorld::get_chunk_key(cell.x, cell.y, cell.z);
+        self.walkable.get(&chunk_key).is_some_and(|cells| cells.contains(&cell))
+    }
+
+    /// Finds the walkable cell the position is in, or the first walkable cell below it within max_drop
+    pub fn find_ground_cell(&self, config: &NavConfig, position: Vec3) -> Option<IVec3> {
+        let cell = position.floor().as_ivec3();
+        (0..=config.max_drop)
+            .map(|drop| cell - drop * IVec3::Y)
+            .find(|cell| self.is_walkable(*cell))
+    }
+
+    /// Neighbours reachable from cell with their movement cost, costs are ~10 per voxel travelled
+    fn get_links(&self, vorld: &Vorld, config: &NavConfig, cell: IVec3, links: &mut Vec<(IVec3, u32)>) {
+        links.clear();
+        let offsets = [
+            (IVec3::X, 10), (IVec3::NEG_X, 10), (IVec3::Z, 10), (IVec3::NEG_Z, 10),
+            (IVec3::new(1, 0, 1), 14), (IVec3::new(1, 0, -1), 14),
+            (IVec3::new(-1, 0, 1), 14), (IVec3::new(-1, 0, -1), 14),
+        ];
+        for (offset, cost) in offsets {
+            let is_diagonal = offset.x != 0 && offset.z != 0;
+            let target = cell + offset;
+
+            if self.is_walkable(target) {
+                // Don't cut corners on diagonals
+                if !is_diagonal
+                    || (self.is_walkable(cell + IVec3::new(offset.x, 0, 0))
+                        && self.is_walkable(cell + IVec3::new(0, 0, offset.z)))
+                {
+                    links.push((target, cost));
+                }
+                continue;
+            }
+            if is_diagonal {
+                // Only allow steps and drops along the axes
+                continue;
+            }
+
+            // Step up, needs space above our head to climb into
+            for step in 1..=config.max_step_up {
+                let above_head = cell + (config.headroom + step - 1) * IVec3::Y;
+                if is_solid(vorld, above_head) {
+                    break;
+                }
+                if self.is_walkable(target + step * IVec3::Y) {
+                    links.push((target + step * IVec3::Y, cost + 5 * step as u32));
+                    break;
+                }
+            }
+
+            // Drop, needs the column we step into to be clear down to the landing
+            if (0..config.headroom).all(|h| !is_solid(vorld, target + h * IVec3::Y)) {
+                for drop in 1..=config.max_drop {
+                    let landing = target - drop * IVec3::Y;
+                    if is_solid(vorld, landing) {
+                        break;
+                    }
+                    if self.is_walkable(landing) {
+                        links.push((landing, cost + 5 * drop as u32));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// A* search between two walkable cells, returns the cells of the path including start and goal
+    pub fn find_path(&self, vorld: &Vorld, config: &NavConfig, start: IVec3, goal: IVec3) -> Option<Vec<IVec3>> {
+        if !self.is_walkable(start) || !self.is_walkable(goal) {
+            return None;
+        }
+
+        let heuristic = |cell: IVec3| {
+            let d = (cell - goal).abs();
+            let (min, max) = (d.x.min(d.z) as u32, d.x.max(d.z) as u32);
+            // Vertical movement only ever costs 5 per voxel on top of the horizontal move, keep the estimate admissible
+            14 * min + 10 * (max - min) + 5 * d.y as u32
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<IVec3, IVec3> = HashMap::new();
+        let mut cost_so_far: HashMap<IVec3, u32> = HashMap::new();
+        let mut links = Vec::new();
+
+        open.push(OpenNode { cell: start, cost: 0, estimate: heuristic(start) });
+        cost_so_far.insert(start, 0);
+
+        let mut expanded = 0;
+        while let Some(OpenNode { cell, cost, .. }) = open.pop() {
+            if cell == goal {
+                let mut path = vec![goal];
+                let mut current = goal;
+                while let Some(previous) = came_from.get(&current) {
+                    path.push(*previous);
+                    current = *previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if cost > cost_so_far[&cell] {
+                // Stale entry, a cheaper route to this cell has already been expanded
+                continue;
+            }
+
+            expanded += 1;
+            if expanded > config.max_search_nodes {
+                return None;
+            }
+
+            self.get_links(vorld, config, cell, &mut links);
+            for (next, link_cost) in links.iter() {
+                let next_cost = cost + link_cost;
+                if cost_so_far.get(next).is_none_or(|existing| next_cost < *existing) {
+                    cost_so_far.insert(*next, next_cost);
+                    came_from.insert(*next, cell);
+                    open.push(OpenNode { cell: *next, cost: next_cost, estimate: next_cost + heuristic(*next) });
+                }
+            }
+        }
+        None
+    }
+
+    /// Removes intermediate cells where a straight walk on the same level is clear, returns waypoints at the center of each cell's floor
+    pub fn smooth_path(&self, config: &NavConfig, path: &[IVec3]) -> Vec<Vec3> {
+        let to_waypoint = |cell: IVec3| cell.as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+        let mut waypoints = Vec::new();
+        if path.is_empty() {
+            return waypoints;
+        }
+
+        let mut anchor = 0;
+        waypoints.push(to_waypoint(path[0]));
+        while anchor < path.len() - 1 {
+            let mut next = anchor + 1;
+            for candidate in (anchor + 2..path.len()).rev() {
+                if self.has_clear_walk(config, path[anchor], path[candidate])
+                    && path[anchor..=candidate].iter().all(|cell| cell.y == path[anchor].y)
+                {
+                    next = candidate;
+                    break;
+                }
+            }
+            waypoints.push(to_waypoint(path[next]));
+            anchor = next;
+        }
+        waypoints
+    }
+
+    /// Checks every cell swept by an agent walking in a straight line between the two cells is walkable
+    fn has_clear_walk(&self, config: &NavConfig, from: IVec3, to: IVec3) -> bool {
+        let start = from.as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+        let end = to.as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+        let delta = end - start;
+        let length = delta.length();
+        if length < f32::EPSILON {
+            return true;
+        }
+        let side = Vec3::new(-delta.z, 0.0, delta.x) / length * config.agent_radius;
+        let steps = (length / 0.1).ceil() as i32;
+        (0..=steps).all(|i| {
+            let point = start + delta * (i as f32 / steps as f32);
+            [point, point + side, point - side]
+                .iter()
+                .all(|sample| self.is_walkable(IVec3::new(sample.x.floor() as i32, from.y, sample.z.floor() as i32)))
+        })
+    }
+}
+
+#[derive(PartialEq, Eq)]
+struct OpenNode {
+    cell: IVec3,
+    cost: u32,
+    estimate: u32,
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so the BinaryHeap pops the lowest estimate first
+        other.estimate.cmp(&self.estimate).then_with(|| self.cost.cmp(&other.cost))
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn update_nav_grid(vorld: Res<Vorld>, config: Res<NavConfig>, mut nav_grid: ResMut<NavGrid>) {
+    if vorld.is_changed() {
+        let updated = nav_grid.update(&vorld, &config);
+        if updated > 0 {
+            debug!("Updated navigation for {} chunks", updated);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::StartupVorld;
+
+    fn build_config() -> NavConfig {
+        NavConfig { headroom: 2, max_step_up: 1, max_drop: 3, agent_radius: 0.25, max_search_nodes: 8192 }
+    }
+
+    fn set_door(vorld: &mut Vorld, id: BlockIds) {
+        for x in 7..=8 {
+            for y in 1..=2 {
+                vorld.add_voxel(id as u8, x, y, 4);
+            }
+        }
+    }
+
+    #[test]
+    fn paths_into_tower_through_open_door() {
+        let config = build_config();
+        let (mut vorld, _) = StartupVorld::ChunkTest.build();
+        let mut nav_grid = NavGrid::default();
+        nav_grid.update(&vorld, &config);
+
+        // The tower's door starts open
+        let (outside, inside) = (IVec3::new(8, 1, -4), IVec3::new(8, 1, 8));
+        assert!(nav_grid.is_walkable(outside) && nav_grid.is_walkable(inside));
+        let path = nav_grid.find_path(&vorld, &config, outside, inside).expect("open door should let zombies in");
+        assert_eq!(path.first(), Some(&outside));
+        assert_eq!(path.last(), Some(&inside));
+        assert!(path.iter().all(|cell| nav_grid.is_walkable(*cell)));
+        assert!(path.iter().any(|cell| (cell.x == 7 || cell.x == 8) && cell.z == 4), "path should go through the door");
+        // Every step of the path is to a neighbouring cell, so it never passes through the walls
+        for window in path.windows(2) {
+            let step = window[1] - window[0];
+            assert!(step.x.abs() <= 1 && step.z.abs() <= 1);
+        }
+
+        // Smoothing shouldn't cut through the wall either side of the door
+        let waypoints = nav_grid.smooth_path(&config, &path);
+        for window in waypoints.windows(2) {
+            let (from, to) = (window[0], window[1]);
+            if (from.z < 4.0) != (to.z < 4.0) {
+                let t = (4.5 - from.z) / (to.z - from.z);
+                let x = from.x + t * (to.x - from.x);
+                assert!((7.0..9.0).contains(&x), "waypoints cross the tower wall at x = {}", x);
+            }
+        }
+
+        set_door(&mut vorld, BlockIds::DoorClosed);
+        assert!(nav_grid.update(&vorld, &config) > 0);
+        assert!(nav_grid.find_path(&vorld, &config, outside, inside).is_none(), "closed door should block the way in");
+
+        set_door(&mut vorld, BlockIds::DoorOpen);
+        nav_grid.update(&vorld, &config);
+        assert!(nav_grid.find_path(&vorld, &config, outside, inside).is_some());
+    }
+
+    #[test]
+    fn ignores_changes_which_dont_write_voxels() {
+        let config = build_config();
+        let (mut vorld, _) = StartupVorld::ChunkTest.build();
+        let mut nav_grid = NavGrid::default();
+        assert!(nav_grid.update(&vorld, &config) > 0);
+
+        // Damage below the block's hardness doesn't change any voxels
+        assert!(!vorld.damage_voxel(IVec3::new(4, 5, 4), 1.0));
+        vorld.take_modified_chunks();
+        assert_eq!(nav_grid.update(&vorld, &config), 0);
+
+        // Only the chunk written to and the chunk above it, whose bottom cells stand on it, are recalculated as there is no chunk below
+        vorld.add_voxel(BlockIds::Stone as u8, 2, 1, 2);
+        assert_eq!(nav_grid.update(&vorld, &config), 2);
+        assert!(!nav_grid.is_walkable(IVec3::new(2, 1, 2)));
+        assert!(nav_grid.is_walkable(IVec3::new(2, 2, 2)));
+    }
+}
@@ -6,6 +6,8 @@ use super::health::Health;
 use super::named_collision_groups::*;
 use super::zombie::Zombie;
 use super::utils;
+use super::voxel::decoration::SpawnPoints;
+use super::voxel::prelude::*;
 
 pub struct NpcAssets {
     is_loaded: bool,
@@ -55,47 +57,67 @@ fn handle_asset_load(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     scenes: Res<Assets<Scene>>,
+    vorld: Res<Vorld>,
+    spawn_points: Res<SpawnPoints>,
 ) {
-    if !npc_assets.is_loaded {
-        if scenes.get(&npc_assets.tiny_person).is_some() {
-            npc_assets.is_loaded = true;
-            for x in -4..=4 {
-                commands
-                    .spawn_bundle(SceneBundle {
-                        scene: npc_assets.tiny_person.clone(),
-                        transform: Transform::from_xyz(x as f32, 0.0, 0.0),
-                        ..default()
-                    })
-                    .insert(Npc { animation_player_entity: None })
-                    .insert(FindAnimationPlayerRequest)
-                    .insert(CloneModelMaterialsRequest)
-                    .insert(Zombie::new())
-                    .insert(Health::new(10))
-                    .with_children(|child_builder| {
-                        // Should probably attempt to get the collision information out of the model, for now, hard code
-                        child_builder
-                            .spawn_bundle(SpatialBundle { transform: Transform::from_xyz(0.0, 0.5, 0.0), ..default() })
-                            .insert(Collider::cuboid(3.0 / 16.0, 0.5, 2.0 / 16.0))
-                            .insert(CollisionGroups::new(NamedCollisionGroups::Npc as u32, NamedCollisionGroups::Everything as u32));
-                    });
-            }
+    if !npc_assets.is_loaded && scenes.get(&npc_assets.tiny_person).is_some() {
+        npc_assets.is_loaded = true;
+
+        // Use the spawn points nearest the player, but not so near the player is set upon immediately
+        let zombie_count = 9;
+        let min_player_distance = 8.0;
+        let player_spawn_point = spawn_points.get_closest_player_spawn_point(Vec3::ZERO).unwrap_or(Vec3::ZERO);
+        let mut zombie_spawn_points: Vec<Vec3> = spawn_points.npc.iter()
+            .copied()
+            .filter(|point| point.distance(player_spawn_point) > min_player_distance)
+            .collect();
+        zombie_spawn_points.sort_by(|a, b| a.distance_squared(player_spawn_point).total_cmp(&b.distance_squared(player_spawn_point)));
+        zombie_spawn_points.truncate(zombie_count);
+        if zombie_spawn_points.is_empty() {
+            // Line up along the x axis, standing on the highest block in the column, or at the origin if there's nothing to stand on
+            zombie_spawn_points = (-4..=4).map(|x| {
+                let y = vorld.get_highest_solid_in_column(x, 0).map_or(0, |y| y + 1);
+                Vec3::new(x as f32, y as f32, 0.0)
+            }).collect();
+        }
 
-            let cube_mesh = meshes.add(Mesh::from(shape::Cube { size: 1.0 }));
-            let blue = Color::rgb_u8(0, 40, 90);
-            let cube_material = materials.add(blue.into());
+        for spawn_point in zombie_spawn_points {
             commands
-                .spawn_bundle(PbrBundle {
-                    mesh: cube_mesh.clone(),
-                    material: cube_material.clone(),
-                    transform: Transform::from_xyz(8.0, 0.5, 8.0),
+                .spawn_bundle(SceneBundle {
+                    scene: npc_assets.tiny_person.clone(),
+                    transform: Transform::from_translation(spawn_point),
                     ..default()
                 })
                 .insert(Npc { animation_player_entity: None })
-                .insert(Health { max_health: 100, current_health: 100 })
-                .insert(HitFlashSupport { material: cube_material.clone(), base_color: blue, flash_color: Color::RED  })
-                .insert(Collider::cuboid(0.5, 0.5, 0.5))
-                .insert(CollisionGroups::new(NamedCollisionGroups::Npc as u32, NamedCollisionGroups::Everything as u32));
+                .insert(FindAnimationPlayerRequest)
+                .insert(CloneModelMaterialsRequest)
+                .insert(Zombie::new())
+                .insert(Health::new(10))
+                .with_children(|child_builder| {
+                    // Should probably attempt to get the collision information out of the model, for now, hard code
+                    child_builder
+                        .spawn_bundle(SpatialBundle { transform: Transform::from_xyz(0.0, 0.5, 0.0), ..default() })
+                        .insert(Collider::cuboid(3.0 / 16.0, 0.5, 2.0 / 16.0))
+                        .insert(CollisionGroups::new(NamedCollisionGroups::Npc as u32, NamedCollisionGroups::Everything as u32));
+                });
         }
+
+        let cube_mesh = meshes.add(Mesh::from(shape::Cube { size: 1.0 }));
+        let blue = Color::rgb_u8(0, 40, 90);
+        let cube_material = materials.add(blue.into());
+        let cube_y = vorld.get_highest_solid_in_column(8, 8).map_or(0, |y| y + 1);
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: cube_mesh.clone(),
+                material: cube_material.clone(),
+                transform: Transform::from_xyz(8.0, cube_y as f32 + 0.5, 8.0),
+                ..default()
+            })
+            .insert(Npc { animation_player_entity: None })
+            .insert(Health::new(100))
+            .insert(HitFlashSupport { material: cube_material.clone(), base_color: blue, flash_color: Color::RED  })
+            .insert(Collider::cuboid(0.5, 0.5, 0.5))
+            .insert(CollisionGroups::new(NamedCollisionGroups::Npc as u32, NamedCollisionGroups::Everything as u32));
     }
 }
 
@@ -149,7 +171,7 @@ fn handle_clone_model_materials_request(
                     commands.entity(entity)
                         .insert(HitFlashSupport {
                             material: cloned_material_handle.clone(),
-                            base_color: base_color,
+                            base_color,
                             flash_color: Color::RED,
                         });
 
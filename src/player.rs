@@ -1,23 +1,42 @@
-use bevy::prelude::*;
-use bevy_rapier3d::prelude::*;
+use bevy::{prelude::*, render::camera::Projection, time::FixedTimesteps, transform::TransformSystem};
 
+use super::character_controller::{self, CharacterCollisionConfig, CharacterControllerStage, CharacterInput, CharacterMovementConfig, KinematicCharacterController, Stamina};
+use super::fall_damage::FallDamage;
 use super::gun;
+use super::health::{DeathEvent, Health};
 use super::player_input::PlayerInput;
-use super::named_collision_groups::*;
 use super::smoothed_follow::SmoothedFollow;
 use super::utils;
+use super::voxel::block_entity::{BlockEntities, BlockInteractEvent};
+use super::voxel::decoration::SpawnPoints;
+use super::voxel::prelude::*;
+use super::water::Breath;
+use super::zombie::Zombie;
 
+/// Where the player spawns if the level has no player spawn points
+const DEFAULT_SPAWN_POINT: Vec3 = Vec3::new(8.0, 1.0, -8.0);
+
+/// Marks the character controlled by PlayerInput
+#[derive(Component)]
+pub struct Player;
+
+/// Added to the player when their health runs out, input is disabled until they respawn
 #[derive(Component)]
-pub struct Player {
-    velocity: Vec3,
-    is_grounded: bool,
-    is_crouched: bool,
+pub struct PlayerDeath {
+    /// Seconds since the player died
+    pub time_since_death: f32,
 }
 
 #[derive(Component)]
 pub struct PlayerCamera {
     target: Entity,
     offset: Vec3,
+    /// Field of view when not sprinting, widened slightly while sprinting
+    fov: f32,
+    /// Height the camera lags behind the player after stepping up a ledge, eased back to zero
+    step_offset: f32,
+    /// Step offset before the last character move, for interpolating between moves
+    previous_step_offset: f32,
     /// The desired angle around the local x axis, -π/2 -> π/2
     pitch: f32,
     /// The desired angle around the global y axis, 0 -> 2π
@@ -29,71 +48,79 @@ pub struct AttachMuzzleRequest {
     entity: Entity
 }
 
-struct PlayerMovementConfig {
-    acceleration: f32,
-    air_acceleration: f32,
-    max_run_speed: f32,
-    max_air_movement_speed: f32,
-    stop_speed: f32,
-    jump_delta_v: f32,
-    crouch_jump_delta_v: f32,
-    acceleration_due_to_gravity: f32,
-}
-
-struct PlayerCollisionConfig {
-    player_standing_half_height: f32,
-    player_crouched_half_height: f32,
-    skin_depth: f32,
-    collider_radius: f32,
-}
-
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(setup)
             .add_system(attach_muzzle)
-            .add_system(move_player)
-            .add_system(update_look.after(move_player));
+            .add_system(update_character_input)
+            .add_system(interact)
+            .add_system(handle_player_death)
+            .add_system(respawn_player)
+            .add_system_to_stage(CharacterControllerStage, update_step_offset.after(character_controller::move_characters))
+            .add_system_to_stage(CoreStage::PostUpdate, update_look.before(TransformSystem::TransformPropagate));
     }
 }
 
-fn setup(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-) {
-    let player_spawn_point = Vec3::new(8.0, 1.0, -8.0);
-    let camera_offset = Vec3::new(0.0, 1.25, 0.0);
-
-    let movement_config = PlayerMovementConfig {
+/// Character controller with the player's movement and collision config
+pub fn build_player_controller() -> KinematicCharacterController {
+    let movement_config = CharacterMovementConfig {
         acceleration: 80.0,
         air_acceleration: 10.0,
         max_run_speed: 5.5,
+        max_sprint_speed: 8.5,
+        max_walk_speed: 2.5,
         max_air_movement_speed: 4.0,
         stop_speed: 1.5,
         jump_delta_v: 7.5,
         crouch_jump_delta_v: 6.0,
+        coyote_time: 0.1,
+        jump_buffer_time: 0.15,
+        max_air_jumps: 0,
+        climb_speed: 3.0,
+        swim_speed: 3.0,
+        swim_drag: 4.0,
+        buoyancy: 1.0,
         acceleration_due_to_gravity: 2.0 * 9.8,
     };
 
-    let collider_config = PlayerCollisionConfig {
-        player_standing_half_height: 1.0,
-        player_crouched_half_height: 0.5,
+    let collision_config = CharacterCollisionConfig {
+        standing_half_height: 1.0,
+        crouched_half_height: 0.5,
         skin_depth: 0.01,
         collider_radius: 0.25,
+        max_step_height: 1.05,
+        max_slope_angle: 45f32.to_radians(),
+        ground_snap_distance: 0.5,
+        use_voxel_collision: false,
     };
+    KinematicCharacterController::new(movement_config, collision_config)
+}
 
-    commands.insert_resource(movement_config);
-    commands.insert_resource(collider_config);
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    spawn_points: Res<SpawnPoints>,
+) {
+    let player_spawn_point = spawn_points.get_closest_player_spawn_point(Vec3::ZERO).unwrap_or(DEFAULT_SPAWN_POINT);
+    let camera_offset = Vec3::new(0.0, 1.25, 0.0);
 
     let player_entity =  commands
         .spawn()
         .insert_bundle(SpatialBundle { transform: Transform::identity().with_translation(player_spawn_point), ..default() })
-        .insert(Player {
-            velocity: Vec3::ZERO,
-            is_grounded: false,
-            is_crouched: false,
-        }).id();
+        .insert(Player)
+        .insert(build_player_controller())
+        .insert(CharacterInput::default())
+        .insert(Stamina::new(100.0, 20.0, 15.0, 25.0))
+        .insert(Health { despawn_on_death: false, ..Health::new(100) })
+        .insert(Breath::new(15.0, 5.0, 10, 1.0))
+        .insert(FallDamage {
+            curve: asset_server.load("curves/fall_damage.curve.ron"),
+            min_landing_speed: 12.0,
+            crouch_damage_scale: 0.5,
+        })
+        .id();
     
     let camera_entity = commands.spawn_bundle(SpatialBundle::default())
         .insert_bundle(Camera3dBundle { 
@@ -102,6 +129,9 @@ fn setup(
         }).insert(PlayerCamera {
             target: player_entity,
             offset: camera_offset,
+            fov: PerspectiveProjection::default().fov,
+            step_offset: 0.0,
+            previous_step_offset: 0.0,
             pitch: 0.0,
             yaw: std::f32::consts::PI,
         }).id();
@@ -139,345 +169,160 @@ fn attach_muzzle(
     }
 }
 
-fn move_player(
-    time: Res<Time>,
-    movement_config: Res<PlayerMovementConfig>,
-    collider_config: Res<PlayerCollisionConfig>,
+/// Passes the player's input and camera pitch on to their character controller
+pub fn update_character_input(
     mut player_input: ResMut<PlayerInput>,
-    rapier_context: Res<RapierContext>,
-    mut player_query: Query<(&mut Transform, &mut Player)>,
+    camera_query: Query<&PlayerCamera>,
+    mut player_query: Query<&mut CharacterInput, With<Player>>,
 ) {
-    let (mut player_transform, mut player) = player_query.iter_mut().last().unwrap();
-
-    let terrain_filter = QueryFilter::only_fixed().groups(InteractionGroups::new(NamedCollisionGroups::Everything as u32, NamedCollisionGroups::Terrain as u32));
-
-    // Determine crouch / uncrouch
-    if !player.is_crouched && player_input.crouch_requested {
-        player.is_crouched = true;
-    } else if player.is_crouched && !player_input.crouch_requested {
-        player.is_crouched = false;
-        // Ensure there is space to stand up!
-        let shape = Collider::capsule_y(collider_config.player_standing_half_height - collider_config.skin_depth - collider_config.collider_radius, collider_config.collider_radius);
-        rapier_context.intersections_with_shape(
-            player_transform.translation + collider_config.player_standing_half_height * Vec3::Y,
-            Quat::IDENTITY,
-            &shape,
-            terrain_filter,
-            |_| {
-                player.is_crouched = true;
-                false
-            }
-        );
+    for mut character_input in player_query.iter_mut() {
+        character_input.movement_direction = player_input.movement_direction;
+        character_input.crouch_requested = player_input.crouch_requested;
+        character_input.sprint_requested = player_input.sprint_requested;
+        character_input.walk_requested = player_input.walk_requested;
+        character_input.jump_requested |= player_input.jump_requested;
     }
-    let half_player_height = match player.is_crouched { false =>  collider_config.player_standing_half_height, true => collider_config.player_crouched_half_height };
-    let shape = Collider::capsule_y(half_player_height - collider_config.skin_depth - collider_config.collider_radius, collider_config.collider_radius);
+    player_input.jump_requested = false;
 
-    let local_x = player_transform.local_x();
-    let local_z = player_transform.local_z();
-    
-    // Project to x/z plane (arguably should project onto plane of ground if grounded 0 if normal is ~45 degrees of vertical)
-    let local_x = Vec3::new(local_x.x, 0.0, local_x.z).normalize();
-    let local_z = Vec3::new(local_z.x, 0.0, local_z.z).normalize();
-
-    let time_delta = time.delta_seconds();
-
-    // Transform movement input into world_space 
-    let input_vector = player_input.movement_direction.x * local_x + player_input.movement_direction.z * local_z;
-
-    if !player.is_grounded && player.velocity.length_squared() > 0.0 {
-        // Apply Drag 
-        let air_speed = player.velocity.length();
-        let drag_delta_v = air_speed * air_speed * 1.225 * time_delta / 200.0;
-        // Assumes in air and mass of 100kg, drag coefficient of ~1 and surface area ~1
-        
-        if air_speed < drag_delta_v { // Happens at around air_speed of 99 m/s
-            player.velocity = Vec3::ZERO;
-            // If we wanted to support drag at extremely high speeds properly would need to average drag across the frame, rather than instanteous maximum
-        } else {
-            player.velocity *= (air_speed - drag_delta_v) / air_speed;
+    for player_camera in camera_query.iter() {
+        if let Ok(mut character_input) = player_query.get_mut(player_camera.target) {
+            character_input.look_pitch = player_camera.pitch;
         }
     }
-
-    let player_xz_velocity = Vec3::new(player.velocity.x, 0.0, player.velocity.z);
-    // should be on movement plane see comment above about local x/z plane
-
-    let mut target_velocity = player_xz_velocity;
-    if player.is_grounded {
-        calculate_target_grounded_velocity(&movement_config, player_xz_velocity, &player_input, &mut target_velocity, time_delta, input_vector);
-    } else {
-        calculate_target_air_velocity(player_xz_velocity, &movement_config, time_delta, input_vector, &mut target_velocity);
-    }
-
-    let start_translation = player_transform.translation;
-    move_xz(&mut player_transform, target_velocity, &rapier_context, half_player_height, &shape, time_delta, collider_config.skin_depth, terrain_filter);
-
-    // Handle requested y-movement / movement due to gravity
-    let vertical_velocity = match player_input.jump_requested {
-        true => { 
-            player_input.jump_requested = false;
-            match player.is_crouched {
-                true => movement_config.crouch_jump_delta_v,
-                false => movement_config.jump_delta_v,
-            }
-            // ^^ Air jump style - arrest all vertical momentum 
-        },
-        false => player.velocity.y - movement_config.acceleration_due_to_gravity * time_delta,
-    };
-
-    let direction = match vertical_velocity > 0.0 {
-        true => Vec3::Y,
-        false => Vec3::NEG_Y,
-    };
-
-    move_y(vertical_velocity, &mut player_transform, rapier_context, half_player_height, direction, shape, time_delta, collider_config.skin_depth, terrain_filter, &mut player);
-
-    player.velocity = (player_transform.translation - start_translation) / time_delta;
 }
 
-fn calculate_target_grounded_velocity(
-    movement_config: &Res<PlayerMovementConfig>,
-    player_xz_velocity: Vec3,
-    player_input: &ResMut<PlayerInput>,
-    target_velocity: &mut Vec3,
-    time_delta: f32,
-    input_vector: Vec3
+/// Sends an interact event to the block entity the camera is looking at, if it is within reach
+fn interact(
+    vorld: Res<Vorld>,
+    block_entities: Res<BlockEntities>,
+    mut player_input: ResMut<PlayerInput>,
+    mut interact_events: EventWriter<BlockInteractEvent>,
+    camera_query: Query<&GlobalTransform, With<PlayerCamera>>,
 ) {
-    let max_movement_speed = movement_config.max_run_speed;
-    // May change in future to allow sprint & walk
-    let max_movement_speed_sqr = max_movement_speed * max_movement_speed;
-    let speed_sqr = player_xz_velocity.length_squared();
-    let is_sliding = speed_sqr > max_movement_speed_sqr + 0.001;
-    let any_input = player_input.movement_direction.length_squared() > 0.0;
-    if is_sliding {
-        // Apply linear slowing force
-        // Proportional to v can quickly result at velocity being negated at high speeds 
-        *target_velocity *= 1.0 - (5.0 * time_delta).min(1.0);
-
-        // Only allow deceleration if moving faster than max movment speed
-        if player_xz_velocity.x.is_sign_positive() != input_vector.x.is_sign_positive() {
-            target_velocity.x += movement_config.acceleration * time_delta * input_vector.x;
-        }
-        if player_xz_velocity.z.is_sign_positive() != input_vector.z.is_sign_positive() {
-            target_velocity.z += movement_config.acceleration * time_delta * input_vector.z;
-        }
-    } else if any_input {
-        // Apply slow if input in opposite direction to velocity for faster change of direction
-        if player_xz_velocity.x.is_sign_positive() != input_vector.x.is_sign_positive() {
-            target_velocity.x *= 1.0 - (2.5 * speed_sqr.sqrt() * time_delta).min(1.0);
-        } 
-        if player_xz_velocity.z.is_sign_positive() != input_vector.z.is_sign_positive() {
-            target_velocity.z *= 1.0 - (2.5 * speed_sqr.sqrt() * time_delta).min(1.0);
-        }
-        *target_velocity += movement_config.acceleration * time_delta * input_vector;
-    
-        if target_velocity.length_squared() > max_movement_speed_sqr {
-            *target_velocity = max_movement_speed * target_velocity.normalize();
-        }
-    } else {
-        if speed_sqr < movement_config.stop_speed * movement_config.stop_speed {
-            *target_velocity = Vec3::ZERO;
-        } else {
-            *target_velocity *= (2.5 * speed_sqr.sqrt() * time_delta).min(1.0)
+    if !player_input.interact_requested {
+        return;
+    }
+    player_input.interact_requested = false;
+
+    let reach = 3.0;
+    if let Some(camera_transform) = camera_query.iter().next() {
+        let hit = vorld.raycast(camera_transform.translation(), camera_transform.forward(), reach, |id| {
+            let properties = get_block_properties(id);
+            properties.is_meshed || properties.has_block_entity
+        });
+        if let Some(hit) = hit {
+            if let Some(entity) = block_entities.get(hit.position) {
+                interact_events.send(BlockInteractEvent { entity });
+            }
         }
     }
 }
 
-fn calculate_target_air_velocity(
-    player_xz_velocity: Vec3,
-    movement_config: &Res<PlayerMovementConfig>,
-    time_delta: f32,
-    input_vector: Vec3,
-    target_velocity: &mut Vec3
+/// Disables input and starts the death camera when the player's health runs out
+fn handle_player_death(
+    mut commands: Commands,
+    mut player_input: ResMut<PlayerInput>,
+    mut death_events: EventReader<DeathEvent>,
+    player_query: Query<Entity, (With<Player>, Without<PlayerDeath>)>,
 ) {
-    let target_x = player_xz_velocity.x + movement_config.air_acceleration * time_delta * input_vector.x;
-    let target_z = player_xz_velocity.z + movement_config.air_acceleration * time_delta * input_vector.z;
-    let max_air_movement_speed_sqr = movement_config.max_air_movement_speed * movement_config.max_air_movement_speed;
-    let target_air_speed_sqr = target_x * target_x + target_z * target_z;
-    let can_accelerate = target_air_speed_sqr < max_air_movement_speed_sqr;
-    if can_accelerate || target_x.abs() < player_xz_velocity.x.abs() {
-        target_velocity.x = target_x;
-    }
-    if can_accelerate || target_z.abs() < player_xz_velocity.z.abs() {
-        target_velocity.z = target_z;
-    }
-    if !(target_velocity.x == target_x && target_velocity.z == target_z) {
-        // Must be above max air movement speed, and not trying to decelerate in both axes
-        let redirect_threshold_speed_sqr = (movement_config.max_run_speed * movement_config.max_run_speed).max(max_air_movement_speed_sqr);
-        let current_air_speed_sqr = target_velocity.length_squared(); 
-        if current_air_speed_sqr < redirect_threshold_speed_sqr {
-            // allow redirection of the direction of air movement if below redirect threshold
-            *target_velocity = (current_air_speed_sqr.sqrt() / target_air_speed_sqr.sqrt()) * Vec3::new(target_x, 0.0, target_z);
+    for event in death_events.iter() {
+        if let Ok(entity) = player_query.get(event.entity) {
+            commands.entity(entity).insert(PlayerDeath { time_since_death: 0.0 });
+            player_input.is_enabled = false;
         }
     }
 }
 
-fn move_xz(
-    player_transform: &mut Mut<Transform>,
-    target_velocity: Vec3,
-    rapier_context: &Res<RapierContext>,
-    half_player_height: f32,
-    shape: &Collider,
-    time_delta: f32,
-    skin_depth: f32,
-    collision_filter: QueryFilter,
+/// Once the respawn delay has passed, moves the dead player to the spawn point furthest from any zombies and resets them
+#[allow(clippy::type_complexity)]
+fn respawn_player(
+    mut commands: Commands,
+    time: Res<Time>,
+    spawn_points: Res<SpawnPoints>,
+    mut player_input: ResMut<PlayerInput>,
+    zombie_query: Query<(&Transform, &Health), (With<Zombie>, Without<Player>)>,
+    mut camera_query: Query<&mut PlayerCamera>,
+    mut player_query: Query<(
+        Entity,
+        &mut PlayerDeath,
+        &mut Transform,
+        &mut KinematicCharacterController,
+        &mut CharacterInput,
+        &mut Health,
+        Option<&mut Stamina>,
+        Option<&mut Breath>,
+    ), With<Player>>,
 ) {
-    let mut started_overlapping = false;
-    let reset_position = player_transform.translation;
-    // Character Controller Move
-    if target_velocity.length_squared() > 0.0 {
-        let velocity_direction = target_velocity.normalize();
-        let velocity_magnitude = target_velocity.length();
-
-        if let Some((_, hit)) = rapier_context.cast_shape(
-            player_transform.translation + half_player_height * Vec3::Y,
-            Quat::IDENTITY,
-            velocity_direction,
-            shape,
-            time_delta * velocity_magnitude + skin_depth,
-            collision_filter,
-        ) {
-            if hit.toi == 0.0 {
-                // Already overlapping - should only happen if teleported or spawned inside collider
-                warn!("Started movement already overlapping");
-                player_transform.translation += target_velocity * time_delta;
-                started_overlapping = true;
-            } else {
-                // Desired movement collides, attempt to slide along surface
-                // NOTE: Casting in velocity direction means time of impact is in fact distance to impact
-                let close_distance = hit.toi - skin_depth;
-                player_transform.translation += velocity_direction * close_distance;
-                // ^^ This can be negative and will attempt to move the camera away before sliding along the surface
-
-                let stop_time = close_distance / velocity_magnitude;
-                let time_remainder = time_delta - stop_time;
-                let velocity_remainder = target_velocity * time_remainder / time_delta;
-                let slide_velocity =
-                    velocity_remainder - velocity_remainder.dot(hit.normal1) * hit.normal1;
-                let slide_velocity_direction = slide_velocity.normalize();
-                let slide_velocity_magnitude = slide_velocity.length();
-
-                if let Some((_, second_hit)) = rapier_context.cast_shape(
-                    player_transform.translation + half_player_height * Vec3::Y,
-                    Quat::IDENTITY,
-                    slide_velocity_direction,
-                    shape,
-                    time_remainder * slide_velocity_magnitude + skin_depth,
-                    collision_filter,
-                ) {
-                    // slide also collides, attempt to one further slide in direction perpenticular to both hit normals
-                    let second_slide_direction = hit.normal1.cross(second_hit.normal1);
-
-                    let close_distance = second_hit.toi - skin_depth;
-                    player_transform.translation += slide_velocity_direction * close_distance;
-
-                    let time_delta = time_remainder;
-                    let stop_time = close_distance / slide_velocity_magnitude;
-                    let time_remainder = time_delta - stop_time;
-                    let velocity_remainder = slide_velocity * time_remainder / time_delta;
-                    let slide_velocity =
-                        velocity_remainder.dot(second_slide_direction) * second_slide_direction;
-
-                    if rapier_context
-                        .cast_shape(
-                            player_transform.translation,
-                            Quat::IDENTITY,
-                            slide_velocity.normalize(),
-                            shape,
-                            time_remainder * slide_velocity.length() + skin_depth,
-                            QueryFilter::default(),
-                        )
-                        .is_none()
-                    {
-                        // Only move if there are no collisions only second slide axis, else only move up to second contact
-                        player_transform.translation += slide_velocity * time_remainder;
-                    }
-                } else {
-                    player_transform.translation += slide_velocity * time_remainder;
-                }
-            }
-        } else {
-            player_transform.translation += target_velocity * time_delta;
+    let respawn_delay = 3.0;
+    for (entity, mut death, mut transform, mut controller, mut character_input, mut health, stamina, breath) in player_query.iter_mut() {
+        death.time_since_death += time.delta_seconds();
+        if death.time_since_death < respawn_delay {
+            continue;
         }
 
-        if !started_overlapping {
-            rapier_context.intersections_with_shape(
-                player_transform.translation + half_player_height * Vec3::Y,
-                Quat::IDENTITY,
-                shape,
-                collision_filter,
-                |_| {
-                    // cast_shape sometimes lies about there being no collision due to float precision issues,
-                    // so check for intersections and if found restore to starting position
-                    warn!("Camera shape found to intersect world collider after movement, restoring to last valid position");
-                    player_transform.translation = reset_position;
-                    false
-                }
-            );
+        let zombie_positions: Vec<Vec3> = zombie_query.iter()
+            .filter(|(_, health)| health.current_health > 0)
+            .map(|(transform, _)| transform.translation)
+            .collect();
+        transform.translation = spawn_points.get_furthest_player_spawn_point(&zombie_positions)
+            .or_else(|| spawn_points.get_closest_player_spawn_point(Vec3::ZERO))
+            .unwrap_or(DEFAULT_SPAWN_POINT);
+
+        // A fresh controller has no velocity, isn't crouched and has no previous translation to interpolate the camera from
+        *controller = KinematicCharacterController::new(controller.movement_config.clone(), controller.collision_config.clone());
+        *character_input = CharacterInput::default();
+        health.current_health = health.max_health;
+        if let Some(mut stamina) = stamina {
+            stamina.current_stamina = stamina.max_stamina;
+            stamina.is_exhausted = false;
+        }
+        if let Some(mut breath) = breath {
+            breath.current_breath = breath.max_breath;
+            breath.damage_timer.reset();
         }
+        for mut player_camera in camera_query.iter_mut().filter(|camera| camera.target == entity) {
+            player_camera.step_offset = 0.0;
+            player_camera.previous_step_offset = 0.0;
+        }
+
+        commands.entity(entity).remove::<PlayerDeath>();
+        player_input.is_enabled = true;
     }
 }
 
-fn move_y(
-    vertical_velocity: f32,
-    player_transform: &mut Mut<Transform>,
-    rapier_context: Res<RapierContext>,
-    half_player_height: f32,
-    direction: Vec3,
-    shape: Collider,
-    time_delta: f32,
-    skin_depth: f32,
-    collision_filter: QueryFilter,
-    player: &mut Mut<Player>
+/// Eases the camera back up to the player after they step up a ledge, rather than snapping to the new height
+fn update_step_offset(
+    mut camera_query: Query<&mut PlayerCamera>,
+    player_query: Query<&KinematicCharacterController, With<Player>>,
 ) {
-    if vertical_velocity.abs() > 0.0 {
-        let reset_position = player_transform.translation;
-        if let Some((_, hit)) = rapier_context.cast_shape(
-            player_transform.translation + half_player_height * Vec3::Y,
-            Quat::IDENTITY,
-            direction,
-            &shape,
-            time_delta * vertical_velocity.abs() + skin_depth,
-            collision_filter,
-        ) {
-            let close_distance = hit.toi - skin_depth;
-            player_transform.translation += direction * close_distance;
-            player.is_grounded = vertical_velocity < 0.0;
-        } else {
-            player_transform.translation += direction * vertical_velocity.abs() * time_delta;
-            player.is_grounded = false;
+    let step_smoothing_rate = 15.0;
+    for mut player_camera in camera_query.iter_mut() {
+        if let Ok(controller) = player_query.get(player_camera.target) {
+            player_camera.previous_step_offset = player_camera.step_offset;
+            player_camera.step_offset = (player_camera.step_offset - controller.step_height) * (-step_smoothing_rate * character_controller::TIMESTEP).exp();
         }
-
-        rapier_context.intersections_with_shape(
-            player_transform.translation + half_player_height * Vec3::Y,
-            Quat::IDENTITY,
-            &shape,
-            collision_filter,
-            |_| {
-                // cast_shape sometimes lies about there being no collision due to float precision issues,
-                // so check for intersections and if found restore to starting position 
-                // NOTE: Have not seen this in the wild with pure vertical movement, yet
-                warn!("Camera shape found to intersect world collider after vertical movement, restoring to last valid position");
-                player_transform.translation = reset_position;
-                false
-            },
-        );
     }
 }
 
 pub fn update_look(
     time: Res<Time>,
+    fixed_timesteps: Res<FixedTimesteps>,
     player_input: Res<PlayerInput>,
-    mut camera_query: Query<(&mut Transform, &mut PlayerCamera), Without<Player>>,
-    mut player_query: Query<(&mut Transform, &Player)>,
+    mut camera_query: Query<(&mut Transform, &mut Projection, &mut PlayerCamera), Without<Player>>,
+    mut player_query: Query<(&mut Transform, &KinematicCharacterController, Option<&PlayerDeath>), With<Player>>,
 ) {
-    let rotation_speed = 0.1; // TODO: degrees = dots * 0.022
+    // degrees = dots * 0.022 * sensitivity
+    let sensitivity: f32 = 4.0;
+    let rotation_speed = (0.022 * sensitivity).to_radians();
+    let interpolation_fraction = character_controller::get_interpolation_fraction(&fixed_timesteps);
 
-    for (mut camera_transform, mut player_camera) in camera_query.iter_mut() {
-        if let Ok((mut player_transform, player)) = player_query.get_mut(player_camera.target) {
+    for (mut camera_transform, mut projection, mut player_camera) in camera_query.iter_mut() {
+        if let Ok((mut player_transform, controller, death)) = player_query.get_mut(player_camera.target) {
             // prevent rotation past 10 degrees towards vertical
             let clamp_angle = std::f32::consts::PI * (0.5 - 10.0 / 180.0);
 
-            let scaled_mouse_delta = rotation_speed * time.delta_seconds() * player_input.mouse_motion;
+            // Mouse motion is the distance moved this frame, so isn't scaled by frame time
+            let scaled_mouse_delta = rotation_speed * player_input.mouse_motion;
 
             let yaw = (player_camera.yaw - scaled_mouse_delta.x) % (2.0 * std::f32::consts::PI);
             let pitch = utils::clamp(
@@ -494,12 +339,39 @@ pub fn update_look(
             let local_x = camera_transform.local_x();
             camera_transform.rotate_axis(local_x, pitch);
 
-            if player.is_crouched {
+            if controller.is_crouched {
                 player_camera.offset.y = 0.75;
             } else {
                 player_camera.offset.y = 1.25;
             }
-            camera_transform.translation = player_transform.translation + player_camera.offset;
+            // Draw the camera between character moves, so it moves smoothly whatever the frame rate
+            let translation = controller.get_interpolated_translation(player_transform.translation, interpolation_fraction);
+            let step_offset = player_camera.previous_step_offset + (player_camera.step_offset - player_camera.previous_step_offset) * interpolation_fraction;
+            camera_transform.translation = translation + player_camera.offset + step_offset * Vec3::Y;
+
+            // Drop the camera to the ground and roll it onto its side while dead
+            if let Some(death) = death {
+                let death_camera_duration = 1.0;
+                let death_camera_height = 0.25;
+                let death_camera_roll = 75f32.to_radians();
+                let fraction = (death.time_since_death / death_camera_duration).min(1.0);
+                let eased_fraction = 1.0 - (1.0 - fraction) * (1.0 - fraction);
+                camera_transform.translation.y -= eased_fraction * (player_camera.offset.y + step_offset - death_camera_height);
+                camera_transform.rotate_local_z(eased_fraction * death_camera_roll);
+            }
+
+            if let Projection::Perspective(perspective) = projection.as_mut() {
+                let sprint_fov_scale = 1.1;
+                let fov_smoothing_rate = 8.0;
+                let target_fov = match controller.is_sprinting {
+                    true => sprint_fov_scale * player_camera.fov,
+                    false => player_camera.fov,
+                };
+                let fov = target_fov + (perspective.fov - target_fov) * (-fov_smoothing_rate * time.delta_seconds()).exp();
+                if (fov - perspective.fov).abs() > f32::EPSILON {
+                    perspective.fov = fov;
+                }
+            }
 
             player_camera.yaw = yaw;
             player_camera.pitch = pitch;
@@ -1,24 +1,49 @@
-use bevy::{input::mouse::MouseMotion, prelude::*};
+use bevy::{input::mouse::{MouseMotion, MouseWheel}, prelude::*};
 
 pub struct PlayerInput {
+    /// Input is ignored while false, e.g. while the player is dead
+    pub is_enabled: bool,
     pub mouse_motion: Vec2,
     pub movement_direction: Vec3,
     pub jump_requested: bool,
     pub crouch_requested: bool,
+    pub sprint_requested: bool,
+    pub walk_requested: bool,
     pub shoot_requested: bool,
+    pub interact_requested: bool,
+    pub toggle_build_mode_requested: bool,
+    pub place_requested: bool,
+    /// Hotbar slot chosen with the number keys this frame, zero based
+    pub hotbar_slot_requested: Option<usize>,
+    /// Mouse wheel movement this frame, positive is scrolling up
+    pub hotbar_scroll: f32,
 }
 
-pub struct PlayerInputPlugin;
-
-impl Plugin for PlayerInputPlugin {
-    fn build(&self, app: &mut App) {
-        app.insert_resource(PlayerInput {
+impl Default for PlayerInput {
+    fn default() -> Self {
+        Self {
+            is_enabled: true,
             mouse_motion: Vec2::ZERO,
             movement_direction: Vec3::ZERO,
             jump_requested: false,
             crouch_requested: false,
+            sprint_requested: false,
+            walk_requested: false,
             shoot_requested: false,
-        });
+            interact_requested: false,
+            toggle_build_mode_requested: false,
+            place_requested: false,
+            hotbar_slot_requested: None,
+            hotbar_scroll: 0.0,
+        }
+    }
+}
+
+pub struct PlayerInputPlugin;
+
+impl Plugin for PlayerInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerInput>();
         app.add_system(detect_player_input);
     }
 }
@@ -29,7 +54,16 @@ fn detect_player_input(
     mouse_button_input: Res<Input<MouseButton>>,
     mut player_input: ResMut<PlayerInput>,
     mut mouse_motion_events: EventReader<MouseMotion>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
 ) {
+    if !player_input.is_enabled {
+        // Drop input made while disabled rather than acting on it once enabled again
+        *player_input = PlayerInput { is_enabled: false, ..default() };
+        mouse_motion_events.clear();
+        mouse_wheel_events.clear();
+        return;
+    }
+
     let mut delta_x = 0.0;
     if keyboard_input.pressed(KeyCode::A) {
         delta_x -= 1.0;
@@ -62,5 +96,18 @@ fn detect_player_input(
 
     player_input.jump_requested = player_input.jump_requested || keyboard_input.just_pressed(KeyCode::Space);
     player_input.crouch_requested = keyboard_input.pressed(KeyCode::LControl);
+    player_input.sprint_requested = keyboard_input.pressed(KeyCode::LShift);
+    player_input.walk_requested = keyboard_input.pressed(KeyCode::LAlt);
     player_input.shoot_requested = player_input.shoot_requested || mouse_button_input.just_pressed(MouseButton::Left);
+    player_input.interact_requested = player_input.interact_requested || keyboard_input.just_pressed(KeyCode::E);
+    player_input.toggle_build_mode_requested = player_input.toggle_build_mode_requested || keyboard_input.just_pressed(KeyCode::B);
+    player_input.place_requested = player_input.place_requested || mouse_button_input.just_pressed(MouseButton::Right);
+
+    let hotbar_keys = [
+        KeyCode::Key1, KeyCode::Key2, KeyCode::Key3,
+        KeyCode::Key4, KeyCode::Key5, KeyCode::Key6,
+        KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    ];
+    player_input.hotbar_slot_requested = hotbar_keys.iter().position(|key| keyboard_input.just_pressed(*key));
+    player_input.hotbar_scroll = mouse_wheel_events.iter().map(|event| event.y).sum();
 }
@@ -2,14 +2,40 @@ use bevy::prelude::*;
 use bevy_hanabi::*;
 use bevy_rapier3d::prelude::*;
 
+use super::named_collision_groups::*;
+use super::voxel::block_entity::BlockEntities;
+use super::voxel::prelude::*;
+
 #[derive(Component, Copy, Clone)]
 pub struct Projectile {
     pub damage: u32,
 }
 
+/// Projectile moved by stepping through the voxel grid each frame rather than by Rapier,
+/// only npcs are tested against with Rapier, by a ray cast along the step
+#[derive(Component)]
+pub struct GridProjectile {
+    pub velocity: Vec3,
+}
+
+pub struct ProjectileConfig {
+    /// Whether newly fired projectiles are GridProjectiles rather than Rapier dynamic bodies
+    pub use_grid_traversal: bool,
+    pub gravity: Vec3,
+}
+
 pub struct ProjectileImpactEvent {
     pub projectile: Projectile,
-    pub hit_entity: Entity,
+    /// Entity whose collider was hit, None for voxels without a block entity hit by a GridProjectile
+    pub hit_entity: Option<Entity>,
+    /// Position of the projectile when the collision started, the hit point for GridProjectiles
+    pub position: Vec3,
+    /// Direction the projectile was travelling in
+    pub direction: Vec3,
+    /// Normal of the surface hit, if known
+    pub normal: Option<Vec3>,
+    /// The voxel hit, if known
+    pub voxel_hit: Option<VoxelRaycastHit>,
 }
 
 pub struct ImpactEffects {
@@ -21,8 +47,13 @@ pub struct ProjectilePlugin;
 impl Plugin for ProjectilePlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<ProjectileImpactEvent>();
+        app.insert_resource(ProjectileConfig {
+            use_grid_traversal: false,
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+        });
         app.add_startup_system(setup);
         app.add_system(detect_projectile_impact);
+        app.add_system(advance_grid_projectiles);
     }
 }
 
@@ -66,38 +97,126 @@ pub fn detect_projectile_impact(
     mut commands: Commands,
     mut collision_events: EventReader<CollisionEvent>,
     impact_effects : Res<ImpactEffects>,
-    projectile_query: Query<(&Projectile, &Transform)>,
+    projectile_query: Query<(&Projectile, &Transform, &Velocity)>,
     mut projectile_event_writer: EventWriter<ProjectileImpactEvent>,
     mut effect_query: Query<(&mut ParticleEffect, &mut Transform), Without<Projectile>>
 ) {
     for collision in collision_events.iter() {
-        if let Ok((mut effect, mut effect_transform)) = effect_query.get_mut(impact_effects.default_impact_effect) {
-            match collision {
-                CollisionEvent::Started(entity1, entity2, _event_flags) => {
-                    if let Ok((projectile, projectile_transform)) = projectile_query.get(*entity1) {
-                        projectile_event_writer.send(ProjectileImpactEvent { 
-                            projectile: *projectile,
-                            hit_entity: *entity2,
-                        });
-    
-                        effect_transform.translation = projectile_transform.translation;
-                        effect.maybe_spawner().unwrap().reset(); // As it's a once - reset spawns new particles
-    
-                        commands.entity(*entity1).despawn();
-                    } else if let Ok((projectile, projectile_transform)) = projectile_query.get(*entity2) {
-                        projectile_event_writer.send(ProjectileImpactEvent {
-                            projectile: *projectile,
-                            hit_entity: *entity1,
-                        });
-    
-                        effect_transform.translation = projectile_transform.translation;
-                        effect.maybe_spawner().unwrap().reset(); // As it's a once - reset spawns new particles
-    
-                        commands.entity(*entity2).despawn();
-                    }
-                },
-                _ => { }
+        if let CollisionEvent::Started(entity1, entity2, _event_flags) = collision {
+            let (projectile_entity, hit_entity) = match projectile_query.contains(*entity1) {
+                true => (*entity1, *entity2),
+                false => (*entity2, *entity1),
+            };
+            if let Ok((projectile, projectile_transform, velocity)) = projectile_query.get(projectile_entity) {
+                projectile_event_writer.send(ProjectileImpactEvent {
+                    projectile: *projectile,
+                    hit_entity: Some(hit_entity),
+                    position: projectile_transform.translation,
+                    direction: velocity.linvel.normalize_or_zero(),
+                    normal: None,
+                    voxel_hit: None,
+                });
+                play_impact_effect(&impact_effects, &mut effect_query, projectile_transform.translation);
+                commands.entity(projectile_entity).despawn();
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Whether a voxel stops grid projectiles, which pass through anything a character could move through, e.g. open doors and ladders
+fn stops_grid_projectiles(id: u8) -> bool {
+    get_block_properties(id).is_solid
+}
+
+/// Steps each GridProjectile along its path for the frame, stopping at the first voxel or npc in the way
+#[allow(clippy::too_many_arguments)]
+pub fn advance_grid_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<ProjectileConfig>,
+    vorld: Res<Vorld>,
+    block_entities: Res<BlockEntities>,
+    rapier_context: Res<RapierContext>,
+    impact_effects : Res<ImpactEffects>,
+    mut projectile_query: Query<(Entity, &Projectile, &mut GridProjectile, &mut Transform)>,
+    mut projectile_event_writer: EventWriter<ProjectileImpactEvent>,
+    mut effect_query: Query<(&mut ParticleEffect, &mut Transform), Without<Projectile>>
+) {
+    let time_delta = time.delta_seconds();
+    let npc_filter = QueryFilter::new().groups(InteractionGroups::new(NamedCollisionGroups::Projectile as u32, NamedCollisionGroups::Npc as u32));
+
+    for (entity, projectile, mut grid_projectile, mut transform) in projectile_query.iter_mut() {
+        grid_projectile.velocity += config.gravity * time_delta;
+        let step = grid_projectile.velocity * time_delta;
+        let distance = step.length();
+        if distance == 0.0 {
+            continue;
+        }
+        let origin = transform.translation;
+        let direction = step / distance;
+
+        let voxel_hit = vorld.raycast(origin, direction, distance, stops_grid_projectiles);
+        let max_distance = voxel_hit.map_or(distance, |hit| hit.distance);
+        let npc_hit = rapier_context.cast_ray_and_get_normal(origin, direction, max_distance, true, npc_filter);
+
+        let event = if let Some((hit_entity, intersection)) = npc_hit {
+            ProjectileImpactEvent {
+                projectile: *projectile,
+                hit_entity: Some(hit_entity),
+                position: intersection.point,
+                direction,
+                normal: Some(intersection.normal),
+                voxel_hit: None,
+            }
+        } else if let Some(hit) = voxel_hit {
+            ProjectileImpactEvent {
+                projectile: *projectile,
+                hit_entity: block_entities.get(hit.position),
+                position: origin + hit.distance * direction,
+                direction,
+                normal: Some(hit.normal.as_vec3()),
+                voxel_hit: Some(hit),
+            }
+        } else {
+            transform.translation += step;
+            continue;
+        };
+
+        // Lift the effect off the surface, so the particles aren't spawned inside it
+        let effect_position = event.position + 0.05 * event.normal.unwrap_or(Vec3::ZERO);
+        play_impact_effect(&impact_effects, &mut effect_query, effect_position);
+        projectile_event_writer.send(event);
+        commands.entity(entity).despawn();
+    }
+}
+
+fn play_impact_effect(
+    impact_effects: &ImpactEffects,
+    effect_query: &mut Query<(&mut ParticleEffect, &mut Transform), Without<Projectile>>,
+    position: Vec3,
+) {
+    if let Ok((mut effect, mut effect_transform)) = effect_query.get_mut(impact_effects.default_impact_effect) {
+        effect_transform.translation = position;
+        effect.maybe_spawner().unwrap().reset(); // As it's a once - reset spawns new particles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_projectiles_pass_through_non_solid_blocks() {
+        let mut vorld = Vorld::new();
+        vorld.add_voxel(BlockIds::DoorOpen as u8, 2, 0, 0);
+        vorld.add_voxel(BlockIds::Ladder as u8, 4, 0, 0);
+        vorld.add_voxel(BlockIds::DoorClosed as u8, 6, 0, 0);
+        vorld.add_voxel(BlockIds::Stone as u8, 8, 0, 0);
+
+        let hit = vorld.raycast(Vec3::new(0.5, 0.5, 0.5), Vec3::X, 16.0, stops_grid_projectiles).unwrap();
+        assert_eq!(hit.position, IVec3::new(6, 0, 0));
+        vorld.add_voxel(BlockIds::DoorOpen as u8, 6, 0, 0);
+        let hit = vorld.raycast(Vec3::new(0.5, 0.5, 0.5), Vec3::X, 16.0, stops_grid_projectiles).unwrap();
+        assert_eq!(hit.position, IVec3::new(8, 0, 0));
+    }
+}
@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 use std::ops::Mul;
 
+/// Rates are the fraction of the remaining distance to the target covered every 1/60th of a second
 #[derive(Component)]
 pub struct SmoothedFollow {
     pub target: Entity,
@@ -9,17 +10,25 @@ pub struct SmoothedFollow {
     pub rotation_rate: f32,
 }
 
+/// Converts a rate per 1/60th of a second into the fraction to cover in time_delta
+fn get_frame_fraction(rate: f32, time_delta: f32) -> f32 {
+    1.0 - (1.0 - rate).powf(60.0 * time_delta)
+}
+
 pub fn follow(
+    time: Res<Time>,
     mut follower_query: Query<(&SmoothedFollow, &mut Transform)>,
     transform_query: Query<&Transform, Without<SmoothedFollow>>, // This might perform better if follow targets had a "FollowTarget" tag component (?)
 ) {
     for (smoothed_follow, mut transform) in follower_query.iter_mut() {
         if let Ok(target_transform) = transform_query.get(smoothed_follow.target) {
             let from_pos = transform.translation - transform.rotation.mul(smoothed_follow.translation_offset);
-            transform.rotation = transform.rotation.slerp(target_transform.rotation, smoothed_follow.rotation_rate);
+            let rotation_fraction = get_frame_fraction(smoothed_follow.rotation_rate, time.delta_seconds());
+            let translation_fraction = get_frame_fraction(smoothed_follow.translation_rate, time.delta_seconds());
+            transform.rotation = transform.rotation.slerp(target_transform.rotation, rotation_fraction);
             // Arguably could use a smooth damp but lerp does fine
             transform.translation = from_pos.lerp(
-                    target_transform.translation, smoothed_follow.translation_rate
+                    target_transform.translation, translation_fraction
                 ) + transform.rotation.mul(smoothed_follow.translation_offset);
         }
     }
@@ -28,7 +28,7 @@ pub fn find_child_with_name(
             }
         }
     }
-    return None;
+    None
 }
 
 pub fn find_child_entity_with_component<T: Component>(
@@ -39,18 +39,18 @@ pub fn find_child_entity_with_component<T: Component>(
     for child in children {
         if let Ok((children, component_option)) = hierarchy_query.get(*child) {
             if component_option.is_some() {
-                return Some(child.clone());
+                return Some(*child);
             } else {
                 let result = find_child_entity_with_component(children, hierarchy_query, component_query);
                 if result.is_some() {
                     return result;
                 }
             }
-        } else if let Ok(_) = component_query.get(*child) {
-            return Some(child.clone())
+        } else if component_query.get(*child).is_ok() {
+            return Some(*child)
         }
     }
-    return None;
+    None
 }
 
 pub fn find_children_with_component<T: Component>(
@@ -65,7 +65,7 @@ pub fn find_children_with_component<T: Component>(
                 result.push(*child);
             } 
             find_children_with_component(result, children, hierarchy_query, component_query);
-        } else if let Ok(_) = component_query.get(*child) {
+        } else if component_query.get(*child).is_ok() {
             result.push(*child);
         }
     }
@@ -1,11 +1,15 @@
 use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
     prelude::*,
     reflect::TypeUuid,
+    pbr::{MaterialPipeline, MaterialPipelineKey},
     render::{
-        render_resource::{AsBindGroup, ShaderRef},
+        mesh::MeshVertexBufferLayout,
+        render_resource::{AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError},
         texture::ImageSampler,
     },
 };
+use serde::Deserialize;
 use std::collections::HashMap;
 
 #[derive(AsBindGroup, Debug, Clone, TypeUuid)]
@@ -24,61 +28,273 @@ impl Material for ArrayTextureMaterial {
     }
 }
 
+/// Unlit, alpha blended tile from the atlas, drawn over chunk meshes e.g. for damage cracks, or on its own e.g. for water
+/// Both sides are drawn so surfaces such as water can be seen from below
+#[derive(AsBindGroup, Debug, Clone, TypeUuid)]
+#[uuid = "6f0d1a52-2c4b-4e87-a3f1-8b5c9e7d2a64"]
+pub struct OverlayMaterial {
+    #[texture(0, dimension = "2d_array")]
+    #[sampler(1)]
+    array_texture: Handle<Image>,
+    #[uniform(2)]
+    layer: f32,
+}
+
+impl Material for OverlayMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/overlay.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+/// Description of the atlas image, loaded from a .atlas.ron file
+#[derive(Deserialize, Debug, Clone, TypeUuid)]
+#[uuid = "3c2f6a0e-5f7d-4b8e-9d8a-6b1f4e2c7a15"]
+pub struct AtlasConfig {
+    pub image_path: String,
+    /// Number of tiles stacked vertically in the image
+    pub layers: u32,
+    /// Width and height of a single tile in pixels
+    pub tile_size: u32,
+    #[serde(default)]
+    pub animations: Vec<AnimatedTile>,
+    /// Layers drawn over damaged voxels, in order of increasing damage
+    #[serde(default)]
+    pub crack_layers: Vec<u32>,
+}
+
+/// Cycles the material for a tile id through a sequence of layers
+#[derive(Deserialize, Debug, Clone)]
+pub struct AnimatedTile {
+    /// Tile id as used in VoxelConfig::id_to_tile
+    pub tile: u32,
+    pub frames: Vec<u32>,
+    /// Seconds each frame is shown for
+    pub frame_time: f32,
+}
+
+#[derive(Default)]
+pub struct AtlasConfigLoader;
+
+impl AssetLoader for AtlasConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let config = ron::de::from_bytes::<AtlasConfig>(bytes)?;
+            if config.layers == 0 || config.tile_size == 0 {
+                return Err(bevy::asset::Error::msg(format!(
+                    "Atlas config {:?} must have at least one layer and a non-zero tile size",
+                    load_context.path()
+                )));
+            }
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["atlas.ron"]
+    }
+}
+
 pub struct AtlasTexture {
     is_loaded: bool,
-    image_handle: Handle<Image>,
+    /// Set if the image doesn't match the config, the materials are removed as they can't be drawn
+    has_failed: bool,
+    config_handle: Handle<AtlasConfig>,
+    image_handle: Option<Handle<Image>>,
     layers: u32,
+    animations: Vec<AnimatedTile>,
+    /// Material per tile id, empty until the atlas config has loaded
     pub materials: HashMap<u32, Handle<ArrayTextureMaterial>>,
+    /// Overlay material per tile id, empty until the atlas config has loaded
+    pub overlay_materials: HashMap<u32, Handle<OverlayMaterial>>,
+    /// Overlay material per crack stage, empty until the atlas config has loaded
+    pub crack_materials: Vec<Handle<OverlayMaterial>>,
+}
+
+impl AtlasTexture {
+    pub fn has_materials(&self) -> bool {
+        !self.materials.is_empty()
+    }
 }
 
 pub fn init(app: &mut App) {
     app.add_plugin(MaterialPlugin::<ArrayTextureMaterial>::default())
+        .add_plugin(MaterialPlugin::<OverlayMaterial>::default())
+        .add_asset::<AtlasConfig>()
+        .init_asset_loader::<AtlasConfigLoader>()
         // Run setup in pre-startup to ensure AtlasTexture resource is available to other startup systems
         .add_startup_system_to_stage(StartupStage::PreStartup, setup)
-        .add_system(handle_atlas_load);
+        .add_system(handle_atlas_config_load)
+        .add_system(handle_atlas_load.after(handle_atlas_config_load))
+        .add_system(animate_tiles);
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(AtlasTexture {
+        is_loaded: false,
+        has_failed: false,
+        config_handle: asset_server.load("images/atlas.atlas.ron"),
+        image_handle: None,
+        layers: 0,
+        animations: Vec::new(),
+        materials: HashMap::new(),
+        overlay_materials: HashMap::new(),
+        crack_materials: Vec::new(),
+    });
 }
 
-fn setup(
-    mut commands: Commands,
+fn handle_atlas_config_load(
     asset_server: Res<AssetServer>,
+    configs: Res<Assets<AtlasConfig>>,
     mut materials: ResMut<Assets<ArrayTextureMaterial>>,
+    mut overlay_materials: ResMut<Assets<OverlayMaterial>>,
+    mut atlas: ResMut<AtlasTexture>,
 ) {
-    let atlas_handle = asset_server.load("images/atlas.png");
-    let atlas_layers = 23;
+    if atlas.image_handle.is_some() {
+        return;
+    }
+    if let Some(config) = configs.get(&atlas.config_handle) {
+        let atlas_handle: Handle<Image> = asset_server.load(&config.image_path);
+
+        for i in 0..config.layers {
+            let material = materials.add(ArrayTextureMaterial {
+                array_texture: atlas_handle.clone(),
+                layer: i as f32,
+            });
+            atlas.materials.insert(i, material);
+            let overlay_material = overlay_materials.add(OverlayMaterial {
+                array_texture: atlas_handle.clone(),
+                layer: i as f32,
+            });
+            atlas.overlay_materials.insert(i, overlay_material);
+        }
+        for animation in config.animations.iter() {
+            if animation.frames.is_empty() || animation.frame_time <= 0.0 {
+                warn!("Ignoring animation for tile {} as it has no frames or a non-positive frame time", animation.tile);
+            } else if animation.frames.iter().chain([&animation.tile]).any(|layer| *layer >= config.layers) {
+                warn!("Ignoring animation for tile {} as it references layers outside the atlas", animation.tile);
+            } else {
+                atlas.animations.push(animation.clone());
+            }
+        }
+
+        for layer in config.crack_layers.iter() {
+            match atlas.overlay_materials.get(layer).cloned() {
+                Some(material) => atlas.crack_materials.push(material),
+                None => warn!("Ignoring crack layer {} as it is outside the atlas", layer),
+            }
+        }
+
+        atlas.layers = config.layers;
+        atlas.image_handle = Some(atlas_handle);
+    }
+}
 
-    let mut atlas_materials = HashMap::new();
+fn handle_atlas_load(
+    mut image_assets: ResMut<Assets<Image>>,
+    mut atlas: ResMut<AtlasTexture>,
+    configs: Res<Assets<AtlasConfig>>,
+) {
+    if atlas.is_loaded || atlas.has_failed {
+        return;
+    }
+    let config = match configs.get(&atlas.config_handle) {
+        Some(config) => config,
+        None => return,
+    };
+    if let Some(image) = atlas.image_handle.as_ref().and_then(|handle| image_assets.get_mut(handle)) {
+        let size = image.texture_descriptor.size;
+        if size.width != config.tile_size || size.height != atlas.layers * config.tile_size {
+            error!(
+                "Atlas image is {}x{} but config expects {} layers of {}x{} tiles",
+                size.width, size.height, atlas.layers, config.tile_size, config.tile_size
+            );
+            // Materials expect an array texture, so drawing with the unconverted image would fail
+            atlas.has_failed = true;
+            atlas.materials.clear();
+            atlas.overlay_materials.clear();
+            atlas.crack_materials.clear();
+            atlas.animations.clear();
+            return;
+        }
 
-    for i in 0..atlas_layers {
-        let material = materials.add(ArrayTextureMaterial {
-            array_texture: atlas_handle.clone(),
-            layer: i as f32,
+        image.reinterpret_stacked_2d_as_array(atlas.layers);
+        image.sampler_descriptor = ImageSampler::Descriptor(wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
-        atlas_materials.insert(i, material.clone());
+        // Combination of mag Nearest and min Linear seems to work but it doesn't seem like ansiotropic filtering is available*
+        // which is what made it not work in WebGL it's likely if we find a way to enable that we'll not be able to use mag Nearest
+        // *presumably because mip-mapping is not available
+
+        // NOTE: trying to set sampler in response to EventReader<AssetEvent<Image>> AssetEvent::Created is ineffective
+        atlas.is_loaded = true;
     }
+}
 
-    commands.insert_resource(AtlasTexture {
-        is_loaded: false,
-        image_handle: atlas_handle.clone(),
-        layers: atlas_layers,
-        materials: atlas_materials,
-    });
+fn animate_tiles(
+    time: Res<Time>,
+    atlas: Res<AtlasTexture>,
+    mut materials: ResMut<Assets<ArrayTextureMaterial>>,
+    mut overlay_materials: ResMut<Assets<OverlayMaterial>>,
+) {
+    let elapsed = time.seconds_since_startup() as f32;
+    for animation in atlas.animations.iter() {
+        let frame = (elapsed / animation.frame_time) as usize % animation.frames.len();
+        let layer = animation.frames[frame] as f32;
+        if let Some(handle) = atlas.materials.get(&animation.tile) {
+            // Only take a mutable reference on frame change, as that marks the material for re-upload
+            if materials.get(handle).is_some_and(|material| material.layer != layer) {
+                if let Some(material) = materials.get_mut(handle) {
+                    material.layer = layer;
+                }
+            }
+        }
+        if let Some(handle) = atlas.overlay_materials.get(&animation.tile) {
+            if overlay_materials.get(handle).is_some_and(|material| material.layer != layer) {
+                if let Some(material) = overlay_materials.get_mut(handle) {
+                    material.layer = layer;
+                }
+            }
+        }
+    }
 }
 
-fn handle_atlas_load(mut image_assets: ResMut<Assets<Image>>, mut atlas: ResMut<AtlasTexture>) {
-    if !atlas.is_loaded {
-        if let Some(image) = image_assets.get_mut(&atlas.image_handle) {
-            atlas.is_loaded = true;
-            image.reinterpret_stacked_2d_as_array(atlas.layers);
-            image.sampler_descriptor = ImageSampler::Descriptor(wgpu::SamplerDescriptor {
-                mag_filter: wgpu::FilterMode::Nearest,
-                min_filter: wgpu::FilterMode::Linear,
-                ..Default::default()
-            });
-            // Combination of mag Nearest and min Linear seems to work but it doesn't seem like ansiotropic filtering is available*
-            // which is what made it not work in WebGL it's likely if we find a way to enable that we'll not be able to use mag Nearest
-            // *presumably because mip-mapping is not available
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas_config_matches_image() {
+        let config = ron::de::from_bytes::<AtlasConfig>(&std::fs::read("assets/images/atlas.atlas.ron").unwrap()).unwrap();
+        let decoder = png::Decoder::new(std::fs::File::open(format!("assets/{}", config.image_path)).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().width, config.tile_size);
+        assert_eq!(reader.info().height, config.layers * config.tile_size);
 
-            // NOTE: trying to set sampler in response to EventReader<AssetEvent<Image>> AssetEvent::Created is ineffective
+        for animation in config.animations.iter() {
+            assert!(!animation.frames.is_empty() && animation.frame_time > 0.0);
+            assert!(animation.frames.iter().chain([&animation.tile]).all(|layer| *layer < config.layers));
         }
+        assert!(config.crack_layers.iter().all(|layer| *layer < config.layers));
     }
 }
@@ -0,0 +1,125 @@
+use bevy::prelude::{Color, IVec2};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How the vertex colour of a voxel face is determined
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TintMode {
+    None,
+    BiomeGrass,
+    BiomeFoliage,
+    Fixed(Color),
+}
+
+/// Temperature and humidity of a column, both in the range 0 -> 1
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Climate {
+    pub temperature: f32,
+    pub humidity: f32,
+}
+
+impl Climate {
+    pub const TEMPERATE: Climate = Climate { temperature: 0.5, humidity: 0.5 };
+
+    pub fn grass_colour(&self) -> Color {
+        self.blend_corners(
+            Color::rgb(0.50, 0.70, 0.50), // cold & dry
+            Color::rgb(0.35, 0.62, 0.45), // cold & wet
+            Color::rgb(0.75, 0.72, 0.33), // hot & dry
+            Color::rgb(0.28, 0.80, 0.20), // hot & wet
+        )
+    }
+
+    pub fn foliage_colour(&self) -> Color {
+        self.blend_corners(
+            Color::rgb(0.38, 0.60, 0.38),
+            Color::rgb(0.26, 0.50, 0.34),
+            Color::rgb(0.62, 0.60, 0.20),
+            Color::rgb(0.19, 0.66, 0.10),
+        )
+    }
+
+    fn blend_corners(&self, cold_dry: Color, cold_wet: Color, hot_dry: Color, hot_wet: Color) -> Color {
+        let t = self.temperature.clamp(0.0, 1.0);
+        let h = self.humidity.clamp(0.0, 1.0);
+        let cold = lerp_colour(cold_dry, cold_wet, h);
+        let hot = lerp_colour(hot_dry, hot_wet, h);
+        lerp_colour(cold, hot, t)
+    }
+}
+
+fn lerp_colour(a: Color, b: Color, t: f32) -> Color {
+    Color::rgb(
+        a.r() + (b.r() - a.r()) * t,
+        a.g() + (b.g() - a.g()) * t,
+        a.b() + (b.b() - a.b()) * t,
+    )
+}
+
+/// Climate samples on a regular grid of columns, bilinearly interpolated between samples
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClimateMap {
+    /// Distance in voxels between samples
+    cell_size: i32,
+    /// Used for any sample point which hasn't been set
+    default_climate: Climate,
+    samples: HashMap<IVec2, Climate>,
+}
+
+impl ClimateMap {
+    pub fn new(cell_size: i32, default_climate: Climate) -> Self {
+        Self {
+            cell_size,
+            default_climate,
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Sets the climate at the sample point for cell (cell_x, cell_z), i.e. column (cell_x * cell_size, cell_z * cell_size)
+    pub fn set_sample(&mut self, cell_x: i32, cell_z: i32, climate: Climate) {
+        self.samples.insert(IVec2::new(cell_x, cell_z), climate);
+    }
+
+    fn get_sample(&self, cell_x: i32, cell_z: i32) -> Climate {
+        self.samples.get(&IVec2::new(cell_x, cell_z)).copied().unwrap_or(self.default_climate)
+    }
+
+    /// Climate at world space column position (x, z)
+    pub fn sample(&self, x: f32, z: f32) -> Climate {
+        let cell_x = x / self.cell_size as f32;
+        let cell_z = z / self.cell_size as f32;
+        let (x0, z0) = (cell_x.floor() as i32, cell_z.floor() as i32);
+        let (tx, tz) = (cell_x - x0 as f32, cell_z - z0 as f32);
+
+        let c00 = self.get_sample(x0, z0);
+        let c10 = self.get_sample(x0 + 1, z0);
+        let c01 = self.get_sample(x0, z0 + 1);
+        let c11 = self.get_sample(x0 + 1, z0 + 1);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let bilinear = |f: fn(&Climate) -> f32| {
+            lerp(lerp(f(&c00), f(&c10), tx), lerp(f(&c01), f(&c11), tx), tz)
+        };
+        Climate {
+            temperature: bilinear(|c| c.temperature),
+            humidity: bilinear(|c| c.humidity),
+        }
+    }
+}
+
+impl Default for ClimateMap {
+    fn default() -> Self {
+        Self::new(16, Climate::TEMPERATE)
+    }
+}
+
+/// Vertex colour for a tint mode given the climate at the vertex
+pub fn get_tint_colour(tint: TintMode, climate: &Climate) -> [f32; 4] {
+    match tint {
+        TintMode::None => Color::WHITE,
+        TintMode::BiomeGrass => climate.grass_colour(),
+        TintMode::BiomeFoliage => climate.foliage_colour(),
+        TintMode::Fixed(colour) => colour,
+    }.as_linear_rgba_f32()
+}
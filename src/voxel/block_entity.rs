@@ -0,0 +1,250 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::collections::HashMap;
+
+use super::block_ids::*;
+use super::world::Vorld;
+use crate::named_collision_groups::*;
+
+/// The behaviour a block entity provides, derived from the id of its voxel
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockEntityKind {
+    Door,
+    Ladder,
+}
+
+impl BlockEntityKind {
+    pub fn from_block_id(id: u8) -> Option<Self> {
+        match id {
+            id if id == BlockIds::DoorClosed as u8 || id == BlockIds::DoorOpen as u8 => Some(BlockEntityKind::Door),
+            id if id == BlockIds::Ladder as u8 => Some(BlockEntityKind::Ladder),
+            _ => None,
+        }
+    }
+}
+
+/// Companion entity for a voxel whose block id has a block entity
+/// State which must persist is encoded in the block id, so the entity can be rebuilt from the Vorld at any time
+#[derive(Component)]
+pub struct BlockEntity {
+    /// World space position of the voxel
+    pub position: IVec3,
+    pub kind: BlockEntityKind,
+}
+
+/// Block entity for each voxel position
+#[derive(Default)]
+pub struct BlockEntities {
+    entities: HashMap<IVec3, Entity>,
+}
+
+impl BlockEntities {
+    pub fn get(&self, position: IVec3) -> Option<Entity> {
+        self.entities.get(&position).copied()
+    }
+}
+
+/// Sent when something interacts with a block entity, e.g. the player pressing use on it
+pub struct BlockInteractEvent {
+    pub entity: Entity,
+}
+
+#[derive(Component)]
+pub struct Door {
+    pub is_open: bool,
+}
+
+struct DoorAssets {
+    closed_mesh: Handle<Mesh>,
+    open_mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+struct LadderAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+const DOOR_THICKNESS: f32 = 0.125;
+const LADDER_THICKNESS: f32 = 0.0625;
+
+pub fn init(app: &mut App) {
+    app.insert_resource(BlockEntities::default())
+        .add_event::<BlockInteractEvent>()
+        .add_startup_system(setup)
+        .add_system(handle_door_interaction)
+        .add_system(sync_block_entities.after(handle_door_interaction));
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let half_thickness = 0.5 * DOOR_THICKNESS;
+    // Closed panel spans the voxel on x, open panel is swung through 90 degrees about the hinge at -x
+    let closed_mesh = meshes.add(Mesh::from(shape::Box::new(1.0, 1.0, DOOR_THICKNESS)));
+    let open_mesh = meshes.add(Mesh::from(shape::Box {
+        min_x: -0.5,
+        max_x: -0.5 + DOOR_THICKNESS,
+        min_y: -0.5,
+        max_y: 0.5,
+        min_z: -half_thickness,
+        max_z: 1.0 - half_thickness,
+    }));
+    let material = materials.add(StandardMaterial {
+        base_color: Color::rgb_u8(110, 72, 40),
+        perceptual_roughness: 0.8,
+        ..default()
+    });
+    commands.insert_resource(DoorAssets { closed_mesh, open_mesh, material });
+
+    // Ladders have no collision, characters climb while overlapping the voxel instead
+    let mesh = meshes.add(Mesh::from(shape::Box::new(1.0, 1.0, LADDER_THICKNESS)));
+    let material = materials.add(StandardMaterial {
+        base_color: Color::rgb_u8(150, 110, 65),
+        perceptual_roughness: 0.9,
+        ..default()
+    });
+    commands.insert_resource(LadderAssets { mesh, material });
+}
+
+/// Spawns, updates and despawns block entities to match voxels changed in the Vorld
+fn sync_block_entities(
+    mut commands: Commands,
+    mut vorld: ResMut<Vorld>,
+    mut block_entities: ResMut<BlockEntities>,
+    door_assets: Res<DoorAssets>,
+    ladder_assets: Res<LadderAssets>,
+    block_entity_query: Query<&BlockEntity>,
+) {
+    if !vorld.has_block_entity_changes() {
+        return;
+    }
+
+    for position in vorld.take_block_entity_changes() {
+        let id = vorld.get_voxel(position.x, position.y, position.z);
+        let kind = BlockEntityKind::from_block_id(id);
+        let existing = block_entities.get(position);
+
+        let existing_kind = existing.and_then(|entity| block_entity_query.get(entity).ok()).map(|block_entity| block_entity.kind);
+        let entity = match (existing, kind) {
+            (Some(entity), Some(kind)) if existing_kind == Some(kind) => entity,
+            _ => {
+                if let Some(entity) = block_entities.entities.remove(&position) {
+                    commands.entity(entity).despawn();
+                }
+                match kind {
+                    Some(kind) => {
+                        let entity = spawn_block_entity(&mut commands, &vorld, &door_assets, &ladder_assets, position, kind);
+                        block_entities.entities.insert(position, entity);
+                        entity
+                    },
+                    None => continue,
+                }
+            },
+        };
+
+        match kind {
+            Some(BlockEntityKind::Door) => {
+                apply_door_state(&mut commands, entity, &door_assets, id == BlockIds::DoorOpen as u8);
+            },
+            Some(BlockEntityKind::Ladder) | None => { },
+        }
+    }
+}
+
+fn spawn_block_entity(
+    commands: &mut Commands,
+    vorld: &Vorld,
+    door_assets: &DoorAssets,
+    ladder_assets: &LadderAssets,
+    position: IVec3,
+    kind: BlockEntityKind,
+) -> Entity {
+    let mut transform = Transform::from_translation(position.as_vec3() + Vec3::splat(0.5));
+    let mut entity_commands = commands.spawn();
+    entity_commands.insert(BlockEntity { position, kind });
+    let is_meshed_at = |offset: IVec3| {
+        let neighbour = position + offset;
+        get_block_properties(vorld.get_voxel(neighbour.x, neighbour.y, neighbour.z)).is_meshed
+    };
+    match kind {
+        BlockEntityKind::Door => {
+            // Doors span the gap between solid neighbours, prefer x if the door is free standing
+            if !(is_meshed_at(IVec3::X) || is_meshed_at(IVec3::NEG_X))
+                && (is_meshed_at(IVec3::Z) || is_meshed_at(IVec3::NEG_Z))
+            {
+                transform.rotate_y(std::f32::consts::FRAC_PI_2);
+            }
+            entity_commands.insert_bundle(PbrBundle {
+                mesh: door_assets.closed_mesh.clone(),
+                material: door_assets.material.clone(),
+                transform,
+                ..default()
+            });
+        },
+        BlockEntityKind::Ladder => {
+            // Ladders lie flat against the first wall found, or stand in the middle of the voxel if there isn't one
+            let wall = [IVec3::NEG_Z, IVec3::Z, IVec3::NEG_X, IVec3::X].into_iter().find(|offset| is_meshed_at(*offset));
+            if let Some(wall) = wall {
+                let wall = wall.as_vec3();
+                if wall.x != 0.0 {
+                    transform.rotate_y(std::f32::consts::FRAC_PI_2);
+                }
+                transform.translation += (0.5 - 0.5 * LADDER_THICKNESS) * wall;
+            }
+            entity_commands.insert_bundle(PbrBundle {
+                mesh: ladder_assets.mesh.clone(),
+                material: ladder_assets.material.clone(),
+                transform,
+                ..default()
+            });
+        },
+    }
+    entity_commands.id()
+}
+
+/// Swaps the door's mesh to match its state, only closed doors have collision
+fn apply_door_state(commands: &mut Commands, entity: Entity, door_assets: &DoorAssets, is_open: bool) {
+    let mut entity_commands = commands.entity(entity);
+    entity_commands.insert(Door { is_open });
+    if is_open {
+        entity_commands
+            .insert(door_assets.open_mesh.clone())
+            .remove::<Collider>()
+            .remove::<CollisionGroups>();
+    } else {
+        entity_commands
+            .insert(door_assets.closed_mesh.clone())
+            .insert(Collider::cuboid(0.5, 0.5, 0.5 * DOOR_THICKNESS))
+            .insert(CollisionGroups::new(
+                NamedCollisionGroups::Terrain as u32,
+                NamedCollisionGroups::Everything as u32,
+            ));
+    }
+}
+
+/// Toggles doors which are interacted with, along with any door voxels stacked directly above or below
+fn handle_door_interaction(
+    mut vorld: ResMut<Vorld>,
+    mut interact_events: EventReader<BlockInteractEvent>,
+    door_query: Query<(&Door, &BlockEntity)>,
+) {
+    for event in interact_events.iter() {
+        if let Ok((door, block_entity)) = door_query.get(event.entity) {
+            let id = if door.is_open { BlockIds::DoorClosed as u8 } else { BlockIds::DoorOpen as u8 };
+            let is_door = |id: u8| BlockEntityKind::from_block_id(id) == Some(BlockEntityKind::Door);
+
+            let mut bottom = block_entity.position;
+            while is_door(vorld.get_voxel(bottom.x, bottom.y - 1, bottom.z)) {
+                bottom.y -= 1;
+            }
+            let mut voxel = bottom;
+            while is_door(vorld.get_voxel(voxel.x, voxel.y, voxel.z)) {
+                vorld.add_voxel(id, voxel.x, voxel.y, voxel.z);
+                voxel.y += 1;
+            }
+        }
+    }
+}
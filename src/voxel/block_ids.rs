@@ -1,5 +1,6 @@
 #[repr(u8)]
 #[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum BlockIds {
     Air = 0,
     Grass = 1,
@@ -11,4 +12,82 @@ pub enum BlockIds {
     Planks = 7,
     Debug = 8,
     Rink = 9,
-}
\ No newline at end of file
+    Leaves = 10,
+    DoorClosed = 11,
+    DoorOpen = 12,
+    Bedrock = 13,
+    CoalOre = 14,
+    IronOre = 15,
+    Ladder = 16,
+    Lava = 17,
+    Screen = 18,
+}
+
+impl BlockIds {
+    pub fn from_id(id: u8) -> Option<Self> {
+        let block = match id {
+            0 => Self::Air,
+            1 => Self::Grass,
+            2 => Self::Soil,
+            3 => Self::Stone,
+            4 => Self::StoneSlab,
+            5 => Self::StoneBlocks,
+            6 => Self::Wood,
+            7 => Self::Planks,
+            8 => Self::Debug,
+            9 => Self::Rink,
+            10 => Self::Leaves,
+            11 => Self::DoorClosed,
+            12 => Self::DoorOpen,
+            13 => Self::Bedrock,
+            14 => Self::CoalOre,
+            15 => Self::IronOre,
+            16 => Self::Ladder,
+            17 => Self::Lava,
+            18 => Self::Screen,
+            _ => return None,
+        };
+        Some(block)
+    }
+}
+
+/// Gameplay properties of a block id
+#[derive(Copy, Clone, Debug)]
+pub struct BlockProperties {
+    /// Whether the chunk mesher builds faces (and so terrain collision) for the block,
+    /// blocks which aren't meshed are drawn by their block entity if they have one
+    pub is_meshed: bool,
+    /// Whether the block obstructs movement
+    pub is_solid: bool,
+    /// Whether the block has a companion entity, see block_entity
+    pub has_block_entity: bool,
+    /// Whether characters overlapping the block climb rather than fall
+    pub is_climbable: bool,
+    /// Damage required to destroy the block, None if it can't be damaged
+    pub hardness: Option<f32>,
+    /// Whether the block is offered for placement in build mode
+    pub is_placeable: bool,
+}
+
+impl BlockProperties {
+    const EMPTY: BlockProperties = BlockProperties { is_meshed: false, is_solid: false, has_block_entity: false, is_climbable: false, hardness: None, is_placeable: false };
+    const CUBE: BlockProperties = BlockProperties { is_meshed: true, is_solid: true, has_block_entity: false, is_climbable: false, hardness: Some(16.0), is_placeable: true };
+}
+
+pub fn get_block_properties(id: u8) -> BlockProperties {
+    match id {
+        id if id == BlockIds::Air as u8 => BlockProperties::EMPTY,
+        id if id == BlockIds::Grass as u8 || id == BlockIds::Soil as u8 => BlockProperties { hardness: Some(8.0), ..BlockProperties::CUBE },
+        id if id == BlockIds::Stone as u8 => BlockProperties { hardness: Some(32.0), ..BlockProperties::CUBE },
+        id if id == BlockIds::StoneSlab as u8 || id == BlockIds::StoneBlocks as u8 => BlockProperties { hardness: Some(40.0), ..BlockProperties::CUBE },
+        id if id == BlockIds::Leaves as u8 => BlockProperties { hardness: Some(4.0), ..BlockProperties::CUBE },
+        id if id == BlockIds::CoalOre as u8 || id == BlockIds::IronOre as u8 => BlockProperties { hardness: Some(48.0), ..BlockProperties::CUBE },
+        id if id == BlockIds::Debug as u8 || id == BlockIds::Rink as u8 || id == BlockIds::Bedrock as u8 => BlockProperties { hardness: None, is_placeable: false, ..BlockProperties::CUBE },
+        id if id == BlockIds::Lava as u8 => BlockProperties { hardness: None, ..BlockProperties::CUBE },
+        id if id == BlockIds::DoorClosed as u8 => BlockProperties { is_meshed: false, is_solid: true, has_block_entity: true, is_climbable: false, hardness: Some(16.0), is_placeable: true },
+        // Doors are placed closed and opened in place
+        id if id == BlockIds::DoorOpen as u8 => BlockProperties { is_meshed: false, is_solid: false, has_block_entity: true, is_climbable: false, hardness: Some(16.0), is_placeable: false },
+        id if id == BlockIds::Ladder as u8 => BlockProperties { is_meshed: false, is_solid: false, has_block_entity: true, is_climbable: true, hardness: Some(8.0), is_placeable: true },
+        _ => BlockProperties::CUBE,
+    }
+}
@@ -5,35 +5,374 @@ pub const CHUNK_SIZE_I32: i32 = 16;
 pub const CHUNK_SIZE_F32: f32 = 16.0;
 pub const CHUNK_ARRAY_SIZE: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
 
-#[derive(Copy, Clone, Debug)]
+/// Largest palette before a chunk switches to full storage, i.e. 4 bits per voxel
+const MAX_PALETTE_SIZE: usize = 16;
+/// Full chunks switch back to a palette once they hold this few ids, below MAX_PALETTE_SIZE so
+/// a chunk gaining and losing a single id doesn't convert back and forth on every write
+const MIN_FULL_IDS: usize = MAX_PALETTE_SIZE / 2;
+
+/// How the voxels of a chunk are stored, chosen to be as small as the contents allow
+#[derive(Clone, Debug)]
+pub enum ChunkStorage {
+    /// Every voxel has the same id, e.g. all air or all stone
+    Uniform(u8),
+    /// Voxels index into a palette of ids, indices are packed bits_per_index bits at a time into u64s
+    Palette {
+        palette: Vec<u8>,
+        /// Number of voxels using each palette entry, entries are removed when they reach zero
+        counts: Vec<u16>,
+        bits_per_index: u32,
+        indices: Vec<u64>,
+    },
+    Full {
+        voxels: Box<[u8; CHUNK_ARRAY_SIZE]>,
+        /// Number of voxels of each id, so the chunk knows when it fits a palette again
+        counts: Box<[u16; 256]>,
+    },
+}
+
+#[derive(Clone, Debug)]
 pub struct Chunk {
     pub indices: IVec3,
-    pub voxels: [u8; CHUNK_ARRAY_SIZE],
+    storage: ChunkStorage,
 }
 
 impl Chunk {
     pub fn new(indices: IVec3, fill: u8) -> Self {
         Self {
             indices,
-            voxels: [ fill; CHUNK_ARRAY_SIZE ],
+            storage: ChunkStorage::Uniform(fill),
         }
     }
+
+    /// Builds a chunk from a dense array, indexed as get_block_position, using the smallest storage that fits
+    pub fn from_array(indices: IVec3, voxels: [u8; CHUNK_ARRAY_SIZE]) -> Self {
+        Self {
+            indices,
+            storage: Self::encode(&voxels),
+        }
+    }
+
     pub fn add_voxel(&mut self, id: u8, x: usize, y: usize, z: usize) {
         if x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE {
-            self.voxels[x + CHUNK_SIZE * z + CHUNK_SIZE * CHUNK_SIZE * y] = id;
+            self.set_voxel_at_index(id, Self::get_index(x, y, z));
         } else {
             panic!("Received add_voxel instruction outside chunk bounds");
         }
     }
+
     pub fn get_voxel(&self, x: usize, y: usize, z: usize) -> u8 {
         if x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE {
-            self.voxels[x + CHUNK_SIZE * z + CHUNK_SIZE * CHUNK_SIZE * y]
+            self.get_voxel_at_index(Self::get_index(x, y, z))
         } else {
             panic!("Received get_voxel request outside chunk bounds");
         }
     }
 
+    pub fn get_voxel_at_index(&self, i: usize) -> u8 {
+        match &self.storage {
+            ChunkStorage::Uniform(id) => *id,
+            ChunkStorage::Palette { palette, bits_per_index, indices, .. } => {
+                palette[Self::read_packed(indices, *bits_per_index, i)]
+            },
+            ChunkStorage::Full { voxels, .. } => voxels[i],
+        }
+    }
+
+    /// Writes a voxel, converting the storage if the chunk's ids no longer fit the current representation
+    /// or now fit a smaller one
+    fn set_voxel_at_index(&mut self, id: u8, i: usize) {
+        match &mut self.storage {
+            ChunkStorage::Uniform(fill) => {
+                if *fill != id {
+                    let mut indices = vec![0; Self::get_word_count(1)];
+                    Self::write_packed(&mut indices, 1, i, 1);
+                    let counts = vec![CHUNK_ARRAY_SIZE as u16 - 1, 1];
+                    self.storage = ChunkStorage::Palette { palette: vec![*fill, id], counts, bits_per_index: 1, indices };
+                }
+            },
+            ChunkStorage::Palette { palette, counts, bits_per_index, indices } => {
+                let previous_index = Self::read_packed(indices, *bits_per_index, i);
+                if palette[previous_index] == id {
+                    return;
+                }
+                let palette_index = match Self::find_or_add_palette_entry(palette, counts, bits_per_index, indices, id) {
+                    Some(palette_index) => palette_index,
+                    None => {
+                        // Palette is full, switch to storing every voxel
+                        let mut voxels = self.to_array();
+                        voxels[i] = id;
+                        self.storage = Self::encode_full(voxels);
+                        return;
+                    },
+                };
+                Self::write_packed(indices, *bits_per_index, i, palette_index);
+                counts[palette_index] += 1;
+                counts[previous_index] -= 1;
+                if counts[previous_index] == 0 {
+                    Self::remove_palette_entry(palette, counts, *bits_per_index, indices, previous_index);
+                    // Shrink once the palette uses a quarter of the index width, or is down to one id
+                    if palette.len() == 1 || (*bits_per_index > 1 && palette.len() * 4 <= 1 << *bits_per_index) {
+                        self.compact();
+                    }
+                }
+            },
+            ChunkStorage::Full { voxels, counts } => {
+                let previous_id = voxels[i];
+                if previous_id == id {
+                    return;
+                }
+                voxels[i] = id;
+                counts[id as usize] += 1;
+                counts[previous_id as usize] -= 1;
+                if counts[previous_id as usize] == 0 && counts.iter().filter(|count| **count > 0).count() <= MIN_FULL_IDS {
+                    self.compact();
+                }
+            },
+        }
+    }
+
+    /// Returns the palette index for id, adding it and widening the packed indices if required
+    /// None if the palette is already at MAX_PALETTE_SIZE
+    fn find_or_add_palette_entry(palette: &mut Vec<u8>, counts: &mut Vec<u16>, bits_per_index: &mut u32, indices: &mut Vec<u64>, id: u8) -> Option<usize> {
+        if let Some(palette_index) = palette.iter().position(|entry| *entry == id) {
+            return Some(palette_index);
+        }
+        if palette.len() >= MAX_PALETTE_SIZE {
+            return None;
+        }
+
+        palette.push(id);
+        counts.push(0);
+        if palette.len() > 1 << *bits_per_index {
+            let new_bits = *bits_per_index * 2;
+            let mut repacked = vec![0; Self::get_word_count(new_bits)];
+            for j in 0..CHUNK_ARRAY_SIZE {
+                Self::write_packed(&mut repacked, new_bits, j, Self::read_packed(indices, *bits_per_index, j));
+            }
+            *indices = repacked;
+            *bits_per_index = new_bits;
+        }
+        Some(palette.len() - 1)
+    }
+
+    /// Removes an unused palette entry by moving the last entry into its place
+    fn remove_palette_entry(palette: &mut Vec<u8>, counts: &mut Vec<u16>, bits_per_index: u32, indices: &mut [u64], palette_index: usize) {
+        let last = palette.len() - 1;
+        if palette_index != last {
+            for j in 0..CHUNK_ARRAY_SIZE {
+                if Self::read_packed(indices, bits_per_index, j) == last {
+                    Self::write_packed(indices, bits_per_index, j, palette_index);
+                }
+            }
+        }
+        palette.swap_remove(palette_index);
+        counts.swap_remove(palette_index);
+    }
+
+    /// Re-encodes the chunk with the smallest storage that fits its current contents
+    pub fn compact(&mut self) {
+        self.storage = Self::encode(&self.to_array());
+    }
+
+    fn encode(voxels: &[u8; CHUNK_ARRAY_SIZE]) -> ChunkStorage {
+        let mut palette: Vec<u8> = Vec::new();
+        let mut counts: Vec<u16> = Vec::new();
+        for id in voxels.iter() {
+            match palette.iter().position(|entry| entry == id) {
+                Some(palette_index) => counts[palette_index] += 1,
+                None if palette.len() < MAX_PALETTE_SIZE => {
+                    palette.push(*id);
+                    counts.push(1);
+                },
+                None => return Self::encode_full(*voxels),
+            }
+        }
+
+        if palette.len() == 1 {
+            return ChunkStorage::Uniform(palette[0]);
+        }
+
+        let mut bits_per_index = 1;
+        while palette.len() > 1 << bits_per_index {
+            bits_per_index *= 2;
+        }
+        let mut indices = vec![0; Self::get_word_count(bits_per_index)];
+        for (i, id) in voxels.iter().enumerate() {
+            let palette_index = palette.iter().position(|entry| entry == id).unwrap();
+            Self::write_packed(&mut indices, bits_per_index, i, palette_index);
+        }
+        ChunkStorage::Palette { palette, counts, bits_per_index, indices }
+    }
+
+    fn encode_full(voxels: [u8; CHUNK_ARRAY_SIZE]) -> ChunkStorage {
+        let mut counts = Box::new([0; 256]);
+        for id in voxels.iter() {
+            counts[*id as usize] += 1;
+        }
+        ChunkStorage::Full { voxels: Box::new(voxels), counts }
+    }
+
+    /// Returns the id of every voxel if the chunk is a single id
+    pub fn get_uniform_id(&self) -> Option<u8> {
+        match self.storage {
+            ChunkStorage::Uniform(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn get_storage(&self) -> &ChunkStorage {
+        &self.storage
+    }
+
+    /// Decodes the chunk into a dense array, indexed as get_block_position
+    pub fn to_array(&self) -> [u8; CHUNK_ARRAY_SIZE] {
+        match &self.storage {
+            ChunkStorage::Uniform(id) => [*id; CHUNK_ARRAY_SIZE],
+            ChunkStorage::Full { voxels, .. } => **voxels,
+            ChunkStorage::Palette { .. } => {
+                let mut voxels = [0; CHUNK_ARRAY_SIZE];
+                for (i, voxel) in voxels.iter_mut().enumerate() {
+                    *voxel = self.get_voxel_at_index(i);
+                }
+                voxels
+            },
+        }
+    }
+
+    /// Approximate bytes used to store the voxels, including heap allocations
+    pub fn get_memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + match &self.storage {
+            ChunkStorage::Uniform(_) => 0,
+            ChunkStorage::Palette { palette, counts, indices, .. } => palette.capacity() + 2 * counts.capacity() + 8 * indices.capacity(),
+            ChunkStorage::Full { .. } => CHUNK_ARRAY_SIZE + 2 * 256,
+        }
+    }
+
+    fn get_index(x: usize, y: usize, z: usize) -> usize {
+        x + CHUNK_SIZE * z + CHUNK_SIZE * CHUNK_SIZE * y
+    }
+
+    fn get_word_count(bits_per_index: u32) -> usize {
+        CHUNK_ARRAY_SIZE * bits_per_index as usize / 64
+    }
+
+    fn read_packed(indices: &[u64], bits_per_index: u32, i: usize) -> usize {
+        let per_word = 64 / bits_per_index as usize;
+        let shift = (i % per_word) as u32 * bits_per_index;
+        let mask = (1u64 << bits_per_index) - 1;
+        ((indices[i / per_word] >> shift) & mask) as usize
+    }
+
+    fn write_packed(indices: &mut [u64], bits_per_index: u32, i: usize, value: usize) {
+        let per_word = 64 / bits_per_index as usize;
+        let shift = (i % per_word) as u32 * bits_per_index;
+        let mask = (1u64 << bits_per_index) - 1;
+        let word = &mut indices[i / per_word];
+        *word = (*word & !(mask << shift)) | ((value as u64 & mask) << shift);
+    }
+
     pub fn get_block_position(i: usize) -> (usize, usize, usize) {
         ( i % CHUNK_SIZE, i / (CHUNK_SIZE * CHUNK_SIZE), (i / CHUNK_SIZE) % CHUNK_SIZE)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_palette(chunk: &Chunk) -> bool {
+        matches!(chunk.get_storage(), ChunkStorage::Palette { .. })
+    }
+
+    fn is_full(chunk: &Chunk) -> bool {
+        matches!(chunk.get_storage(), ChunkStorage::Full { .. })
+    }
+
+    #[test]
+    fn dug_out_chunk_becomes_uniform() {
+        let mut chunk = Chunk::new(IVec3::ZERO, 3);
+        assert_eq!(chunk.get_memory_usage(), std::mem::size_of::<Chunk>());
+
+        chunk.set_voxel_at_index(0, 0);
+        assert!(is_palette(&chunk));
+        // 1 bit per voxel, an eighth of a dense array
+        assert_eq!(chunk.get_memory_usage(), std::mem::size_of::<Chunk>() + 2 + 2 * 2 + CHUNK_ARRAY_SIZE / 8);
+
+        for i in 0..CHUNK_ARRAY_SIZE {
+            chunk.set_voxel_at_index(0, i);
+        }
+        assert_eq!(chunk.get_uniform_id(), Some(0));
+        assert_eq!(chunk.get_memory_usage(), std::mem::size_of::<Chunk>());
+    }
+
+    #[test]
+    fn full_chunk_returns_to_palette() {
+        let mut chunk = Chunk::new(IVec3::ZERO, 0);
+        for id in 1..=20 {
+            chunk.set_voxel_at_index(id, id as usize);
+        }
+        assert!(is_full(&chunk));
+        assert!(chunk.get_memory_usage() > CHUNK_ARRAY_SIZE);
+
+        // Still too many ids to be worth converting back
+        for id in 1..=10 {
+            chunk.set_voxel_at_index(0, id as usize);
+        }
+        assert!(is_full(&chunk));
+
+        // Down to MIN_FULL_IDS ids, air plus 11 to 17
+        for id in 18..=20 {
+            chunk.set_voxel_at_index(0, id as usize);
+        }
+        assert!(is_palette(&chunk));
+        // 8 ids needs 4 bits per voxel, half a dense array
+        assert_eq!(chunk.get_memory_usage(), std::mem::size_of::<Chunk>() + 8 + 2 * 8 + CHUNK_ARRAY_SIZE / 2);
+
+        // Narrows back to 2 bits per voxel at 4 ids, air plus 11 to 13
+        for id in 14..=17 {
+            chunk.set_voxel_at_index(0, id as usize);
+        }
+        match chunk.get_storage() {
+            ChunkStorage::Palette { palette, bits_per_index, .. } => {
+                assert_eq!(palette.len(), 4);
+                assert_eq!(*bits_per_index, 2);
+            },
+            _ => panic!("expected palette storage"),
+        }
+
+        for id in 11..=13 {
+            chunk.set_voxel_at_index(0, id as usize);
+        }
+        assert_eq!(chunk.get_uniform_id(), Some(0));
+    }
+
+    #[test]
+    fn matches_dense_array_under_random_writes() {
+        let mut chunk = Chunk::new(IVec3::ZERO, 0);
+        let mut expected = [0u8; CHUNK_ARRAY_SIZE];
+        // Deterministic LCG, the number of ids in use rises and falls so every conversion is exercised
+        let mut state: u32 = 12345;
+        let mut next = || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            state >> 8
+        };
+        for step in 0..200_000 {
+            let id_range = match (step / 20_000) % 4 {
+                0 => 2,
+                1 => 6,
+                2 => 24,
+                _ => 1,
+            };
+            let i = next() as usize % CHUNK_ARRAY_SIZE;
+            let id = (next() % id_range) as u8;
+            chunk.set_voxel_at_index(id, i);
+            expected[i] = id;
+            assert_eq!(chunk.get_voxel_at_index(i), id);
+            if step % 10_000 == 0 {
+                assert_eq!(chunk.to_array(), expected);
+            }
+        }
+        assert_eq!(chunk.to_array(), expected);
+    }
+}
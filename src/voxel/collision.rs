@@ -0,0 +1,228 @@
+use bevy::prelude::*;
+
+use super::block_ids::*;
+use super::world::Vorld;
+
+/// Boxes closer than this to a voxel face are treated as touching rather than overlapping it
+const EPSILON: f32 = 1e-4;
+
+/// Result of sweeping a box through the vorld
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VoxelSweep {
+    /// Movement which can be made without entering a solid voxel
+    pub movement: Vec3,
+    /// Normals of the voxel faces which stopped the movement, at most one per axis
+    pub contact_normals: Vec<IVec3>,
+    /// Whether downward movement was stopped by a voxel below
+    pub is_grounded: bool,
+}
+
+/// Range of voxel positions the box covers on an axis, excluding voxels it only touches
+fn get_covered_range(min: f32, max: f32) -> (i32, i32) {
+    ((min + EPSILON).floor() as i32, (max - EPSILON).ceil() as i32 - 1)
+}
+
+/// Voxel positions covered by the box, excluding voxels it only touches
+fn get_covered_voxels(min: Vec3, max: Vec3) -> (IVec3, IVec3) {
+    let (x, y, z) = (get_covered_range(min.x, max.x), get_covered_range(min.y, max.y), get_covered_range(min.z, max.z));
+    (IVec3::new(x.0, y.0, z.0), IVec3::new(x.1, y.1, z.1))
+}
+
+fn contains_solid(vorld: &Vorld, min: IVec3, max: IVec3) -> bool {
+    min.cmple(max).all() && vorld.voxels_in_aabb(min, max).any(|(_, id)| get_block_properties(id).is_solid)
+}
+
+/// Whether the box between min and max overlaps any voxel matching the predicate
+pub fn is_aabb_overlapping(vorld: &Vorld, min: Vec3, max: Vec3, predicate: impl Fn(u8) -> bool) -> bool {
+    let (min, max) = get_covered_voxels(min, max);
+    min.cmple(max).all() && vorld.voxels_in_aabb(min, max).any(|(_, id)| predicate(id))
+}
+
+/// Whether the box between min and max overlaps no solid voxels
+pub fn is_aabb_clear(vorld: &Vorld, min: Vec3, max: Vec3) -> bool {
+    let (min, max) = get_covered_voxels(min, max);
+    !contains_solid(vorld, min, max)
+}
+
+/// Moves the box distance along a single axis (0 x, 1 y, 2 z), returns None if the way is clear
+/// or the distance it can move while staying skin_depth away from the first solid voxel in the way
+/// Solid voxels the box already overlaps are ignored, so a box which starts overlapping can move out
+pub fn sweep_aabb_axis(vorld: &Vorld, min: Vec3, max: Vec3, axis: usize, distance: f32, skin_depth: f32) -> Option<f32> {
+    if distance == 0.0 {
+        return None;
+    }
+    // Layers of voxels perpendicular to the axis the leading face passes, nearest first
+    let (step, face, first, last) = if distance > 0.0 {
+        (1, max[axis], (max[axis] - EPSILON).ceil() as i32, (max[axis] + distance + skin_depth).floor() as i32)
+    } else {
+        (-1, min[axis], (min[axis] + EPSILON).floor() as i32 - 1, (min[axis] + distance - skin_depth).floor() as i32)
+    };
+    let (mut covered_min, mut covered_max) = get_covered_voxels(min, max);
+
+    let mut layer = first;
+    while (last - layer) * step >= 0 {
+        covered_min[axis] = layer;
+        covered_max[axis] = layer;
+        if contains_solid(vorld, covered_min, covered_max) {
+            let gap = match step {
+                1 => layer as f32 - face,
+                _ => face - (layer + 1) as f32,
+            };
+            let allowed = (gap - skin_depth).max(0.0);
+            return (allowed < distance.abs()).then_some(step as f32 * allowed);
+        }
+        layer += step;
+    }
+    None
+}
+
+/// Moves the box by movement one axis at a time, stopping each axis skin_depth short of solid voxels
+/// Vertical movement is resolved first, so a box landing against a wall doesn't catch on it
+pub fn sweep_aabb(vorld: &Vorld, min: Vec3, max: Vec3, movement: Vec3, skin_depth: f32) -> VoxelSweep {
+    let mut sweep = VoxelSweep::default();
+    let (mut min, mut max) = (min, max);
+    for axis in [1, 0, 2] {
+        let distance = movement[axis];
+        let allowed = match sweep_aabb_axis(vorld, min, max, axis, distance, skin_depth) {
+            Some(allowed) => {
+                let mut normal = IVec3::ZERO;
+                normal[axis] = -distance.signum() as i32;
+                sweep.contact_normals.push(normal);
+                sweep.is_grounded |= normal == IVec3::Y;
+                allowed
+            },
+            None => distance,
+        };
+        min[axis] += allowed;
+        max[axis] += allowed;
+        sweep.movement[axis] = allowed;
+    }
+    sweep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::StartupVorld;
+
+    const SKIN_DEPTH: f32 = 0.01;
+
+    fn build_vorld(solid: &[IVec3]) -> Vorld {
+        let mut vorld = Vorld::new();
+        for position in solid {
+            vorld.add_voxel(BlockIds::Stone as u8, position.x, position.y, position.z);
+        }
+        vorld
+    }
+
+    fn assert_approx(actual: Option<f32>, expected: Option<f32>) {
+        match (actual, expected) {
+            (Some(actual), Some(expected)) => assert!((actual - expected).abs() < 1e-5, "{} != {}", actual, expected),
+            _ => assert_eq!(actual, expected),
+        }
+    }
+
+    /// Box of a character's collider, as KinematicCharacterController::get_aabb with the player's config
+    fn get_character_aabb(translation: Vec3, half_height: f32) -> (Vec3, Vec3) {
+        let radius = 0.25;
+        (
+            translation + Vec3::new(-radius, SKIN_DEPTH, -radius),
+            translation + Vec3::new(radius, 2.0 * half_height - SKIN_DEPTH, radius),
+        )
+    }
+
+    #[test]
+    fn touching_corners_and_edges_is_clear() {
+        let vorld = build_vorld(&[IVec3::ZERO]);
+        assert!(is_aabb_clear(&vorld, Vec3::new(-1.0, 0.2, -1.0), Vec3::new(0.0, 0.8, 0.0)));
+        assert!(is_aabb_clear(&vorld, Vec3::new(1.0, 1.0, 1.0), Vec3::new(2.0, 2.0, 2.0)));
+        // Within EPSILON of the face still counts as touching
+        assert!(is_aabb_clear(&vorld, Vec3::new(-1.0, 0.2, 0.2), Vec3::new(0.00005, 0.8, 0.8)));
+        assert!(!is_aabb_clear(&vorld, Vec3::new(-1.0, 0.2, 0.2), Vec3::new(0.001, 0.8, 0.8)));
+
+        // Sliding past the corner diagonally moves freely on x, which then puts the voxel in the way on z
+        let sweep = sweep_aabb(&vorld, Vec3::new(-1.0, 0.2, -1.0), Vec3::new(0.0, 0.8, 0.0), Vec3::new(0.5, 0.0, 0.5), SKIN_DEPTH);
+        assert_eq!(sweep.movement, Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(sweep.contact_normals, vec![IVec3::new(0, 0, -1)]);
+        assert!(!sweep.is_grounded);
+
+        // Moving along the edge without crossing into the voxel isn't stopped
+        let sweep = sweep_aabb(&vorld, Vec3::new(-1.0, 0.2, -1.0), Vec3::new(0.0, 0.8, 0.0), Vec3::new(0.0, 0.0, 3.0), SKIN_DEPTH);
+        assert_eq!(sweep.movement, Vec3::new(0.0, 0.0, 3.0));
+        assert!(sweep.contact_normals.is_empty());
+    }
+
+    #[test]
+    fn fits_exact_one_voxel_gap() {
+        let walls: Vec<IVec3> = (0..4).flat_map(|z| [IVec3::new(-1, 0, z), IVec3::new(1, 0, z)]).collect();
+        let vorld = build_vorld(&walls);
+        let (min, max) = (Vec3::new(0.0, 0.1, -2.0), Vec3::new(1.0, 0.9, -1.0));
+        assert!(is_aabb_clear(&vorld, min, max));
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 2, 6.0, SKIN_DEPTH), None);
+
+        let sweep = sweep_aabb(&vorld, min, max, Vec3::new(0.0, 0.0, 6.0), SKIN_DEPTH);
+        assert_eq!(sweep.movement, Vec3::new(0.0, 0.0, 6.0));
+
+        // Any wider and the box catches on both walls
+        let (wide_min, wide_max) = (min - Vec3::new(0.001, 0.0, 0.0), max + Vec3::new(0.001, 0.0, 0.0));
+        assert!(!is_aabb_clear(&vorld, wide_min + Vec3::Z * 2.0, wide_max + Vec3::Z * 2.0));
+        assert_approx(sweep_aabb_axis(&vorld, wide_min, wide_max, 2, 6.0, SKIN_DEPTH), Some(1.0 - SKIN_DEPTH));
+    }
+
+    #[test]
+    fn stops_skin_depth_short() {
+        let vorld = build_vorld(&[IVec3::new(3, 0, 0)]);
+        let (min, max) = (Vec3::new(0.5, 0.2, 0.2), Vec3::new(1.5, 0.8, 0.8));
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 0, 2.0, SKIN_DEPTH), Some(1.5 - SKIN_DEPTH));
+        // Movement which stops at or before the skin is unobstructed
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 0, 1.5 - SKIN_DEPTH, SKIN_DEPTH), None);
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 0, 1.0, SKIN_DEPTH), None);
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 0, -2.0, SKIN_DEPTH), None);
+
+        // Already inside the skin never moves closer, or gets pushed back
+        let inside = Vec3::new(1.495, 0.0, 0.0);
+        assert_approx(sweep_aabb_axis(&vorld, min + inside, max + inside, 0, 0.1, SKIN_DEPTH), Some(0.0));
+        assert_approx(sweep_aabb_axis(&vorld, min + inside, max + inside, 0, -0.1, SKIN_DEPTH), None);
+    }
+
+    #[test]
+    fn lands_on_floor_and_escapes_overlap() {
+        let vorld = build_vorld(&[IVec3::ZERO]);
+        let (min, max) = (Vec3::new(0.2, 2.0, 0.2), Vec3::new(0.8, 3.0, 0.8));
+        let sweep = sweep_aabb(&vorld, min, max, Vec3::new(0.0, -5.0, 0.0), SKIN_DEPTH);
+        assert!((sweep.movement.y + 1.0 - SKIN_DEPTH).abs() < 1e-5, "{}", sweep.movement);
+        assert_eq!(sweep.contact_normals, vec![IVec3::Y]);
+        assert!(sweep.is_grounded);
+
+        // A box overlapping the voxel ignores it, so it can move out
+        let (min, max) = (Vec3::new(0.2, 0.5, 0.2), Vec3::new(0.8, 1.5, 0.8));
+        assert!(!is_aabb_clear(&vorld, min, max));
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 1, 1.0, SKIN_DEPTH), None);
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 0, 1.0, SKIN_DEPTH), None);
+    }
+
+    #[test]
+    fn crouch_jump_corridor_only_fits_crouched() {
+        // The controller test vorld has a wall at z 0 from x -29 to -27 with a one voxel hole at x -28, y 1
+        let (vorld, _) = StartupVorld::ControllerTest.build();
+        let (standing_half_height, crouched_half_height) = (1.0, 0.5);
+
+        let in_hole = Vec3::new(-27.5, 1.0, 0.5);
+        let (min, max) = get_character_aabb(in_hole, crouched_half_height);
+        assert!(is_aabb_clear(&vorld, min, max));
+        let (min, max) = get_character_aabb(in_hole, standing_half_height);
+        assert!(!is_aabb_clear(&vorld, min, max));
+
+        // Crouched at the height of the hole passes straight through
+        let before_wall = Vec3::new(-27.5, 1.0, -1.0);
+        let (min, max) = get_character_aabb(before_wall, crouched_half_height);
+        let sweep = sweep_aabb(&vorld, min, max, Vec3::new(0.0, 0.0, 2.5), SKIN_DEPTH);
+        assert_eq!(sweep.movement, Vec3::new(0.0, 0.0, 2.5));
+
+        // Standing hits the top of the hole, and crouching too low hits its bottom
+        let (min, max) = get_character_aabb(before_wall, standing_half_height);
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 2, 2.5, SKIN_DEPTH), Some(0.75 - SKIN_DEPTH));
+        let (min, max) = get_character_aabb(before_wall - 0.5 * Vec3::Y, crouched_half_height);
+        assert_approx(sweep_aabb_axis(&vorld, min, max, 2, 2.5, SKIN_DEPTH), Some(0.75 - SKIN_DEPTH));
+    }
+}
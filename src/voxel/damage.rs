@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use super::atlas_loader::AtlasTexture;
+use super::block_entity::BlockEntity;
+use super::block_ids::*;
+use super::direction::Direction;
+use super::world::Vorld;
+use super::ChunkMesh;
+use crate::mesher;
+use crate::projectile::{self, ProjectileImpactEvent};
+
+pub struct VoxelDamageConfig {
+    /// Damage removed from every damaged voxel per second
+    pub decay_per_second: f32,
+}
+
+/// Crack overlay for a damaged voxel, with the crack stage and faces it is showing
+struct DamageOverlay {
+    entity: Entity,
+    stage: usize,
+    mesh: Handle<Mesh>,
+    exposed_faces: Vec<Direction>,
+}
+
+/// Crack overlays by damaged voxel position
+#[derive(Default)]
+struct DamageOverlays {
+    overlays: HashMap<IVec3, DamageOverlay>,
+    /// Vorld revision the overlays' faces were built against
+    vorld_revision: Option<u32>,
+}
+
+pub fn init(app: &mut App) {
+    app.insert_resource(VoxelDamageConfig { decay_per_second: 2.0 })
+        .insert_resource(DamageOverlays::default())
+        .add_system(handle_projectile_impact.after(projectile::detect_projectile_impact).after(projectile::advance_grid_projectiles))
+        .add_system(decay_damage.after(handle_projectile_impact))
+        .add_system(update_damage_overlays.after(decay_damage));
+}
+
+/// Finds the voxel a projectile hit, by stepping along its path through the impact position
+/// falling back to the closest meshed voxel around the impact if the path doesn't cross one
+fn find_impacted_voxel(vorld: &Vorld, position: Vec3, direction: Vec3) -> Option<IVec3> {
+    let is_meshed = |id: u8| get_block_properties(id).is_meshed;
+    if let Some(hit) = vorld.raycast(position - 0.5 * direction, direction, 1.0, is_meshed) {
+        return Some(hit.position);
+    }
+
+    let center = position.floor().as_ivec3();
+    let mut closest: Option<(f32, IVec3)> = None;
+    for (voxel, id) in vorld.voxels_in_aabb(center - IVec3::ONE, center + IVec3::ONE) {
+        if is_meshed(id) {
+            let min = voxel.as_vec3();
+            let distance = position.distance_squared(position.clamp(min, min + Vec3::ONE));
+            if closest.is_none_or(|(closest_distance, _)| distance < closest_distance) {
+                closest = Some((distance, voxel));
+            }
+        }
+    }
+    closest.map(|(_, voxel)| voxel)
+}
+
+fn handle_projectile_impact(
+    mut vorld: ResMut<Vorld>,
+    mut projectile_event_reader: EventReader<ProjectileImpactEvent>,
+    chunk_mesh_query: Query<(), With<ChunkMesh>>,
+    block_entity_query: Query<&BlockEntity>,
+) {
+    for event in projectile_event_reader.iter() {
+        let position = if let Some(hit) = event.voxel_hit {
+            Some(hit.position)
+        } else if let Some(block_entity) = event.hit_entity.and_then(|entity| block_entity_query.get(entity).ok()) {
+            Some(block_entity.position)
+        } else if event.hit_entity.is_some_and(|entity| chunk_mesh_query.contains(entity)) {
+            find_impacted_voxel(&vorld, event.position, event.direction)
+        } else {
+            None
+        };
+
+        if let Some(position) = position {
+            vorld.damage_voxel(position, event.projectile.damage as f32);
+        }
+    }
+}
+
+fn decay_damage(
+    time: Res<Time>,
+    config: Res<VoxelDamageConfig>,
+    mut vorld: ResMut<Vorld>,
+) {
+    // Check first, to avoid flagging the Vorld as changed every frame
+    if vorld.has_damaged_voxels() {
+        vorld.decay_damage(config.decay_per_second * time.delta_seconds());
+    }
+}
+
+/// Faces of the voxel at position not hidden by a meshed neighbour
+fn get_exposed_faces(vorld: &Vorld, position: IVec3) -> Vec<Direction> {
+    Direction::ALL.into_iter().filter(|direction| {
+        let neighbour = position + direction.offset();
+        !get_block_properties(vorld.get_voxel(neighbour.x, neighbour.y, neighbour.z)).is_meshed
+    }).collect()
+}
+
+/// Keeps a crack overlay over the exposed faces of each damaged voxel, with a stage matching its damage
+/// Faces are rebuilt whenever voxels are written, as a neighbour being placed or removed changes which are exposed
+fn update_damage_overlays(
+    mut commands: Commands,
+    vorld: Res<Vorld>,
+    atlas: Res<AtlasTexture>,
+    mut damage_overlays: ResMut<DamageOverlays>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let stage_count = atlas.crack_materials.len();
+    if stage_count == 0 || (damage_overlays.overlays.is_empty() && !vorld.has_damaged_voxels()) {
+        return;
+    }
+
+    let damaged_voxels: HashMap<IVec3, f32> = vorld.get_damaged_voxels()
+        .filter(|(position, _)| get_block_properties(vorld.get_voxel(position.x, position.y, position.z)).is_meshed)
+        .collect();
+
+    damage_overlays.overlays.retain(|position, overlay| {
+        let is_damaged = damaged_voxels.contains_key(position);
+        if !is_damaged {
+            commands.entity(overlay.entity).despawn();
+        }
+        is_damaged
+    });
+
+    let vorld_revision = Some(vorld.get_revision());
+    if damage_overlays.vorld_revision != vorld_revision {
+        damage_overlays.vorld_revision = vorld_revision;
+        for (position, overlay) in damage_overlays.overlays.iter_mut() {
+            let exposed_faces = get_exposed_faces(&vorld, *position);
+            if exposed_faces != overlay.exposed_faces {
+                if let Some(mesh) = meshes.get_mut(&overlay.mesh) {
+                    *mesh = mesher::build_voxel_overlay_mesh(&exposed_faces, 0.002);
+                }
+                overlay.exposed_faces = exposed_faces;
+            }
+        }
+    }
+
+    for (position, fraction) in damaged_voxels {
+        let stage = ((fraction * stage_count as f32) as usize).min(stage_count - 1);
+        let material = atlas.crack_materials[stage].clone();
+        match damage_overlays.overlays.get_mut(&position) {
+            Some(overlay) => {
+                if overlay.stage != stage {
+                    commands.entity(overlay.entity).insert(material);
+                    overlay.stage = stage;
+                }
+            },
+            None => {
+                let exposed_faces = get_exposed_faces(&vorld, position);
+                let mesh = meshes.add(mesher::build_voxel_overlay_mesh(&exposed_faces, 0.002));
+                let entity = commands.spawn_bundle(MaterialMeshBundle {
+                    mesh: mesh.clone(),
+                    material,
+                    transform: Transform::from_translation(position.as_vec3()),
+                    ..default()
+                }).id();
+                damage_overlays.overlays.insert(position, DamageOverlay { entity, stage, mesh, exposed_faces });
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::chunk::Chunk;
+
+    #[test]
+    fn exposed_faces_follow_neighbour_changes() {
+        let mut vorld = Vorld::new();
+        vorld.fill_region(IVec3::ZERO, IVec3::splat(2), BlockIds::Stone as u8);
+        let center = IVec3::ONE;
+        assert!(get_exposed_faces(&vorld, center).is_empty());
+
+        vorld.add_voxel(BlockIds::Air as u8, 1, 2, 1);
+        assert_eq!(get_exposed_faces(&vorld, center), vec![Direction::Up]);
+
+        // Doors and ladders aren't meshed, so the face behind them still needs cracks
+        vorld.add_voxel(BlockIds::Ladder as u8, 0, 1, 1);
+        let exposed_faces = get_exposed_faces(&vorld, center);
+        assert_eq!(exposed_faces.len(), 2);
+        assert!(exposed_faces.contains(&Direction::Up));
+
+        vorld.add_voxel(BlockIds::Stone as u8, 1, 2, 1);
+        assert_eq!(get_exposed_faces(&vorld, center).len(), 1);
+        assert!(!get_exposed_faces(&vorld, center).contains(&Direction::Up));
+    }
+
+    #[test]
+    fn damage_removes_voxels_at_hardness() {
+        let mut vorld = Vorld::new();
+        vorld.add_voxel(BlockIds::Soil as u8, 0, 0, 0);
+        vorld.add_voxel(BlockIds::Bedrock as u8, 1, 0, 0);
+
+        assert!(!vorld.damage_voxel(IVec3::ZERO, 6.0));
+        assert_eq!(vorld.get_damaged_voxels().collect::<Vec<_>>(), vec![(IVec3::ZERO, 0.75)]);
+        assert!(vorld.damage_voxel(IVec3::ZERO, 2.0));
+        assert_eq!(vorld.get_voxel(0, 0, 0), BlockIds::Air as u8);
+        assert!(!vorld.has_damaged_voxels());
+
+        // Blocks without a hardness can't be damaged, nor can air
+        assert!(!vorld.damage_voxel(IVec3::X, 1000.0));
+        assert!(!vorld.damage_voxel(IVec3::ZERO, 1000.0));
+        assert_eq!(vorld.get_voxel(1, 0, 0), BlockIds::Bedrock as u8);
+        assert!(!vorld.has_damaged_voxels());
+    }
+
+    #[test]
+    fn decay_clears_damage() {
+        let mut vorld = Vorld::new();
+        vorld.add_voxel(BlockIds::Soil as u8, 0, 0, 0);
+        vorld.damage_voxel(IVec3::ZERO, 3.0);
+
+        let mut time = Time::default();
+        time.update_with_instant(time.startup());
+        let mut app = App::new();
+        app.insert_resource(VoxelDamageConfig { decay_per_second: 2.0 })
+            .insert_resource(vorld)
+            .insert_resource(time)
+            .add_system(decay_damage);
+        let advance = |app: &mut App, seconds: f32| {
+            let mut time = app.world.resource_mut::<Time>();
+            let instant = time.last_update().unwrap() + std::time::Duration::from_secs_f32(seconds);
+            time.update_with_instant(instant);
+            app.update();
+        };
+
+        advance(&mut app, 1.0);
+        assert_eq!(app.world.resource::<Vorld>().get_damaged_voxels().collect::<Vec<_>>(), vec![(IVec3::ZERO, 0.125)]);
+        advance(&mut app, 1.0);
+        assert!(!app.world.resource::<Vorld>().has_damaged_voxels());
+        assert_eq!(app.world.resource::<Vorld>().get_voxel(0, 0, 0), BlockIds::Soil as u8);
+    }
+
+    #[test]
+    fn overwriting_voxels_clears_damage() {
+        let mut vorld = Vorld::new();
+        vorld.fill_region(IVec3::ZERO, IVec3::new(2, 0, 0), BlockIds::Stone as u8);
+        for x in 0..3 {
+            vorld.damage_voxel(IVec3::new(x, 0, 0), 8.0);
+        }
+
+        // Writing the same block keeps the damage, a different one clears it
+        vorld.add_voxel(BlockIds::Stone as u8, 0, 0, 0);
+        vorld.add_voxel(BlockIds::Soil as u8, 1, 0, 0);
+        let damaged: Vec<IVec3> = vorld.get_damaged_voxels().map(|(position, _)| position).collect();
+        assert_eq!(damaged.len(), 2);
+        assert!(!damaged.contains(&IVec3::X));
+
+        vorld.insert_chunk(Chunk::new(IVec3::ZERO, BlockIds::Stone as u8));
+        assert!(!vorld.has_damaged_voxels());
+    }
+
+    #[test]
+    fn finds_closest_voxel_when_the_path_misses() {
+        let mut vorld = Vorld::new();
+        vorld.add_voxel(BlockIds::Stone as u8, 0, 0, 0);
+        vorld.add_voxel(BlockIds::Stone as u8, 1, 0, 0);
+
+        // Straight into the side of the voxel
+        assert_eq!(find_impacted_voxel(&vorld, Vec3::new(2.0, 0.5, 0.5), Vec3::NEG_X), Some(IVec3::X));
+        // Grazing the top, the path never enters a voxel so the closest to the impact is used
+        assert_eq!(find_impacted_voxel(&vorld, Vec3::new(0.4, 1.05, 0.5), Vec3::X), Some(IVec3::ZERO));
+        assert_eq!(find_impacted_voxel(&vorld, Vec3::new(1.6, 1.05, 0.5), Vec3::X), Some(IVec3::X));
+        assert_eq!(find_impacted_voxel(&vorld, Vec3::new(0.5, 4.0, 0.5), Vec3::X), None);
+    }
+}
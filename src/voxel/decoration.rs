@@ -0,0 +1,273 @@
+use bevy::prelude::*;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+use super::block_ids::*;
+use super::chunk::CHUNK_SIZE_I32;
+use super::noise::Noise;
+use super::world::Vorld;
+
+/// Height of a standing character's head above the floor it stands on, the top of the player's capsule less its radius
+const HEAD_HEIGHT: f32 = 1.75;
+
+/// Candidate spawn positions found while decorating, each is the center of the floor of a voxel with headroom above
+#[derive(Default, Debug)]
+pub struct SpawnPoints {
+    /// Open sky positions on the surface
+    pub player: Vec<Vec3>,
+    /// Surface positions and cave floors
+    pub npc: Vec<Vec3>,
+}
+
+impl SpawnPoints {
+    /// Drops spawn points where a character's head would start below the sea, so nothing spawns drowning
+    pub fn remove_underwater(&mut self, sea_level: f32) {
+        let is_head_above_water = |point: &Vec3| point.y + HEAD_HEIGHT >= sea_level;
+        self.player.retain(is_head_above_water);
+        self.npc.retain(is_head_above_water);
+    }
+
+    pub fn get_closest_player_spawn_point(&self, position: Vec3) -> Option<Vec3> {
+        self.player.iter().copied().min_by(|a, b| a.distance_squared(position).total_cmp(&b.distance_squared(position)))
+    }
+
+    /// Player spawn point whose nearest threat is furthest away, None if there are no player spawn points or no threats
+    pub fn get_furthest_player_spawn_point(&self, threats: &[Vec3]) -> Option<Vec3> {
+        let get_nearest_threat_distance = |point: Vec3| threats.iter().map(|threat| threat.distance_squared(point)).fold(f32::MAX, f32::min);
+        if threats.is_empty() {
+            return None;
+        }
+        self.player.iter().copied().max_by(|a, b| get_nearest_threat_distance(*a).total_cmp(&get_nearest_threat_distance(*b)))
+    }
+}
+
+pub struct DecorationConfig {
+    /// Width in columns of the grid cells features are placed in, at most one feature per cell
+    pub feature_cell_size: i32,
+    pub tree_chance: f32,
+    pub ruin_chance: f32,
+    /// Spacing in columns between columns searched for spawn points
+    pub spawn_cell_size: i32,
+}
+
+impl Default for DecorationConfig {
+    fn default() -> Self {
+        Self {
+            feature_cell_size: 8,
+            tree_chance: 0.35,
+            ruin_chance: 0.04,
+            spawn_cell_size: 8,
+        }
+    }
+}
+
+/// A single voxel written by a feature
+struct FeatureWrite {
+    position: IVec3,
+    id: BlockIds,
+    /// Whether the write may replace solid blocks, otherwise it only fills air and leaves
+    replace_solid: bool,
+}
+
+/// Second phase of world generation, places multi-block features onto generated terrain
+/// Features are planned in parallel against the finished terrain and then written through the Vorld, so they can cross chunk borders freely
+pub struct Decorator {
+    pub config: DecorationConfig,
+    noise: Noise,
+}
+
+impl Decorator {
+    pub fn new(seed: u32, config: DecorationConfig) -> Self {
+        Self {
+            config,
+            noise: Noise::new(seed).derive(100),
+        }
+    }
+
+    /// Decorates columns between min_column and max_column inclusive, returns spawn points found after decorating
+    pub fn decorate(&self, vorld: &mut Vorld, min_column: IVec2, max_column: IVec2) -> SpawnPoints {
+        let cell_size = self.config.feature_cell_size;
+        let min_cell = IVec2::new(min_column.x.div_euclid(cell_size), min_column.y.div_euclid(cell_size));
+        let max_cell = IVec2::new(max_column.x.div_euclid(cell_size), max_column.y.div_euclid(cell_size));
+        let mut cells = Vec::new();
+        for z in min_cell.y..=max_cell.y {
+            for x in min_cell.x..=max_cell.x {
+                cells.push(IVec2::new(x, z));
+            }
+        }
+
+        let planned: &Vorld = vorld;
+        let features = ComputeTaskPool::init(TaskPool::default).scope(|scope| {
+            for cell in cells.iter() {
+                scope.spawn(async move { self.plan_feature(planned, *cell) });
+            }
+        });
+
+        // Apply in cell order so overlapping features resolve the same way every time
+        for write in features.into_iter().flatten() {
+            let position = write.position;
+            let existing = vorld.get_voxel(position.x, position.y, position.z);
+            let is_soft = existing == BlockIds::Air as u8 || existing == BlockIds::Leaves as u8;
+            // Leaves never overwrite anything but air, so overlapping canopies don't eat each other's trunks
+            let can_write = if write.id == BlockIds::Leaves {
+                existing == BlockIds::Air as u8
+            } else {
+                write.replace_solid || is_soft
+            };
+            if can_write {
+                vorld.add_voxel(write.id as u8, position.x, position.y, position.z);
+            }
+        }
+
+        self.find_spawn_points(vorld, min_column, max_column)
+    }
+
+    fn plan_feature(&self, vorld: &Vorld, cell: IVec2) -> Vec<FeatureWrite> {
+        let cell_size = self.config.feature_cell_size;
+        let roll = self.noise.hash_unit(cell.x, 0, cell.y);
+        // Keep features away from the cell edges so neighbouring features rarely touch
+        let margin = 2;
+        let span = (cell_size - 2 * margin).max(1) as u32;
+        let column = cell * cell_size + IVec2::splat(margin) + IVec2::new(
+            (self.noise.hash(cell.x, 1, cell.y) % span) as i32,
+            (self.noise.hash(cell.x, 2, cell.y) % span) as i32,
+        );
+
+        let ground_y = match vorld.get_highest_solid_in_column(column.x, column.y) {
+            Some(y) => y,
+            None => return Vec::new(),
+        };
+        let ground = IVec3::new(column.x, ground_y, column.y);
+        let ground_id = vorld.get_voxel(ground.x, ground.y, ground.z);
+
+        if roll < self.config.tree_chance {
+            if ground_id == BlockIds::Grass as u8 {
+                return self.plan_tree(cell, ground);
+            }
+        } else if roll < self.config.tree_chance + self.config.ruin_chance {
+            return self.plan_ruin(vorld, cell, ground);
+        }
+        Vec::new()
+    }
+
+    fn plan_tree(&self, cell: IVec2, ground: IVec3) -> Vec<FeatureWrite> {
+        let mut writes = Vec::new();
+        let height = 4 + (self.noise.hash(cell.x, 3, cell.y) % 3) as i32;
+        let top = ground + height * IVec3::Y;
+
+        // Rounded blob of leaves around the top of the trunk, skipping the outermost corners
+        for y in -2..=1 {
+            let radius: i32 = if y == 1 { 1 } else { 2 };
+            for z in -radius..=radius {
+                for x in -radius..=radius {
+                    let is_corner = x.abs() == radius && z.abs() == radius;
+                    if is_corner && (radius == 1 || self.noise.hash(top.x + x, top.y + y, top.z + z) & 1 == 0) {
+                        continue;
+                    }
+                    writes.push(FeatureWrite { position: top + IVec3::new(x, y, z), id: BlockIds::Leaves, replace_solid: false });
+                }
+            }
+        }
+        for y in 1..=height {
+            writes.push(FeatureWrite { position: ground + y * IVec3::Y, id: BlockIds::Wood, replace_solid: false });
+        }
+        writes
+    }
+
+    /// Square of broken walls on a slab floor, only placed on ground flat enough for the floor
+    fn plan_ruin(&self, vorld: &Vorld, cell: IVec2, ground: IVec3) -> Vec<FeatureWrite> {
+        let mut writes = Vec::new();
+        let half_size = 2 + (self.noise.hash(cell.x, 4, cell.y) % 2) as i32;
+        for (x, z) in [(-half_size, -half_size), (half_size, -half_size), (-half_size, half_size), (half_size, half_size)] {
+            let corner_y = vorld.get_highest_solid_in_column(ground.x + x, ground.z + z);
+            if corner_y.is_none_or(|y| (y - ground.y).abs() > 1) {
+                return writes;
+            }
+        }
+
+        for z in -half_size..=half_size {
+            for x in -half_size..=half_size {
+                let floor = ground + IVec3::new(x, 0, z);
+                writes.push(FeatureWrite { position: floor, id: BlockIds::StoneSlab, replace_solid: true });
+
+                let is_wall = x.abs() == half_size || z.abs() == half_size;
+                if is_wall {
+                    // Walls crumble to a random height, with 0 leaving gaps to enter through
+                    let height = (self.noise.hash(floor.x, floor.y, floor.z) % 4) as i32;
+                    for y in 1..=height {
+                        writes.push(FeatureWrite { position: floor + y * IVec3::Y, id: BlockIds::StoneBlocks, replace_solid: false });
+                    }
+                } else {
+                    // Clear the interior, in case the ground rises within the ruin
+                    for y in 1..=2 {
+                        writes.push(FeatureWrite { position: floor + y * IVec3::Y, id: BlockIds::Air, replace_solid: true });
+                    }
+                }
+            }
+        }
+        writes
+    }
+
+    /// Searches one seeded column per spawn cell for positions with solid ground and two voxels of headroom
+    fn find_spawn_points(&self, vorld: &Vorld, min_column: IVec2, max_column: IVec2) -> SpawnPoints {
+        let mut spawn_points = SpawnPoints::default();
+        let (min_key, max_key) = match vorld.get_chunk_bounds() {
+            Some(bounds) => bounds,
+            None => return spawn_points,
+        };
+        let min_y = min_key.y * CHUNK_SIZE_I32;
+        let max_y = (max_key.y + 1) * CHUNK_SIZE_I32;
+        let is_solid = |position: IVec3| get_block_properties(vorld.get_voxel(position.x, position.y, position.z)).is_solid;
+
+        let cell_size = self.config.spawn_cell_size;
+        let mut z = min_column.y;
+        while z <= max_column.y {
+            let mut x = min_column.x;
+            while x <= max_column.x {
+                let column = IVec2::new(x, z) + IVec2::new(
+                    (self.noise.hash(x, 5, z) % cell_size as u32) as i32,
+                    (self.noise.hash(x, 6, z) % cell_size as u32) as i32,
+                );
+                if column.x <= max_column.x && column.y <= max_column.y {
+                    let mut is_open_sky = true;
+                    for y in (min_y + 1..max_y).rev() {
+                        let position = IVec3::new(column.x, y, column.y);
+                        if is_solid(position) {
+                            is_open_sky = false;
+                            continue;
+                        }
+                        let ground = position - IVec3::Y;
+                        let ground_id = vorld.get_voxel(ground.x, ground.y, ground.z);
+                        if !is_solid(ground) || is_solid(position + IVec3::Y) || ground_id == BlockIds::Leaves as u8 {
+                            continue;
+                        }
+
+                        let spawn_point = position.as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+                        if is_open_sky {
+                            spawn_points.player.push(spawn_point);
+                        }
+                        spawn_points.npc.push(spawn_point);
+                    }
+                }
+                x += cell_size;
+            }
+            z += cell_size;
+        }
+        spawn_points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_spawn_points_with_heads_underwater() {
+        let mut spawn_points = SpawnPoints {
+            player: vec![Vec3::new(0.5, 4.0, 0.5), Vec3::new(0.5, 2.0, 0.5)],
+            npc: vec![Vec3::new(0.5, 3.25, 0.5), Vec3::new(0.5, 3.0, 0.5), Vec3::new(0.5, -8.0, 0.5)],
+        };
+        spawn_points.remove_underwater(5.0);
+        assert_eq!(spawn_points.player, vec![Vec3::new(0.5, 4.0, 0.5)]);
+        assert_eq!(spawn_points.npc, vec![Vec3::new(0.5, 3.25, 0.5)]);
+    }
+}
@@ -1,3 +1,5 @@
+use bevy::prelude::IVec3;
+
 #[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
 pub enum Direction {
     /// Positive Z
@@ -12,4 +14,37 @@ pub enum Direction {
     Right = 4,
     /// Negative X
     Left = 5,
-}
\ No newline at end of file
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::Forward,
+        Direction::Back,
+        Direction::Up,
+        Direction::Down,
+        Direction::Right,
+        Direction::Left,
+    ];
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Forward => Direction::Back,
+            Direction::Back => Direction::Forward,
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Right => Direction::Left,
+            Direction::Left => Direction::Right,
+        }
+    }
+
+    pub fn offset(self) -> IVec3 {
+        match self {
+            Direction::Forward => IVec3::Z,
+            Direction::Back => IVec3::NEG_Z,
+            Direction::Up => IVec3::Y,
+            Direction::Down => IVec3::NEG_Y,
+            Direction::Right => IVec3::X,
+            Direction::Left => IVec3::NEG_X,
+        }
+    }
+}
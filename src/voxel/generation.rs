@@ -0,0 +1,271 @@
+use bevy::prelude::IVec3;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+use super::block_ids::*;
+use super::chunk::*;
+use super::noise::Noise;
+use super::world::Vorld;
+
+/// Block which replaces stone where its noise exceeds threshold within a band of heights
+pub struct OreVein {
+    pub id: BlockIds,
+    pub min_y: i32,
+    pub max_y: i32,
+    /// Size of the noise features in voxels, larger is bigger veins
+    pub scale: f32,
+    /// Noise value above which ore is placed, closer to 1 is rarer
+    pub threshold: f32,
+}
+
+pub struct TerrainConfig {
+    /// Average height of the surface
+    pub base_height: f32,
+    /// Height of the water surface, columns whose ground is below it are flooded, see water
+    pub sea_level: f32,
+    /// Maximum distance the surface rises or falls from base_height
+    pub height_amplitude: f32,
+    /// Size of hills in voxels
+    pub height_scale: f32,
+    /// Strength of the 3D noise added to the surface density, makes overhangs and arches
+    pub overhang_amplitude: f32,
+    pub overhang_scale: f32,
+    /// Voxels at or below this height are always bedrock
+    pub bedrock_y: i32,
+    /// Large open caverns are carved where cheese noise exceeds this threshold
+    pub cheese_threshold: f32,
+    pub cheese_scale: f32,
+    /// Cheese caves are kept at least this far below the surface
+    pub cheese_surface_margin: f32,
+    /// Winding tunnels are carved where two noise fields are both within this distance of zero
+    pub spaghetti_width: f32,
+    pub spaghetti_scale: f32,
+    /// Depth of soil beneath grass
+    pub soil_depth: i32,
+    pub ores: Vec<OreVein>,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            base_height: 8.0,
+            sea_level: 6.0,
+            height_amplitude: 10.0,
+            height_scale: 64.0,
+            overhang_amplitude: 6.0,
+            overhang_scale: 24.0,
+            bedrock_y: -48,
+            cheese_threshold: 0.3,
+            cheese_scale: 40.0,
+            cheese_surface_margin: 8.0,
+            spaghetti_width: 0.07,
+            spaghetti_scale: 28.0,
+            soil_depth: 3,
+            ores: vec![
+                OreVein { id: BlockIds::CoalOre, min_y: -40, max_y: 16, scale: 6.0, threshold: 0.45 },
+                OreVein { id: BlockIds::IronOre, min_y: -48, max_y: -8, scale: 5.0, threshold: 0.5 },
+            ],
+        }
+    }
+}
+
+/// Density function terrain, each chunk depends only on the seed and its key so chunks can be generated in any order or in parallel
+pub struct TerrainGenerator {
+    pub config: TerrainConfig,
+    height_noise: Noise,
+    overhang_noise: Noise,
+    cheese_noise: Noise,
+    spaghetti_noise: [Noise; 2],
+    ore_noise: Noise,
+    bedrock_noise: Noise,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u32, config: TerrainConfig) -> Self {
+        let noise = Noise::new(seed);
+        Self {
+            config,
+            height_noise: noise.derive(1),
+            overhang_noise: noise.derive(2),
+            cheese_noise: noise.derive(3),
+            spaghetti_noise: [noise.derive(4), noise.derive(5)],
+            ore_noise: noise.derive(6),
+            bedrock_noise: noise.derive(7),
+        }
+    }
+
+    pub fn get_surface_height(&self, x: i32, z: i32) -> f32 {
+        let scale = self.config.height_scale;
+        self.config.base_height
+            + self.config.height_amplitude * self.height_noise.fractal_2d(x as f32 / scale, z as f32 / scale, 3)
+    }
+
+    /// Whether the voxel is solid before block types are chosen
+    fn is_solid(&self, x: i32, y: i32, z: i32, surface_height: f32) -> bool {
+        let config = &self.config;
+        if y <= config.bedrock_y {
+            return true;
+        }
+
+        let (fx, fy, fz) = (x as f32, y as f32, z as f32);
+        let overhang_scale = config.overhang_scale;
+        let density = surface_height - fy
+            + config.overhang_amplitude * self.overhang_noise.sample_3d(fx / overhang_scale, fy / overhang_scale, fz / overhang_scale);
+        if density <= 0.0 {
+            return false;
+        }
+
+        // Keep a few layers above the bedrock uncarved so the floor is never exposed to the void
+        if y <= config.bedrock_y + 3 {
+            return true;
+        }
+
+        // Squash cheese caves vertically so they form wide caverns rather than shafts
+        let cheese_scale = config.cheese_scale;
+        let is_cheese_cave = fy < surface_height - config.cheese_surface_margin
+            && self.cheese_noise.sample_3d(fx / cheese_scale, 2.0 * fy / cheese_scale, fz / cheese_scale) > config.cheese_threshold;
+        if is_cheese_cave {
+            return false;
+        }
+
+        let spaghetti_scale = config.spaghetti_scale;
+        let is_spaghetti_cave = self.spaghetti_noise.iter().all(|noise| {
+            noise.sample_3d(fx / spaghetti_scale, fy / spaghetti_scale, fz / spaghetti_scale).abs() < config.spaghetti_width
+        });
+        !is_spaghetti_cave
+    }
+
+    fn get_ore(&self, x: i32, y: i32, z: i32) -> Option<BlockIds> {
+        self.config.ores.iter().enumerate().find_map(|(i, ore)| {
+            if y < ore.min_y || y > ore.max_y {
+                return None;
+            }
+            let noise = self.ore_noise.derive(i as u32);
+            let value = noise.sample_3d(x as f32 / ore.scale, y as f32 / ore.scale, z as f32 / ore.scale);
+            (value > ore.threshold).then_some(ore.id)
+        })
+    }
+
+    pub fn generate_chunk(&self, key: IVec3) -> Chunk {
+        let config = &self.config;
+        let origin = key * CHUNK_SIZE_I32;
+        let mut voxels = [BlockIds::Air as u8; CHUNK_ARRAY_SIZE];
+        // Solidity of the column from the bottom of the chunk up past its top, so surface blocks can look above themselves
+        let column_height = CHUNK_SIZE + config.soil_depth as usize + 1;
+        let mut column = vec![false; column_height];
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let (world_x, world_z) = (origin.x + x as i32, origin.z + z as i32);
+                let surface_height = self.get_surface_height(world_x, world_z);
+                for (i, solid) in column.iter_mut().enumerate() {
+                    *solid = self.is_solid(world_x, origin.y + i as i32, world_z, surface_height);
+                }
+
+                for y in 0..CHUNK_SIZE {
+                    if !column[y] {
+                        continue;
+                    }
+                    let world_y = origin.y + y as i32;
+                    let depth = (1..=config.soil_depth as usize + 1).take_while(|above| column[y + above]).count() as i32;
+                    let is_near_surface = world_y as f32 >= surface_height - (config.soil_depth + 2) as f32;
+
+                    let is_bedrock = world_y <= config.bedrock_y
+                        || (world_y == config.bedrock_y + 1 && self.bedrock_noise.hash(world_x, world_y, world_z) & 1 == 0);
+                    let id = if is_bedrock {
+                        BlockIds::Bedrock
+                    } else if is_near_surface && depth == 0 {
+                        BlockIds::Grass
+                    } else if is_near_surface && depth <= config.soil_depth {
+                        BlockIds::Soil
+                    } else {
+                        self.get_ore(world_x, world_y, world_z).unwrap_or(BlockIds::Stone)
+                    };
+                    voxels[x + CHUNK_SIZE * z + CHUNK_SIZE * CHUNK_SIZE * y] = id as u8;
+                }
+            }
+        }
+
+        Chunk::from_array(key, voxels)
+    }
+
+    /// Generates every chunk between min_key and max_key inclusive in parallel, chunks which are entirely air are left out
+    pub fn generate(&self, min_key: IVec3, max_key: IVec3) -> Vorld {
+        let mut keys = Vec::new();
+        for y in min_key.y..=max_key.y {
+            for z in min_key.z..=max_key.z {
+                for x in min_key.x..=max_key.x {
+                    keys.push(IVec3::new(x, y, z));
+                }
+            }
+        }
+
+        let chunks = ComputeTaskPool::init(TaskPool::default).scope(|scope| {
+            for key in keys.iter() {
+                scope.spawn(async move { self.generate_chunk(*key) });
+            }
+        });
+
+        let mut vorld = Vorld::new();
+        for chunk in chunks {
+            if chunk.get_uniform_id() != Some(BlockIds::Air as u8) {
+                vorld.insert_chunk(chunk);
+            }
+        }
+        vorld
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::IVec2;
+    use crate::voxel::decoration::{DecorationConfig, Decorator};
+
+    const MIN_KEY: IVec3 = IVec3::new(-2, -1, -2);
+    const MAX_KEY: IVec3 = IVec3::new(1, 1, 1);
+
+    fn assert_same_vorld(a: &Vorld, b: &Vorld) {
+        assert_eq!(a.chunks.len(), b.chunks.len());
+        for (key, chunk) in a.chunks.iter() {
+            let other = b.chunks.get(key).unwrap_or_else(|| panic!("chunk {} missing", key));
+            assert!(chunk.to_array() == other.to_array(), "chunk {} differs", key);
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_same_chunks() {
+        let generator = TerrainGenerator::new(7, TerrainConfig::default());
+        let first = generator.generate(MIN_KEY, MAX_KEY);
+        let second = TerrainGenerator::new(7, TerrainConfig::default()).generate(MIN_KEY, MAX_KEY);
+        assert!(!first.chunks.is_empty());
+        assert_same_vorld(&first, &second);
+
+        // Generating a chunk on its own gives the same voxels as generating it in parallel with its neighbours
+        for (key, chunk) in first.chunks.iter() {
+            assert!(generator.generate_chunk(*key).to_array() == chunk.to_array(), "chunk {} differs", key);
+        }
+
+        let other_seed = TerrainGenerator::new(8, TerrainConfig::default()).generate(MIN_KEY, MAX_KEY);
+        assert!(first.chunks.iter().any(|(key, chunk)| {
+            other_seed.chunks.get(key).is_none_or(|other| other.to_array() != chunk.to_array())
+        }));
+    }
+
+    #[test]
+    fn same_seed_decorates_same_features() {
+        let decorate = || {
+            let mut vorld = TerrainGenerator::new(7, TerrainConfig::default()).generate(MIN_KEY, MAX_KEY);
+            let decorator = Decorator::new(7, DecorationConfig::default());
+            let spawn_points = decorator.decorate(&mut vorld, IVec2::splat(-24), IVec2::splat(23));
+            (vorld, spawn_points)
+        };
+        let (first, first_spawn_points) = decorate();
+        let (second, second_spawn_points) = decorate();
+        assert_same_vorld(&first, &second);
+        let has_trees = first.voxels_in_aabb(IVec3::new(-24, -16, -24), IVec3::new(23, 31, 23)).any(|(_, id)| id == BlockIds::Wood as u8);
+        assert!(has_trees);
+        assert!(!first_spawn_points.npc.is_empty());
+        assert_eq!(first_spawn_points.player, second_spawn_points.player);
+        assert_eq!(first_spawn_points.npc, second_spawn_points.npc);
+    }
+}
@@ -0,0 +1,155 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    render::texture::ImageSampler,
+};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use super::block_ids::*;
+use super::world::Vorld;
+
+/// Heights at which map shading is darkest and brightest, heights outside are clamped
+const SHADE_MIN_Y: i32 = -16;
+const SHADE_MAX_Y: i32 = 32;
+
+/// Colour of the block when seen from above on the map
+pub fn get_map_colour(id: u8) -> [u8; 3] {
+    match id {
+        id if id == BlockIds::Grass as u8 => [96, 140, 60],
+        id if id == BlockIds::Soil as u8 => [120, 85, 55],
+        id if id == BlockIds::Stone as u8 => [125, 125, 125],
+        id if id == BlockIds::StoneSlab as u8 => [150, 150, 150],
+        id if id == BlockIds::StoneBlocks as u8 => [110, 110, 115],
+        id if id == BlockIds::Wood as u8 => [100, 75, 45],
+        id if id == BlockIds::Planks as u8 => [170, 135, 85],
+        id if id == BlockIds::Rink as u8 => [200, 220, 235],
+        id if id == BlockIds::Leaves as u8 => [60, 110, 40],
+        id if id == BlockIds::DoorClosed as u8 || id == BlockIds::DoorOpen as u8 => [140, 100, 60],
+        id if id == BlockIds::Ladder as u8 => [150, 110, 65],
+        id if id == BlockIds::Bedrock as u8 => [40, 40, 40],
+        id if id == BlockIds::CoalOre as u8 => [70, 70, 70],
+        id if id == BlockIds::IronOre as u8 => [160, 130, 110],
+        id if id == BlockIds::Lava as u8 => [230, 100, 20],
+        id if id == BlockIds::Screen as u8 => [30, 110, 90],
+        _ => [255, 0, 255],
+    }
+}
+
+/// Renders the columns between min_column and max_column inclusive as seen from above, one pixel per column
+/// The first row of the image is the column with the lowest z, columns with nothing solid in them are transparent
+pub fn render_top_down(vorld: &Vorld, min_column: IVec2, max_column: IVec2) -> Image {
+    let size = (max_column - min_column + IVec2::ONE).max(IVec2::ONE);
+    let (width, height) = (size.x as usize, size.y as usize);
+
+    // Includes the row before the first, so the first row can be shaded against it
+    let tops: Vec<Option<(i32, u8)>> = (-1..height as i32)
+        .flat_map(|z| (0..width as i32).map(move |x| min_column + IVec2::new(x, z)))
+        .map(|column| {
+            vorld.get_highest_solid_in_column(column.x, column.y)
+                .map(|y| (y, vorld.get_voxel(column.x, y, column.y)))
+        })
+        .collect();
+
+    let mut data = vec![0; 4 * width * height];
+    for z in 0..height {
+        for x in 0..width {
+            let (y, id) = match tops[(z + 1) * width + x] {
+                Some(top) => top,
+                None => continue,
+            };
+            let height_fraction = ((y - SHADE_MIN_Y) as f32 / (SHADE_MAX_Y - SHADE_MIN_Y) as f32).clamp(0.0, 1.0);
+            let mut shade = 0.6 + 0.4 * height_fraction;
+            // Lit from the low z side, so steps up are brighter and steps down darker, which picks out edges on flat colours
+            if let Some((previous_y, _)) = tops[z * width + x] {
+                shade *= match y.cmp(&previous_y) {
+                    Ordering::Greater => 1.15,
+                    Ordering::Less => 0.85,
+                    Ordering::Equal => 1.0,
+                };
+            }
+
+            let index = 4 * (z * width + x);
+            let pixel = &mut data[index..index + 4];
+            for (channel, colour) in pixel.iter_mut().zip(get_map_colour(id)) {
+                *channel = (colour as f32 * shade).min(255.0) as u8;
+            }
+            pixel[3] = 255;
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d { width: width as u32, height: height as u32, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    // Keep the columns crisp when the map is scaled up
+    image.sampler_descriptor = ImageSampler::nearest();
+    image
+}
+
+/// Writes an image made by render_top_down to a PNG file
+pub fn save_png(image: &Image, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    let size = image.texture_descriptor.size;
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, size.width, size.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&image.data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three by two columns, plus a column in the row before the first to shade against
+    fn build_map_vorld() -> Vorld {
+        let mut vorld = Vorld::new();
+        vorld.add_voxel(BlockIds::Stone as u8, 0, 5, -1);
+        vorld.add_voxel(BlockIds::Stone as u8, 0, 0, 0);
+        vorld.add_voxel(BlockIds::Stone as u8, 0, -3, 0);
+        vorld.add_voxel(BlockIds::Grass as u8, 1, 2, 0);
+        vorld.add_voxel(BlockIds::Stone as u8, 0, 0, 1);
+        vorld.add_voxel(BlockIds::Planks as u8, 1, -16, 1);
+        vorld.add_voxel(BlockIds::Leaves as u8, 2, 32, 1);
+        // Not solid, so the column is left empty
+        vorld.add_voxel(BlockIds::Ladder as u8, 2, 4, 0);
+        vorld
+    }
+
+    const EXPECTED: [u8; 24] = [
+        // Stone at y 0 below a step down from y 5, grass at y 2, empty
+        77, 77, 77, 255,   72, 105, 45, 255,   0, 0, 0, 0,
+        // Stone level with the row before, planks at the lowest shade below a step down, leaves at full brightness
+        91, 91, 91, 255,   86, 68, 43, 255,    60, 110, 40, 255,
+    ];
+
+    #[test]
+    fn renders_top_down_colours_and_shading() {
+        let image = render_top_down(&build_map_vorld(), IVec2::new(0, 0), IVec2::new(2, 1));
+        assert_eq!(image.texture_descriptor.size.width, 3);
+        assert_eq!(image.texture_descriptor.size.height, 2);
+        assert_eq!(image.data, EXPECTED);
+    }
+
+    #[test]
+    fn saved_png_matches_image() {
+        let image = render_top_down(&build_map_vorld(), IVec2::new(0, 0), IVec2::new(2, 1));
+        let path = std::env::temp_dir().join(format!("rusty_vorld_map_test_{}.png", std::process::id()));
+        save_png(&image, &path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(&data[..info.buffer_size()], EXPECTED);
+    }
+}
@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy::utils::Instant;
+use bevy_rapier3d::prelude::*;
+use futures_lite::future;
+use std::collections::{HashMap, HashSet};
+
+use super::atlas_loader;
+use super::chunk::*;
+use super::world::Vorld;
+use super::{ChunkMesh, VoxelConfig};
+use crate::mesher;
+use crate::named_collision_groups::*;
+
+/// Chunks waiting to be meshed and the meshing tasks currently running
+pub struct MeshingQueue {
+    /// Maximum number of meshing tasks allowed to run at once
+    pub max_tasks_in_flight: usize,
+    pending: HashSet<IVec3>,
+    in_flight: HashMap<IVec3, Entity>,
+    mesh_entities: HashMap<IVec3, Vec<Entity>>,
+}
+
+impl MeshingQueue {
+    pub fn new(max_tasks_in_flight: usize) -> Self {
+        Self {
+            max_tasks_in_flight,
+            pending: HashSet::new(),
+            in_flight: HashMap::new(),
+            mesh_entities: HashMap::new(),
+        }
+    }
+}
+
+/// Running totals and current sizes of the meshing queue, for tuning max_tasks_in_flight
+#[derive(Default, Debug)]
+pub struct MeshingQueueMetrics {
+    pub pending: usize,
+    pub in_flight: usize,
+    pub dispatched: u32,
+    pub completed: u32,
+    /// Tasks dropped because their chunk was modified again before the mesh returned
+    pub cancelled: u32,
+    /// Time between dispatch and the result being picked up, in seconds
+    pub last_task_duration: f32,
+    pub max_task_duration: f32,
+}
+
+#[derive(Component)]
+struct ComputeChunkMeshes {
+    dispatched_at: Instant,
+    task: Task<(IVec3, Vec<(u32, Mesh)>)>,
+}
+
+pub fn init(app: &mut App) {
+    app.insert_resource(MeshingQueue::new(8))
+        .insert_resource(MeshingQueueMetrics::default())
+        .add_system(queue_modified_chunks)
+        .add_system(dispatch_meshing_tasks.after(queue_modified_chunks))
+        .add_system(handle_meshing_tasks.after(dispatch_meshing_tasks));
+}
+
+fn queue_modified_chunks(
+    mut commands: Commands,
+    mut vorld: ResMut<Vorld>,
+    mut queue: ResMut<MeshingQueue>,
+    mut metrics: ResMut<MeshingQueueMetrics>,
+) {
+    if !vorld.has_modified_chunks() {
+        return;
+    }
+
+    for key in vorld.take_modified_chunks() {
+        if let Some(task_entity) = queue.in_flight.remove(&key) {
+            // Dropping the task cancels it, the chunk is re-queued below with its latest data
+            commands.entity(task_entity).despawn();
+            metrics.cancelled += 1;
+        }
+        queue.pending.insert(key);
+    }
+}
+
+fn dispatch_meshing_tasks(
+    mut commands: Commands,
+    vorld: Res<Vorld>,
+    voxel_config: Res<VoxelConfig>,
+    mut queue: ResMut<MeshingQueue>,
+    mut metrics: ResMut<MeshingQueueMetrics>,
+    camera_query: Query<&GlobalTransform, With<Camera3d>>,
+) {
+    let available = queue.max_tasks_in_flight.saturating_sub(queue.in_flight.len());
+    if available > 0 && !queue.pending.is_empty() {
+        let camera_position = camera_query.iter().next()
+            .map(|transform| transform.translation())
+            .unwrap_or(Vec3::ZERO);
+
+        let mut by_distance: Vec<(f32, IVec3)> = queue.pending.iter()
+            .map(|key| ((key.as_vec3() + Vec3::splat(0.5)) * CHUNK_SIZE_F32, *key))
+            .map(|(center, key)| (center.distance_squared(camera_position), key))
+            .collect();
+        by_distance.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let thread_pool = AsyncComputeTaskPool::get();
+        let look_up = voxel_config.id_to_tile;
+        let tint_look_up = voxel_config.id_to_tint;
+
+        for (_, key) in by_distance.into_iter().take(available) {
+            queue.pending.remove(&key);
+            if let Some(slice) = vorld.get_slice_for_chunk(&key) {
+                let task = thread_pool.spawn(async move {
+                    (
+                        slice.chunk.indices,
+                        mesher::build_chunk_meshes(slice, look_up, tint_look_up),
+                    )
+                });
+                let task_entity = commands.spawn().insert(ComputeChunkMeshes {
+                    dispatched_at: Instant::now(),
+                    task,
+                }).id();
+                queue.in_flight.insert(key, task_entity);
+                metrics.dispatched += 1;
+            } else if let Some(previous_entities) = queue.mesh_entities.remove(&key) {
+                // Chunk has been removed
+                for previous_entity in previous_entities {
+                    commands.entity(previous_entity).despawn();
+                }
+            }
+        }
+    }
+
+    metrics.pending = queue.pending.len();
+    metrics.in_flight = queue.in_flight.len();
+}
+
+fn handle_meshing_tasks(
+    mut commands: Commands,
+    mut meshing_tasks: Query<(Entity, &mut ComputeChunkMeshes)>,
+    atlas: Res<atlas_loader::AtlasTexture>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut queue: ResMut<MeshingQueue>,
+    mut metrics: ResMut<MeshingQueueMetrics>,
+) {
+    if !atlas.has_materials() {
+        // Leave finished tasks until the atlas config has loaded
+        return;
+    }
+
+    for (entity, mut compute) in meshing_tasks.iter_mut() {
+        if let Some((key, mut tile_meshes)) = future::block_on(future::poll_once(&mut compute.task)) {
+            if queue.in_flight.get(&key) != Some(&entity) {
+                // Cancelled this frame, entity is already being despawned
+                continue;
+            }
+            queue.in_flight.remove(&key);
+            commands.entity(entity).despawn();
+
+            let task_duration = compute.dispatched_at.elapsed().as_secs_f32();
+            metrics.completed += 1;
+            metrics.last_task_duration = task_duration;
+            metrics.max_task_duration = metrics.max_task_duration.max(task_duration);
+
+            // Replace any previous meshes for this chunk
+            if let Some(previous_entities) = queue.mesh_entities.remove(&key) {
+                for previous_entity in previous_entities {
+                    commands.entity(previous_entity).despawn();
+                }
+            }
+
+            let mut mesh_entities = Vec::new();
+            while let Some((tile_id, mesh)) = tile_meshes.pop() {
+                let mut entity_commands = commands.spawn();
+                if let Some(collider) =
+                    Collider::from_bevy_mesh(&mesh, &ComputedColliderShape::TriMesh)
+                {
+                    entity_commands
+                        .insert(collider)
+                        .insert(CollisionGroups::new(
+                            NamedCollisionGroups::Terrain as u32,
+                            NamedCollisionGroups::Everything as u32,
+                        ));
+                } else {
+                    error!("Unable to generate mesh collider");
+                }
+                entity_commands.insert(ChunkMesh { key }).insert_bundle(MaterialMeshBundle {
+                    mesh: meshes.add(mesh),
+                    material: atlas.materials[&tile_id].clone(),
+                    transform: Transform::from_xyz(
+                        key.x as f32 * CHUNK_SIZE_F32,
+                        key.y as f32 * CHUNK_SIZE_F32,
+                        key.z as f32 * CHUNK_SIZE_F32,
+                    ),
+                    ..default()
+                });
+                mesh_entities.push(entity_commands.id());
+            }
+            queue.mesh_entities.insert(key, mesh_entities);
+        }
+    }
+    metrics.in_flight = queue.in_flight.len();
+}
@@ -1,29 +1,41 @@
-use super::mesher;
-use super::named_collision_groups::*;
 use bevy::prelude::*;
-use bevy::tasks::{AsyncComputeTaskPool, Task};
-use bevy_rapier3d::prelude::*;
-use futures_lite::future;
-use std::collections::HashMap;
 
 pub mod atlas_loader;
+pub mod biome;
+pub mod block_entity;
 pub mod block_ids;
 pub mod chunk;
+pub mod collision;
+pub mod damage;
+pub mod decoration;
 pub mod direction;
+pub mod generation;
+pub mod map;
+pub mod meshing;
+pub mod noise;
+pub mod persistence;
+pub mod visibility;
 pub mod world;
 
 pub mod prelude {
     pub use crate::voxel::block_ids::*;
     pub use crate::voxel::chunk::*;
+    #[allow(unused_imports)]
     pub use crate::voxel::direction::*;
     pub use crate::voxel::world::*;
 }
+use biome::{Climate, TintMode};
+use decoration::{DecorationConfig, Decorator, SpawnPoints};
+use generation::{TerrainConfig, TerrainGenerator};
+use direction::Direction;
 use prelude::*;
 
 pub struct VoxelConfig {
     /// indexed on voxel id (0-255) and then direction (0-5) returns tile id (u32)
     /// NOTE: direction is from the perspective of the voxel, not the observer (i.e. forward not front or perhaps not "left as I look at it" if front is the forward direction)
     pub id_to_tile: [[u32; 6]; 256],
+    /// indexed on voxel id and then direction, as id_to_tile, returns how the face is vertex coloured
+    pub id_to_tint: [[TintMode; 6]; 256],
 }
 
 pub struct VoxelPlugin;
@@ -31,6 +43,10 @@ pub struct VoxelPlugin;
 impl Plugin for VoxelPlugin {
     fn build(&self, app: &mut App) {
         atlas_loader::init(app);
+        visibility::init(app);
+        block_entity::init(app);
+        damage::init(app);
+        persistence::init(app);
         let mut look_up = [[0; 6]; 256];
         look_up[BlockIds::Grass as usize] = [1, 1, 0, 2, 1, 1];
         look_up[BlockIds::Soil as usize] = [2, 2, 2, 2, 2, 2];
@@ -41,27 +57,103 @@ impl Plugin for VoxelPlugin {
         look_up[BlockIds::Planks as usize] = [10, 10, 10, 10, 10, 10];
         look_up[BlockIds::Debug as usize] = [17, 18, 15, 16, 20, 19];
         look_up[BlockIds::Rink as usize] = [21, 21, 21, 21, 21, 21];
+        look_up[BlockIds::Leaves as usize] = [11, 11, 11, 11, 11, 11];
+        look_up[BlockIds::Bedrock as usize] = [27, 27, 27, 27, 27, 27];
+        look_up[BlockIds::CoalOre as usize] = [28, 28, 28, 28, 28, 28];
+        look_up[BlockIds::IronOre as usize] = [29, 29, 29, 29, 29, 29];
+        look_up[BlockIds::Lava as usize] = [32, 32, 32, 32, 32, 32];
+        look_up[BlockIds::Screen as usize] = [35, 35, 35, 35, 35, 35];
+        let mut tint_look_up = [[TintMode::None; 6]; 256];
+        tint_look_up[BlockIds::Grass as usize][Direction::Up as usize] = TintMode::BiomeGrass;
+        tint_look_up[BlockIds::Leaves as usize] = [TintMode::BiomeFoliage; 6];
         app.insert_resource(VoxelConfig {
             id_to_tile: look_up,
+            id_to_tint: tint_look_up,
         });
-        app.add_startup_system(setup);
-        app.add_system(handle_meshing_tasks);
+        app.init_resource::<StartupVorld>();
+        // Pre-startup so the vorld and spawn points are available to other startup systems
+        app.add_startup_system_to_stage(StartupStage::PreStartup, setup);
+        meshing::init(app);
     }
 }
 
+/// Marks a mesh entity as belonging to the chunk with the given key
 #[derive(Component)]
-struct ComputeChunkMeshes(Task<(IVec3, Vec<(u32, Mesh)>)>);
+pub struct ChunkMesh {
+    pub key: IVec3,
+}
+
+/// Seed the generated vorld uses unless another is given
+const WORLD_SEED: u32 = 1;
+
+/// Which vorld setup builds, the test arena unless another is chosen e.g. with `--vorld generated`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StartupVorld {
+    #[default]
+    Arena,
+    /// Chunk border markers and a tower with a door
+    ChunkTest,
+    /// Pyramid, jumps, arches and the crouch jump corridor
+    ControllerTest,
+    /// Terrain generated and decorated from the seed
+    Generated { seed: u32 },
+}
+
+impl StartupVorld {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "arena" => Some(Self::Arena),
+            "chunk_test" => Some(Self::ChunkTest),
+            "controller_test" => Some(Self::ControllerTest),
+            "generated" => Some(Self::Generated { seed: WORLD_SEED }),
+            _ => None,
+        }
+    }
+
+    /// Height of the water surface, None if the vorld has no water
+    pub fn get_sea_level(&self) -> Option<f32> {
+        match self {
+            Self::Generated { .. } => Some(TerrainConfig::default().sea_level),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self) -> (Vorld, SpawnPoints) {
+        let (vorld, mut spawn_points) = match self {
+            Self::Arena => build_test_arena_vorld(),
+            Self::ChunkTest => (build_chunk_test_vorld(), SpawnPoints::default()),
+            Self::ControllerTest => (build_controller_test_vorld(), SpawnPoints::default()),
+            Self::Generated { seed } => build_generated_vorld(*seed),
+        };
+        if let Some(sea_level) = self.get_sea_level() {
+            spawn_points.remove_underwater(sea_level);
+        }
+        (vorld, spawn_points)
+    }
+}
 
-pub fn setup(mut commands: Commands, voxel_config: Res<VoxelConfig>) {
-    let world = build_test_arena_vorld();
-    async_instantiate_world(&mut commands, &world, &voxel_config)
+pub fn setup(mut commands: Commands, startup_vorld: Res<StartupVorld>) {
+    let (mut world, spawn_points) = startup_vorld.build();
+    info!("Found {} player and {} npc spawn points", spawn_points.player.len(), spawn_points.npc.len());
+    world.compact_chunks();
+    world.update_visibility();
+    let memory_usage = world.get_memory_usage();
+    info!(
+        "Vorld voxel storage: {} bytes for {} chunks ({} uniform, {} palette, {} full), {} bytes if stored densely",
+        memory_usage.voxel_bytes,
+        memory_usage.chunks,
+        memory_usage.uniform_chunks,
+        memory_usage.palette_chunks,
+        memory_usage.full_chunks,
+        memory_usage.dense_voxel_bytes,
+    );
+    // Every chunk starts out modified so the meshing queue picks them all up
+    commands.insert_resource(world);
+    commands.insert_resource(spawn_points);
 }
 
-#[allow(dead_code)]
 fn build_chunk_test_vorld() -> Vorld {
-    let mut world = Vorld {
-        chunks: HashMap::new(),
-    };
+    let mut world = Vorld::new();
 
     for x in -16..32 {
         for z in -16..32 {
@@ -74,6 +166,10 @@ fn build_chunk_test_vorld() -> Vorld {
         }
     }
 
+    // Climate gradient across the grass to show off biome tinting
+    world.climate.set_sample(-1, -1, Climate { temperature: 0.9, humidity: 0.1 });
+    world.climate.set_sample(2, 2, Climate { temperature: 0.2, humidity: 0.9 });
+
     // Palette / tile lookup debug
     // for i in 0..9 {
     //     world.add_voxel(i+1, 2 * i as i32 - 5, 2, 2);
@@ -86,6 +182,9 @@ fn build_chunk_test_vorld() -> Vorld {
                     && !((x == 7 || x == 8) && z == 4 && y <= 2)
                 {
                     world.add_voxel(BlockIds::StoneBlocks as u8, x, y, z);
+                } else if (x == 7 || x == 8) && z == 4 && y <= 2 {
+                    // Starts open so the way into the tower isn't blocked
+                    world.add_voxel(BlockIds::DoorOpen as u8, x, y, z);
                 }
             }
             world.add_voxel(BlockIds::StoneBlocks as u8, x, 18, z);
@@ -95,11 +194,8 @@ fn build_chunk_test_vorld() -> Vorld {
     world
 }
 
-#[allow(dead_code)]
 fn build_controller_test_vorld() -> Vorld {
-    let mut world = Vorld {
-        chunks: HashMap::new(),
-    };
+    let mut world = Vorld::new();
 
     // Grass base!
     for x in -32..32 {
@@ -171,11 +267,32 @@ fn build_controller_test_vorld() -> Vorld {
     world
 }
 
-#[allow(dead_code)]
-fn build_test_arena_vorld() -> Vorld {
-    let mut world = Vorld {
-        chunks: HashMap::new()
-    };
+/// Builds the startup vorld and writes a top down map of all of it to a PNG, without needing an app or a window
+pub fn dump_map(startup_vorld: StartupVorld, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (world, _) = startup_vorld.build();
+    let (min_key, max_key) = world.get_chunk_bounds().ok_or("Vorld is empty")?;
+    let min_column = IVec2::new(min_key.x, min_key.z) * CHUNK_SIZE_I32;
+    let max_column = (IVec2::new(max_key.x, max_key.z) + IVec2::ONE) * CHUNK_SIZE_I32 - IVec2::ONE;
+    map::save_png(&map::render_top_down(&world, min_column, max_column), path)
+}
+
+fn build_generated_vorld(seed: u32) -> (Vorld, SpawnPoints) {
+    let generator = TerrainGenerator::new(seed, TerrainConfig::default());
+    let min_key = IVec3::new(-4, Vorld::get_chunk_key(0, generator.config.bedrock_y, 0).y, -4);
+    let max_key = IVec3::new(3, 2, 3);
+    let mut world = generator.generate(min_key, max_key);
+
+    // Keep features a cell in from the edge so they don't hang over the side of the vorld
+    let decorator = Decorator::new(seed, DecorationConfig::default());
+    let margin = decorator.config.feature_cell_size;
+    let min_column = IVec2::new(min_key.x, min_key.z) * CHUNK_SIZE_I32 + IVec2::splat(margin);
+    let max_column = (IVec2::new(max_key.x, max_key.z) + IVec2::ONE) * CHUNK_SIZE_I32 - IVec2::splat(margin + 1);
+    let spawn_points = decorator.decorate(&mut world, min_column, max_column);
+    (world, spawn_points)
+}
+
+fn build_test_arena_vorld() -> (Vorld, SpawnPoints) {
+    let mut world = Vorld::new();
 
     for z in -32..32 {
         for x in -32..32 {
@@ -188,17 +305,25 @@ fn build_test_arena_vorld() -> Vorld {
         }
     }
 
-    world
+    // Player spawns in each corner and two nearer the middle, npcs line up along the x axis when there are none
+    let spawn_points = SpawnPoints {
+        player: vec![
+            Vec3::new(8.0, 0.0, -8.0),
+            Vec3::new(-24.0, 0.0, -24.0),
+            Vec3::new(24.0, 0.0, -24.0),
+            Vec3::new(-24.0, 0.0, 24.0),
+            Vec3::new(24.0, 0.0, 24.0),
+            Vec3::new(0.0, 0.0, 16.0),
+        ],
+        npc: Vec::new(),
+    };
+    (world, spawn_points)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn fill(world: &mut Vorld, block: u8, width: i32, height: i32, depth: i32, x: i32 , y: i32, z: i32) {
-    for j in 0..height {
-        for k in 0..depth {
-            for i in 0..width {
-                world.add_voxel(block, x + i, y + j, z + k);
-              }
-        }
-    }
+    let min = IVec3::new(x, y, z);
+    world.fill_region(min, min + IVec3::new(width, height, depth) - IVec3::ONE, block);
 }
 
 fn point_in_chunk(v: i32) -> i32 {
@@ -210,63 +335,3 @@ fn point_in_chunk(v: i32) -> i32 {
         v % CHUNK_SIZE_I32 + CHUNK_SIZE_I32
     }
 }
-
-pub fn async_instantiate_world(commands: &mut Commands, world: &Vorld, voxel_config: &VoxelConfig) {
-    let mut chunk_slices = Vec::<VorldSlice>::new();
-    for (key, _) in world.chunks.iter() {
-        if let Some(slice) = world.get_slice_for_chunk(key) {
-            chunk_slices.push(slice);
-        }
-    }
-
-    let thread_pool = AsyncComputeTaskPool::get();
-    let look_up = voxel_config.id_to_tile;
-
-    while let Some(slice) = chunk_slices.pop() {
-        let task = thread_pool.spawn(async move {
-            (
-                slice.chunk.indices,
-                mesher::build_chunk_meshes(slice, look_up),
-            )
-        });
-        commands.spawn().insert(ComputeChunkMeshes(task));
-    }
-}
-
-fn handle_meshing_tasks(
-    mut commands: Commands,
-    mut meshing_tasks: Query<(Entity, &mut ComputeChunkMeshes)>,
-    atlas: Res<atlas_loader::AtlasTexture>,
-    mut meshes: ResMut<Assets<Mesh>>,
-) {
-    for (entity, mut task) in meshing_tasks.iter_mut() {
-        if let Some((key, mut tile_meshes)) = future::block_on(future::poll_once(&mut task.0)) {
-            while let Some((tile_id, mesh)) = tile_meshes.pop() {
-                let mut entity_commands = commands.spawn();
-                if let Some(collider) =
-                    Collider::from_bevy_mesh(&mesh, &ComputedColliderShape::TriMesh)
-                {
-                    entity_commands
-                        .insert(collider)
-                        .insert(CollisionGroups::new(
-                            NamedCollisionGroups::Terrain as u32,
-                            NamedCollisionGroups::Everything as u32,
-                        ));
-                } else {
-                    error!("Unable to generate mesh collider");
-                }
-                entity_commands.insert_bundle(MaterialMeshBundle {
-                    mesh: meshes.add(mesh),
-                    material: atlas.materials[&tile_id].clone(),
-                    transform: Transform::from_xyz(
-                        key.x as f32 * CHUNK_SIZE_F32,
-                        key.y as f32 * CHUNK_SIZE_F32,
-                        key.z as f32 * CHUNK_SIZE_F32,
-                    ),
-                    ..default()
-                });
-            }
-            commands.entity(entity).remove::<ComputeChunkMeshes>();
-        }
-    }
-}
@@ -0,0 +1,87 @@
+/// Seeded gradient noise, samples depend only on the seed and position so any region can be generated independently
+#[derive(Copy, Clone, Debug)]
+pub struct Noise {
+    seed: u32,
+}
+
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0],
+];
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    /// Noise with a seed derived from this one, so layers built from the same seed don't correlate
+    pub fn derive(&self, salt: u32) -> Self {
+        Self::new(Self::new(salt).hash(self.seed as i32, 0, 0))
+    }
+
+    /// Well mixed hash of an integer position
+    pub fn hash(&self, x: i32, y: i32, z: i32) -> u32 {
+        let mut h = self.seed
+            ^ (x as u32).wrapping_mul(0x8da6_b343)
+            ^ (y as u32).wrapping_mul(0xd816_3841)
+            ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x2c1b_3c6d);
+        h ^= h >> 12;
+        h = h.wrapping_mul(0x297a_2d39);
+        h ^= h >> 15;
+        h
+    }
+
+    /// Hash of an integer position mapped to 0 -> 1
+    pub fn hash_unit(&self, x: i32, y: i32, z: i32) -> f32 {
+        (self.hash(x, y, z) >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn gradient_dot(&self, cell: [i32; 3], offset: [f32; 3]) -> f32 {
+        let gradient = GRADIENTS[self.hash(cell[0], cell[1], cell[2]) as usize % GRADIENTS.len()];
+        gradient[0] * offset[0] + gradient[1] * offset[1] + gradient[2] * offset[2]
+    }
+
+    /// Perlin style gradient noise, roughly -1 -> 1 with features one unit apart
+    pub fn sample_3d(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (tx, ty, tz) = (x - x0, y - y0, z - z0);
+        let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+
+        let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            self.gradient_dot([ix + dx, iy + dy, iz + dz], [tx - dx as f32, ty - dy as f32, tz - dz as f32])
+        };
+
+        let (u, v, w) = (fade(tx), fade(ty), fade(tz));
+        lerp(
+            lerp(lerp(corner(0, 0, 0), corner(1, 0, 0), u), lerp(corner(0, 1, 0), corner(1, 1, 0), u), v),
+            lerp(lerp(corner(0, 0, 1), corner(1, 0, 1), u), lerp(corner(0, 1, 1), corner(1, 1, 1), u), v),
+            w,
+        )
+    }
+
+    pub fn sample_2d(&self, x: f32, z: f32) -> f32 {
+        self.sample_3d(x, 0.0, z)
+    }
+
+    /// Sum of octaves of 2D noise, each at double the frequency and half the amplitude of the last, normalised to roughly -1 -> 1
+    pub fn fractal_2d(&self, x: f32, z: f32, octaves: u32) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max = 0.0;
+        for octave in 0..octaves {
+            // Offset each octave so their lattices don't line up at the origin
+            let offset = 17.31 * octave as f32;
+            total += amplitude * self.sample_2d(x * frequency + offset, z * frequency + offset);
+            max += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        total / max
+    }
+}
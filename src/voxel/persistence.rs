@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use super::biome::ClimateMap;
+use super::chunk::*;
+use super::world::Vorld;
+
+pub const SAVE_PATH: &str = "saves/vorld.ron";
+
+/// On disk representation of a Vorld, block entities are not saved as their state is encoded in block ids
+#[derive(Serialize, Deserialize)]
+struct SavedVorld {
+    climate: ClimateMap,
+    chunks: Vec<SavedChunk>,
+}
+
+/// Chunk voxels run length encoded in index order, as (id, count)
+#[derive(Serialize, Deserialize)]
+struct SavedChunk {
+    indices: IVec3,
+    runs: Vec<(u8, u16)>,
+}
+
+impl SavedChunk {
+    fn from_chunk(chunk: &Chunk) -> Self {
+        let mut runs: Vec<(u8, u16)> = Vec::new();
+        for id in chunk.to_array() {
+            match runs.last_mut() {
+                Some((run_id, count)) if *run_id == id => *count += 1,
+                _ => runs.push((id, 1)),
+            }
+        }
+        Self { indices: chunk.indices, runs }
+    }
+
+    fn to_chunk(&self) -> Result<Chunk, String> {
+        let mut voxels = [0; CHUNK_ARRAY_SIZE];
+        let mut i = 0;
+        for (id, count) in self.runs.iter() {
+            let end = i + *count as usize;
+            if end > CHUNK_ARRAY_SIZE {
+                return Err(format!("Chunk {} has more than {} voxels", self.indices, CHUNK_ARRAY_SIZE));
+            }
+            voxels[i..end].fill(*id);
+            i = end;
+        }
+        if i != CHUNK_ARRAY_SIZE {
+            return Err(format!("Chunk {} has {} voxels, expected {}", self.indices, i, CHUNK_ARRAY_SIZE));
+        }
+        Ok(Chunk::from_array(self.indices, voxels))
+    }
+}
+
+pub fn init(app: &mut App) {
+    app.add_system(save_and_load);
+}
+
+pub fn save_vorld(vorld: &Vorld, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut chunks: Vec<SavedChunk> = vorld.chunks.values().map(|chunk| SavedChunk::from_chunk(chunk)).collect();
+    // Stable order so saves of the same vorld are identical
+    chunks.sort_by_key(|chunk| (chunk.indices.x, chunk.indices.y, chunk.indices.z));
+    let saved = SavedVorld { climate: vorld.climate.clone(), chunks };
+
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, ron::ser::to_string(&saved)?)?;
+    Ok(())
+}
+
+/// Replaces the contents of the vorld with the save at path, the vorld is untouched if the save can't be read
+pub fn load_vorld(vorld: &mut Vorld, path: &Path) -> Result<(), Box<dyn Error>> {
+    let saved: SavedVorld = ron::from_str(&fs::read_to_string(path)?)?;
+    let chunks = saved.chunks.iter().map(SavedChunk::to_chunk).collect::<Result<Vec<_>, _>>()?;
+
+    vorld.clear();
+    vorld.climate = saved.climate;
+    for chunk in chunks {
+        vorld.insert_chunk(chunk);
+    }
+    Ok(())
+}
+
+/// F5 to save, F9 to load
+fn save_and_load(keyboard_input: Res<Input<KeyCode>>, mut vorld: ResMut<Vorld>) {
+    let path = Path::new(SAVE_PATH);
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        match save_vorld(&vorld, path) {
+            Ok(()) => info!("Saved vorld to {}", SAVE_PATH),
+            Err(error) => error!("Unable to save vorld to {}: {}", SAVE_PATH, error),
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        match load_vorld(&mut vorld, path) {
+            Ok(()) => info!("Loaded vorld from {}", SAVE_PATH),
+            Err(error) => error!("Unable to load vorld from {}: {}", SAVE_PATH, error),
+        }
+    }
+}
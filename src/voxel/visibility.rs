@@ -0,0 +1,272 @@
+use bevy::prelude::*;
+use std::collections::{HashSet, VecDeque};
+
+use super::block_ids::*;
+use super::chunk::*;
+use super::direction::Direction;
+use super::world::Vorld;
+use super::ChunkMesh;
+
+/// Which faces of a chunk can see each other through air
+/// See https://tomcc.github.io/2014/08/31/visibility-1.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkVisibility {
+    /// indexed on direction of face entered and direction of face exited
+    connections: [[bool; 6]; 6],
+}
+
+impl ChunkVisibility {
+    /// Visibility for a chunk with no solid voxels, every face can see every other face
+    pub fn all() -> Self {
+        Self {
+            connections: [[true; 6]; 6],
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            connections: [[false; 6]; 6],
+        }
+    }
+
+    pub fn is_connected(&self, from: Direction, to: Direction) -> bool {
+        self.connections[from as usize][to as usize]
+    }
+
+    /// Flood fills each region of air in the chunk and connects every pair of faces the region touches
+    /// Blocks which aren't meshed are treated as air, as they don't fully block the view
+    pub fn compute(chunk: &Chunk) -> Self {
+        match chunk.get_uniform_id() {
+            Some(id) if !get_block_properties(id).is_meshed => return Self::all(),
+            Some(_) => return Self::none(),
+            None => { },
+        }
+
+        let voxels = chunk.to_array();
+        let is_clear = |i: usize| !get_block_properties(voxels[i]).is_meshed;
+        let mut result = Self::none();
+        let mut visited = [false; CHUNK_ARRAY_SIZE];
+        let mut stack = Vec::new();
+
+        for i in 0..CHUNK_ARRAY_SIZE {
+            if visited[i] || !is_clear(i) {
+                continue;
+            }
+
+            // Bit mask of faces touched by this region of air, indexed on direction
+            let mut faces: u8 = 0;
+            visited[i] = true;
+            stack.push(i);
+
+            while let Some(index) = stack.pop() {
+                let (x, y, z) = Chunk::get_block_position(index);
+                faces |= Self::get_faces_touched(x, y, z);
+
+                let mut visit = |neighbour: usize| {
+                    if !visited[neighbour] && is_clear(neighbour) {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                };
+                if x != 0 { visit(index - 1); }
+                if x != CHUNK_SIZE - 1 { visit(index + 1); }
+                if z != 0 { visit(index - CHUNK_SIZE); }
+                if z != CHUNK_SIZE - 1 { visit(index + CHUNK_SIZE); }
+                if y != 0 { visit(index - CHUNK_SIZE * CHUNK_SIZE); }
+                if y != CHUNK_SIZE - 1 { visit(index + CHUNK_SIZE * CHUNK_SIZE); }
+            }
+
+            for from in Direction::ALL {
+                for to in Direction::ALL {
+                    if faces & (1 << from as u8) != 0 && faces & (1 << to as u8) != 0 {
+                        result.connections[from as usize][to as usize] = true;
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    fn get_faces_touched(x: usize, y: usize, z: usize) -> u8 {
+        let mut faces = 0;
+        if z == CHUNK_SIZE - 1 { faces |= 1 << Direction::Forward as u8; }
+        if z == 0 { faces |= 1 << Direction::Back as u8; }
+        if y == CHUNK_SIZE - 1 { faces |= 1 << Direction::Up as u8; }
+        if y == 0 { faces |= 1 << Direction::Down as u8; }
+        if x == CHUNK_SIZE - 1 { faces |= 1 << Direction::Right as u8; }
+        if x == 0 { faces |= 1 << Direction::Left as u8; }
+        faces
+    }
+}
+
+pub fn init(app: &mut App) {
+    app.add_system(cull_chunks);
+}
+
+/// Walks the chunk graph out from the camera's chunk, only stepping through faces connected by air
+/// and never doubling back on a direction already travelled, returns the set of chunks which may be visible.
+pub fn find_visible_chunks(vorld: &Vorld, camera_chunk: IVec3) -> HashSet<IVec3> {
+    let mut visible = HashSet::new();
+    let (min, max) = match vorld.get_chunk_bounds() {
+        Some(bounds) => bounds,
+        None => return visible,
+    };
+    // Chunks outside the vorld are empty, allow one layer of them so we can walk around the outside
+    let (min, max) = (min - IVec3::ONE, max + IVec3::ONE);
+    let is_in_bounds = |key: IVec3| key.cmpge(min).all() && key.cmple(max).all();
+
+    if !is_in_bounds(camera_chunk) {
+        // Can't reason about connectivity from outside, everything could be in view
+        visible.extend(vorld.chunks.keys());
+        return visible;
+    }
+
+    // (chunk key, face entered through, bit mask of directions travelled)
+    let mut queue = VecDeque::new();
+    visible.insert(camera_chunk);
+    queue.push_back((camera_chunk, None, 0u8));
+
+    while let Some((key, entered_from, travelled)) = queue.pop_front() {
+        let chunk_visibility = vorld.get_chunk_visibility(&key);
+        for direction in Direction::ALL {
+            if travelled & (1 << direction.opposite() as u8) != 0 {
+                continue;
+            }
+            let neighbour = key + direction.offset();
+            if !is_in_bounds(neighbour) || visible.contains(&neighbour) {
+                continue;
+            }
+            if let Some(from) = entered_from {
+                if !chunk_visibility.is_connected(from, direction) {
+                    continue;
+                }
+            }
+            visible.insert(neighbour);
+            queue.push_back((neighbour, Some(direction.opposite()), travelled | (1 << direction as u8)));
+        }
+    }
+
+    visible
+}
+
+fn cull_chunks(
+    mut vorld: ResMut<Vorld>,
+    camera_query: Query<&GlobalTransform, With<Camera3d>>,
+    mut chunk_query: Query<(&ChunkMesh, &mut Visibility)>,
+) {
+    if vorld.has_stale_visibility() {
+        vorld.update_visibility();
+    }
+
+    if let Some(camera_transform) = camera_query.iter().next() {
+        let camera_chunk = Vorld::get_chunk_key_for_position(camera_transform.translation());
+        let visible_chunks = find_visible_chunks(&vorld, camera_chunk);
+
+        for (chunk_mesh, mut visibility) in chunk_query.iter_mut() {
+            let is_visible = visible_chunks.contains(&chunk_mesh.key);
+            if visibility.is_visible != is_visible {
+                visibility.is_visible = is_visible;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: u8 = BlockIds::Stone as u8;
+    const AIR: u8 = BlockIds::Air as u8;
+
+    /// A row of air chunks from x = 0 to 4 with the given chunk in the middle at x = 2
+    fn build_row_vorld(middle: Chunk) -> Vorld {
+        let mut vorld = Vorld::new();
+        for x in [0, 1, 3, 4] {
+            vorld.insert_chunk(Chunk::new(IVec3::new(x, 0, 0), AIR));
+        }
+        vorld.insert_chunk(middle);
+        vorld.update_visibility();
+        vorld
+    }
+
+    /// Stone chunk with a straight tunnel through it along the x axis
+    fn build_tunnel_chunk(key: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(key, STONE);
+        for x in 0..CHUNK_SIZE {
+            chunk.add_voxel(AIR, x, 8, 8);
+        }
+        chunk
+    }
+
+    #[test]
+    fn open_chunk_connects_every_face() {
+        let chunk = Chunk::new(IVec3::ZERO, AIR);
+        assert_eq!(ChunkVisibility::compute(&chunk), ChunkVisibility::all());
+
+        // A slab of air across the chunk joined by a tunnel along z touches every face as one region
+        let mut chunk = Chunk::new(IVec3::ZERO, STONE);
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                chunk.add_voxel(AIR, x, y, 8);
+            }
+        }
+        for z in 0..CHUNK_SIZE {
+            chunk.add_voxel(AIR, 8, 8, z);
+        }
+        assert_eq!(ChunkVisibility::compute(&chunk), ChunkVisibility::all());
+    }
+
+    #[test]
+    fn open_row_is_all_visible() {
+        let vorld = build_row_vorld(Chunk::new(IVec3::new(2, 0, 0), AIR));
+        let visible = find_visible_chunks(&vorld, IVec3::ZERO);
+        for x in 0..5 {
+            assert!(visible.contains(&IVec3::new(x, 0, 0)), "chunk {} should be visible", x);
+        }
+    }
+
+    #[test]
+    fn sealed_chunk_connects_nothing() {
+        assert_eq!(ChunkVisibility::compute(&Chunk::new(IVec3::ZERO, STONE)), ChunkVisibility::none());
+
+        // A pocket of air which doesn't reach any face
+        let mut chunk = Chunk::new(IVec3::ZERO, STONE);
+        for x in 4..12 {
+            for y in 4..12 {
+                chunk.add_voxel(AIR, x, y, 8);
+            }
+        }
+        assert_eq!(ChunkVisibility::compute(&chunk), ChunkVisibility::none());
+    }
+
+    #[test]
+    fn sealed_chunk_hides_chunks_behind_it() {
+        let vorld = build_row_vorld(Chunk::new(IVec3::new(2, 0, 0), STONE));
+        let visible = find_visible_chunks(&vorld, IVec3::ZERO);
+        // The wall itself can be seen, but nothing behind it
+        assert!(visible.contains(&IVec3::new(1, 0, 0)));
+        assert!(visible.contains(&IVec3::new(2, 0, 0)));
+        assert!(!visible.contains(&IVec3::new(3, 0, 0)));
+        assert!(!visible.contains(&IVec3::new(4, 0, 0)));
+    }
+
+    #[test]
+    fn cave_through_wall_connects_its_faces() {
+        let visibility = ChunkVisibility::compute(&build_tunnel_chunk(IVec3::ZERO));
+        assert!(visibility.is_connected(Direction::Left, Direction::Right));
+        assert!(visibility.is_connected(Direction::Right, Direction::Left));
+        for direction in [Direction::Up, Direction::Down, Direction::Forward, Direction::Back] {
+            assert!(!visibility.is_connected(Direction::Left, direction));
+            assert!(!visibility.is_connected(direction, direction));
+        }
+    }
+
+    #[test]
+    fn cave_through_wall_shows_chunks_behind_it() {
+        let vorld = build_row_vorld(build_tunnel_chunk(IVec3::new(2, 0, 0)));
+        let visible = find_visible_chunks(&vorld, IVec3::ZERO);
+        assert!(visible.contains(&IVec3::new(3, 0, 0)));
+        assert!(visible.contains(&IVec3::new(4, 0, 0)));
+    }
+}
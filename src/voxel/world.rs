@@ -1,15 +1,47 @@
-use bevy::prelude::IVec3;
-use std::collections::HashMap;
+use bevy::prelude::{IVec3, Vec3};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
+use std::sync::Arc;
 use super::chunk::*;
 use super::block_ids::*;
+use super::biome::{Climate, ClimateMap};
+use super::direction::Direction;
+use super::visibility::ChunkVisibility;
 
 #[derive(Clone, Debug)]
 pub struct Vorld {
-    pub chunks: HashMap<IVec3, Chunk>,
+    /// Shared so meshing tasks can hold chunks without copying, writes clone a chunk only if it is in use
+    pub chunks: HashMap<IVec3, Arc<Chunk>>,
+    /// Per column climate, used for biome tinting
+    pub climate: ClimateMap,
+    /// Face to face connectivity per chunk, entries are removed when the chunk changes
+    visibility: HashMap<IVec3, ChunkVisibility>,
+    /// Chunks which need re-meshing, includes neighbours of chunks changed on their border
+    modified_chunks: HashSet<IVec3>,
+    /// Incremented on every write to a chunk, so derived data can tell when it is out of date
+    chunk_revisions: HashMap<IVec3, u32>,
+    /// Sum of the chunk revisions, including those of chunks since removed
+    revision: u32,
+    /// World space positions where a block with a block entity was added or removed
+    block_entity_changes: HashSet<IVec3>,
+    /// Accumulated damage per voxel, entries are removed when the voxel changes or the damage decays away
+    damage: HashMap<IVec3, f32>,
 }
 
 impl Vorld {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            climate: ClimateMap::default(),
+            visibility: HashMap::new(),
+            modified_chunks: HashSet::new(),
+            chunk_revisions: HashMap::new(),
+            revision: 0,
+            block_entity_changes: HashSet::new(),
+            damage: HashMap::new(),
+        }
+    }
+
     /// gets chunk index for a voxel at index v in world space on a given axis
     fn get_chunk_index(v: i32) -> i32 {
         if v >= 0 || v % CHUNK_SIZE_I32 == 0 {
@@ -19,7 +51,7 @@ impl Vorld {
         }
     }
 
-    fn get_chunk_key(x: i32, y: i32, z: i32) -> IVec3 {
+    pub fn get_chunk_key(x: i32, y: i32, z: i32) -> IVec3 {
         IVec3::new(
             Self::get_chunk_index(x),
             Self::get_chunk_index(y),
@@ -27,6 +59,11 @@ impl Vorld {
         )
     }
 
+    pub fn get_chunk_key_for_position(position: Vec3) -> IVec3 {
+        let voxel = position.floor();
+        Self::get_chunk_key(voxel.x as i32, voxel.y as i32, voxel.z as i32)
+    }
+
     fn get_position_in_chunk(chunk_key: IVec3, x: i32, y: i32, z: i32) -> (usize, usize, usize) {
         (
             (x - chunk_key.x * CHUNK_SIZE_I32).try_into().unwrap(),
@@ -36,19 +73,167 @@ impl Vorld {
     }
 
     pub fn add_voxel(&mut self, id: u8, x: i32, y: i32, z: i32) {
+        let previous_id = self.get_voxel(x, y, z);
+        if previous_id != id
+            && (get_block_properties(previous_id).has_block_entity || get_block_properties(id).has_block_entity)
+        {
+            self.block_entity_changes.insert(IVec3::new(x, y, z));
+        }
+        if previous_id != id {
+            self.damage.remove(&IVec3::new(x, y, z));
+        }
         let key = Self::get_chunk_key(x, y, z);
+        let block_indicies = Self::get_position_in_chunk(key, x, y, z);
+        self.visibility.remove(&key);
+        self.mark_modified(key, block_indicies);
+        self.increment_revision(key);
         if let Some(chunk) = self.chunks.get_mut(&key) {
-            let block_indicies = Self::get_position_in_chunk(key, x, y, z);
-            chunk.add_voxel(id, block_indicies.0, block_indicies.1, block_indicies.2);
+            Arc::make_mut(chunk).add_voxel(id, block_indicies.0, block_indicies.1, block_indicies.2);
         } else {
             let mut chunk = Chunk::new(key, BlockIds::Air as u8);
-            let block_indicies = Self::get_position_in_chunk(key, x, y, z);
             chunk.add_voxel(id, block_indicies.0, block_indicies.1, block_indicies.2);
-            self.chunks.insert(key, chunk);
+            self.chunks.insert(key, Arc::new(chunk));
         }
     }
 
-    #[allow(dead_code)]
+    /// Inserts a whole chunk, replacing any existing chunk with the same indices
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        let key = chunk.indices;
+        if let Some(previous) = self.chunks.get(&key).cloned() {
+            self.record_block_entity_voxels(&previous);
+        }
+        self.record_block_entity_voxels(&chunk);
+        self.damage.retain(|position, _| Self::get_chunk_key(position.x, position.y, position.z) != key);
+        self.mark_chunk_and_neighbours_modified(key);
+        self.chunks.insert(key, Arc::new(chunk));
+    }
+
+    /// Removes every chunk, derived data is invalidated as if each voxel had been set to air
+    pub fn clear(&mut self) {
+        self.damage.clear();
+        let chunks = std::mem::take(&mut self.chunks);
+        for (key, chunk) in chunks.iter() {
+            self.record_block_entity_voxels(chunk);
+            self.visibility.remove(key);
+            self.modified_chunks.insert(*key);
+            self.increment_revision(*key);
+        }
+    }
+
+    /// Adds damage to the voxel, replacing it with air once the accumulated damage reaches the block's hardness
+    /// returns true if the voxel was destroyed
+    pub fn damage_voxel(&mut self, position: IVec3, amount: f32) -> bool {
+        let hardness = match get_block_properties(self.get_voxel(position.x, position.y, position.z)).hardness {
+            Some(hardness) => hardness,
+            None => return false,
+        };
+        let damage = self.damage.entry(position).or_insert(0.0);
+        *damage += amount;
+        if *damage >= hardness {
+            self.add_voxel(BlockIds::Air as u8, position.x, position.y, position.z);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn has_damaged_voxels(&self) -> bool {
+        !self.damage.is_empty()
+    }
+
+    /// Damaged voxels with their damage as a fraction of the block's hardness
+    pub fn get_damaged_voxels(&self) -> impl Iterator<Item = (IVec3, f32)> + '_ {
+        self.damage.iter().filter_map(|(position, damage)| {
+            let hardness = get_block_properties(self.get_voxel(position.x, position.y, position.z)).hardness?;
+            Some((*position, damage / hardness))
+        })
+    }
+
+    /// Reduces the damage of every damaged voxel, forgetting voxels once their damage reaches zero
+    pub fn decay_damage(&mut self, amount: f32) {
+        self.damage.retain(|_, damage| {
+            *damage -= amount;
+            *damage > 0.0
+        });
+    }
+
+    fn mark_chunk_and_neighbours_modified(&mut self, key: IVec3) {
+        self.visibility.remove(&key);
+        self.increment_revision(key);
+        self.modified_chunks.insert(key);
+        for direction in Direction::ALL {
+            let neighbour_key = key + direction.offset();
+            if self.chunks.contains_key(&neighbour_key) {
+                self.modified_chunks.insert(neighbour_key);
+            }
+        }
+    }
+
+    fn record_block_entity_voxels(&mut self, chunk: &Chunk) {
+        if chunk.get_uniform_id().is_some_and(|id| !get_block_properties(id).has_block_entity) {
+            return;
+        }
+        let origin = chunk.indices * CHUNK_SIZE_I32;
+        for (i, id) in chunk.to_array().iter().enumerate() {
+            if get_block_properties(*id).has_block_entity {
+                let (x, y, z) = Chunk::get_block_position(i);
+                self.block_entity_changes.insert(origin + IVec3::new(x as i32, y as i32, z as i32));
+            }
+        }
+    }
+
+    fn mark_modified(&mut self, key: IVec3, block_indicies: (usize, usize, usize)) {
+        self.modified_chunks.insert(key);
+        let (x, y, z) = block_indicies;
+        let last = CHUNK_SIZE - 1;
+        let mut neighbours = Vec::new();
+        if x == 0 { neighbours.push(IVec3::NEG_X); }
+        if x == last { neighbours.push(IVec3::X); }
+        if y == 0 { neighbours.push(IVec3::NEG_Y); }
+        if y == last { neighbours.push(IVec3::Y); }
+        if z == 0 { neighbours.push(IVec3::NEG_Z); }
+        if z == last { neighbours.push(IVec3::Z); }
+        for offset in neighbours {
+            let neighbour_key = key + offset;
+            if self.chunks.contains_key(&neighbour_key) {
+                self.modified_chunks.insert(neighbour_key);
+            }
+        }
+    }
+
+    fn increment_revision(&mut self, key: IVec3) {
+        *self.chunk_revisions.entry(key).or_insert(0) += 1;
+        self.revision += 1;
+    }
+
+    /// Number of voxel writes made to the whole vorld, unlike change detection it isn't affected by damage or other metadata
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// Number of writes made to the chunk, 0 if it doesn't exist
+    pub fn get_chunk_revision(&self, chunk_key: &IVec3) -> u32 {
+        self.chunk_revisions.get(chunk_key).copied().unwrap_or(0)
+    }
+
+    pub fn has_modified_chunks(&self) -> bool {
+        !self.modified_chunks.is_empty()
+    }
+
+    /// Returns the keys of all chunks modified since the last call
+    pub fn take_modified_chunks(&mut self) -> HashSet<IVec3> {
+        std::mem::take(&mut self.modified_chunks)
+    }
+
+    pub fn has_block_entity_changes(&self) -> bool {
+        !self.block_entity_changes.is_empty()
+    }
+
+    /// Returns the positions of all voxels which gained, lost or changed block entity since the last call
+    pub fn take_block_entity_changes(&mut self) -> HashSet<IVec3> {
+        std::mem::take(&mut self.block_entity_changes)
+    }
+
     pub fn get_voxel(&self, x: i32, y: i32, z: i32) -> u8 {
         let key = Self::get_chunk_key(x, y, z);
         if let Some(chunk) = self.chunks.get(&key) {
@@ -59,38 +244,429 @@ impl Vorld {
         }
     }
 
+    /// Steps through the voxel grid along the ray, returning the first voxel for which is_hit returns true
+    /// See http://www.cse.yorku.ca/~amana/research/grid.pdf
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32, is_hit: impl Fn(u8) -> bool) -> Option<VoxelRaycastHit> {
+        let direction = direction.normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return None;
+        }
+
+        let mut voxel = origin.floor().as_ivec3();
+        let step = direction.signum().as_ivec3();
+        // Distance along the ray to cross one voxel on each axis, and to the first boundary on each axis
+        let delta = direction.recip().abs();
+        let next_boundary = voxel.as_vec3() + step.max(IVec3::ZERO).as_vec3();
+        let mut t_max = Vec3::select(
+            direction.cmpeq(Vec3::ZERO),
+            Vec3::splat(f32::INFINITY),
+            (next_boundary - origin) / direction,
+        );
+
+        let mut distance = 0.0;
+        let mut normal = IVec3::ZERO;
+        while distance <= max_distance {
+            let id = self.get_voxel(voxel.x, voxel.y, voxel.z);
+            if is_hit(id) {
+                return Some(VoxelRaycastHit { position: voxel, normal, distance, id });
+            }
+
+            if t_max.x < t_max.y && t_max.x < t_max.z {
+                distance = t_max.x;
+                t_max.x += delta.x;
+                voxel.x += step.x;
+                normal = IVec3::new(-step.x, 0, 0);
+            } else if t_max.y < t_max.z {
+                distance = t_max.y;
+                t_max.y += delta.y;
+                voxel.y += step.y;
+                normal = IVec3::new(0, -step.y, 0);
+            } else {
+                distance = t_max.z;
+                t_max.z += delta.z;
+                voxel.z += step.z;
+                normal = IVec3::new(0, 0, -step.z);
+            }
+        }
+        None
+    }
+
+    /// Writes id to every voxel in the region, min and max inclusive, a chunk at a time
+    pub fn fill_region(&mut self, min: IVec3, max: IVec3, id: u8) {
+        self.write_region(min, max, |_| Some(id));
+    }
+
+    /// Replaces every voxel of id from with id to in the region, min and max inclusive
+    #[allow(dead_code)]
+    pub fn replace_region(&mut self, min: IVec3, max: IVec3, from: u8, to: u8) {
+        self.write_region(min, max, |id| (id == from).then_some(to));
+    }
+
+    /// Writes the result of write for each voxel in the region, None leaves the voxel unchanged
+    /// Chunks the region fully covers with a single id are replaced by a uniform chunk rather than written voxel by voxel
+    fn write_region(&mut self, min: IVec3, max: IVec3, write: impl Fn(u8) -> Option<u8>) {
+        let (min, max) = (min.min(max), min.max(max));
+        for key in Self::get_chunk_keys_in_region(min, max) {
+            let origin = key * CHUNK_SIZE_I32;
+            let local_min = (min - origin).max(IVec3::ZERO);
+            let local_max = (max - origin).min(IVec3::splat(CHUNK_SIZE_I32 - 1));
+            let covers_chunk = local_min == IVec3::ZERO && local_max == IVec3::splat(CHUNK_SIZE_I32 - 1);
+
+            let previous = self.chunks.get(&key).cloned()
+                .unwrap_or_else(|| Arc::new(Chunk::new(key, BlockIds::Air as u8)));
+            let mut chunk = previous.clone();
+            let mut changed = Vec::new();
+            if let Some(previous_id) = previous.get_uniform_id().filter(|_| covers_chunk) {
+                // Whole chunk becomes a single id, no need to touch individual voxels
+                if let Some(fill) = write(previous_id).filter(|fill| *fill != previous_id) {
+                    for i in 0..CHUNK_ARRAY_SIZE {
+                        let (x, y, z) = Chunk::get_block_position(i);
+                        changed.push((origin + IVec3::new(x as i32, y as i32, z as i32), previous_id, fill));
+                    }
+                    chunk = Arc::new(Chunk::new(key, fill));
+                }
+            } else {
+                for y in local_min.y..=local_max.y {
+                    for z in local_min.z..=local_max.z {
+                        for x in local_min.x..=local_max.x {
+                            let (lx, ly, lz) = (x as usize, y as usize, z as usize);
+                            let id = previous.get_voxel(lx, ly, lz);
+                            if let Some(new_id) = write(id).filter(|new_id| *new_id != id) {
+                                Arc::make_mut(&mut chunk).add_voxel(new_id, lx, ly, lz);
+                                changed.push((origin + IVec3::new(x, y, z), id, new_id));
+                            }
+                        }
+                    }
+                }
+            }
+
+            if changed.is_empty() {
+                continue;
+            }
+            if covers_chunk {
+                // Region may have left the chunk a single id
+                Arc::make_mut(&mut chunk).compact();
+            }
+            for (position, previous_id, id) in changed {
+                if get_block_properties(previous_id).has_block_entity || get_block_properties(id).has_block_entity {
+                    self.block_entity_changes.insert(position);
+                }
+                self.damage.remove(&position);
+            }
+            self.chunks.insert(key, chunk);
+            self.mark_chunk_and_neighbours_modified(key);
+        }
+    }
+
+    /// Keys of every chunk overlapping the region, min and max inclusive
+    fn get_chunk_keys_in_region(min: IVec3, max: IVec3) -> impl Iterator<Item = IVec3> {
+        let min_key = Self::get_chunk_key(min.x, min.y, min.z);
+        let max_key = Self::get_chunk_key(max.x, max.y, max.z);
+        (min_key.y..=max_key.y).flat_map(move |y| {
+            (min_key.z..=max_key.z).flat_map(move |z| (min_key.x..=max_key.x).map(move |x| IVec3::new(x, y, z)))
+        })
+    }
+
+    /// Iterates the position and id of every voxel in the region, min and max inclusive, a chunk at a time
+    pub fn voxels_in_aabb(&self, min: IVec3, max: IVec3) -> impl Iterator<Item = (IVec3, u8)> + '_ {
+        let (min, max) = (min.min(max), min.max(max));
+        Self::get_chunk_keys_in_region(min, max).flat_map(move |key| {
+            let chunk = self.chunks.get(&key);
+            let origin = key * CHUNK_SIZE_I32;
+            let local_min = (min - origin).max(IVec3::ZERO);
+            let local_max = (max - origin).min(IVec3::splat(CHUNK_SIZE_I32 - 1));
+            (local_min.y..=local_max.y).flat_map(move |y| {
+                (local_min.z..=local_max.z).flat_map(move |z| {
+                    (local_min.x..=local_max.x).map(move |x| {
+                        let id = chunk.map_or(BlockIds::Air as u8, |chunk| chunk.get_voxel(x as usize, y as usize, z as usize));
+                        (origin + IVec3::new(x, y, z), id)
+                    })
+                })
+            })
+        })
+    }
+
+    /// Iterates the position and id of every voxel whose center is within radius of center
+    #[allow(dead_code)]
+    pub fn voxels_in_sphere(&self, center: Vec3, radius: f32) -> impl Iterator<Item = (IVec3, u8)> + '_ {
+        let min = (center - Vec3::splat(radius)).floor().as_ivec3();
+        let max = (center + Vec3::splat(radius)).floor().as_ivec3();
+        self.voxels_in_aabb(min, max).filter(move |(position, _)| {
+            (position.as_vec3() + Vec3::splat(0.5)).distance_squared(center) <= radius * radius
+        })
+    }
+
+    /// Returns the y of the highest solid voxel in the column, None if the column is empty
+    pub fn get_highest_solid_in_column(&self, x: i32, z: i32) -> Option<i32> {
+        let (min, max) = self.get_chunk_bounds()?;
+        let key = Self::get_chunk_key(x, 0, z);
+        for key_y in (min.y..=max.y).rev() {
+            let chunk = match self.chunks.get(&IVec3::new(key.x, key_y, key.z)) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+            if chunk.get_uniform_id().is_some_and(|id| !get_block_properties(id).is_solid) {
+                continue;
+            }
+            let (local_x, _, local_z) = Self::get_position_in_chunk(IVec3::new(key.x, key_y, key.z), x, key_y * CHUNK_SIZE_I32, z);
+            for local_y in (0..CHUNK_SIZE).rev() {
+                if get_block_properties(chunk.get_voxel(local_x, local_y, local_z)).is_solid {
+                    return Some(key_y * CHUNK_SIZE_I32 + local_y as i32);
+                }
+            }
+        }
+        None
+    }
+
+    /// Estimates the surface normal at a point from the solid voxels around it, pointing away from solid
+    /// None if the neighbourhood is entirely solid or entirely empty
+    #[allow(dead_code)]
+    pub fn estimate_surface_normal(&self, position: Vec3) -> Option<Vec3> {
+        let center = position.floor().as_ivec3();
+        let solid_offsets: Vec<Vec3> = self.voxels_in_aabb(center - IVec3::ONE, center + IVec3::ONE)
+            .filter(|(_, id)| get_block_properties(*id).is_solid)
+            .map(|(voxel, _)| position - (voxel.as_vec3() + Vec3::splat(0.5)))
+            .collect();
+        // Offsets from a fully solid neighbourhood don't cancel exactly, so check for it explicitly
+        if solid_offsets.is_empty() || solid_offsets.len() == 27 {
+            return None;
+        }
+        let normal = solid_offsets.iter()
+            .map(|offset| offset.normalize_or_zero())
+            .fold(Vec3::ZERO, |sum, offset| sum + offset)
+            .normalize_or_zero();
+        (normal != Vec3::ZERO).then_some(normal)
+    }
+
+    /// Returns the minimum and maximum chunk keys, if there are any chunks
+    pub fn get_chunk_bounds(&self) -> Option<(IVec3, IVec3)> {
+        let mut keys = self.chunks.keys();
+        let first = *keys.next()?;
+        Some(keys.fold((first, first), |(min, max), key| (min.min(*key), max.max(*key))))
+    }
+
+    pub fn has_stale_visibility(&self) -> bool {
+        self.chunks.len() != self.visibility.len()
+    }
+
+    /// Recalculates visibility for any chunks which have changed since it was last calculated
+    pub fn update_visibility(&mut self) {
+        for (key, chunk) in self.chunks.iter() {
+            if !self.visibility.contains_key(key) {
+                self.visibility.insert(*key, ChunkVisibility::compute(chunk));
+            }
+        }
+    }
+
+    /// Chunks which don't exist are empty and so fully connected, as are chunks with stale visibility
+    pub fn get_chunk_visibility(&self, chunk_key: &IVec3) -> ChunkVisibility {
+        self.visibility.get(chunk_key).copied().unwrap_or_else(ChunkVisibility::all)
+    }
+
     pub fn get_slice_for_chunk(&self, chunk_key: &IVec3) -> Option<VorldSlice> {
-        if let Some(chunk) = self.chunks.get(&chunk_key) {
+        if let Some(chunk) = self.chunks.get(chunk_key) {
             return Some(VorldSlice {
-                chunk: *chunk,
+                chunk: chunk.clone(),
                 up_chunk: self.get_adjacent_chunk(chunk_key, IVec3::Y),
                 down_chunk: self.get_adjacent_chunk(chunk_key, IVec3::NEG_Y),
-                left_chunk: self.get_adjacent_chunk(chunk_key, IVec3::X),
-                right_chunk: self.get_adjacent_chunk(chunk_key, IVec3::NEG_X),
+                left_chunk: self.get_adjacent_chunk(chunk_key, IVec3::NEG_X),
+                right_chunk: self.get_adjacent_chunk(chunk_key, IVec3::X),
                 forward_chunk: self.get_adjacent_chunk(chunk_key, IVec3::Z),
                 back_chunk: self.get_adjacent_chunk(chunk_key, IVec3::NEG_Z),
+                climate: self.get_climate_corners(chunk_key),
             });
         }
         None
     }
 
-    fn get_adjacent_chunk(&self, chunk_key: &IVec3, offset: IVec3) -> Option<Chunk> {
+    /// Samples the climate map at each column corner of the chunk, so tints blend across chunk borders
+    fn get_climate_corners(&self, chunk_key: &IVec3) -> [Climate; CLIMATE_CORNERS] {
+        let mut corners = [Climate::TEMPERATE; CLIMATE_CORNERS];
+        for z in 0..=CHUNK_SIZE {
+            for x in 0..=CHUNK_SIZE {
+                corners[x + (CHUNK_SIZE + 1) * z] = self.climate.sample(
+                    (chunk_key.x * CHUNK_SIZE_I32 + x as i32) as f32,
+                    (chunk_key.z * CHUNK_SIZE_I32 + z as i32) as f32,
+                );
+            }
+        }
+        corners
+    }
+
+    fn get_adjacent_chunk(&self, chunk_key: &IVec3, offset: IVec3) -> Option<Arc<Chunk>> {
         self.chunks.get(&IVec3::new(
             chunk_key.x + offset.x,
             chunk_key.y + offset.y,
             chunk_key.z + offset.z))
-        .copied()
+        .cloned()
+    }
+
+    /// Re-encodes every chunk with the smallest storage for its contents, useful after bulk edits
+    pub fn compact_chunks(&mut self) {
+        for chunk in self.chunks.values_mut() {
+            Arc::make_mut(chunk).compact();
+        }
+    }
+
+    pub fn get_memory_usage(&self) -> VorldMemoryUsage {
+        let mut usage = VorldMemoryUsage::default();
+        for chunk in self.chunks.values() {
+            usage.chunks += 1;
+            usage.voxel_bytes += chunk.get_memory_usage();
+            match chunk.get_storage() {
+                ChunkStorage::Uniform(_) => usage.uniform_chunks += 1,
+                ChunkStorage::Palette { .. } => usage.palette_chunks += 1,
+                ChunkStorage::Full { .. } => usage.full_chunks += 1,
+            }
+        }
+        usage.dense_voxel_bytes = usage.chunks * CHUNK_ARRAY_SIZE;
+        usage
     }
 }
 
-/// Chunk and adjacent chunks data required for meshing
+/// Voxel hit by Vorld::raycast
+#[allow(dead_code)]
 #[derive(Copy, Clone, Debug)]
+pub struct VoxelRaycastHit {
+    /// World space position of the voxel hit
+    pub position: IVec3,
+    /// Normal of the face the ray entered through, zero if the ray started inside the voxel
+    pub normal: IVec3,
+    /// Distance along the ray to the point of entry
+    pub distance: f32,
+    pub id: u8,
+}
+
+/// Breakdown of chunk storage, dense_voxel_bytes is what storing every chunk as a full array would use
+#[derive(Default, Debug)]
+pub struct VorldMemoryUsage {
+    pub chunks: usize,
+    pub uniform_chunks: usize,
+    pub palette_chunks: usize,
+    pub full_chunks: usize,
+    pub voxel_bytes: usize,
+    pub dense_voxel_bytes: usize,
+}
+
+/// Number of column corners on the x/z face of a chunk
+pub const CLIMATE_CORNERS: usize = (CHUNK_SIZE + 1) * (CHUNK_SIZE + 1);
+
+/// Chunk and adjacent chunks data required for meshing
+#[derive(Clone, Debug)]
 pub struct VorldSlice {
-    pub chunk: Chunk,
-    pub up_chunk: Option<Chunk>,
-    pub down_chunk: Option<Chunk>,
-    pub left_chunk: Option<Chunk>,
-    pub right_chunk: Option<Chunk>,
-    pub forward_chunk: Option<Chunk>,
-    pub back_chunk: Option<Chunk>,
-}
\ No newline at end of file
+    pub chunk: Arc<Chunk>,
+    pub up_chunk: Option<Arc<Chunk>>,
+    pub down_chunk: Option<Arc<Chunk>>,
+    pub left_chunk: Option<Arc<Chunk>>,
+    pub right_chunk: Option<Arc<Chunk>>,
+    pub forward_chunk: Option<Arc<Chunk>>,
+    pub back_chunk: Option<Arc<Chunk>>,
+    /// Climate at each column corner, indexed x + (CHUNK_SIZE + 1) * z
+    pub climate: [Climate; CLIMATE_CORNERS],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x3 chunks of solid stone under 3x3 chunks of stone floor with a few wooden pillars
+    fn build_floor_vorld() -> Vorld {
+        let mut vorld = Vorld::new();
+        vorld.fill_region(IVec3::splat(-CHUNK_SIZE_I32), IVec3::new(2 * CHUNK_SIZE_I32 - 1, 1, 2 * CHUNK_SIZE_I32 - 1), BlockIds::Stone as u8);
+        for (x, z) in [(3, 3), (20, 5), (-10, 12)] {
+            vorld.fill_region(IVec3::new(x, 2, z), IVec3::new(x, 6, z), BlockIds::Wood as u8);
+        }
+        vorld
+    }
+
+    #[test]
+    fn replace_region_only_writes_matching_voxels() {
+        let mut vorld = build_floor_vorld();
+        let revision = vorld.get_revision();
+        // Spans the border between chunks 0 and 1 on x and the pillar at (20, 2..=6, 5)
+        vorld.replace_region(IVec3::new(10, 0, 0), IVec3::new(21, 4, 6), BlockIds::Stone as u8, BlockIds::Soil as u8);
+        assert!(vorld.get_revision() != revision);
+
+        for (position, id) in vorld.voxels_in_aabb(IVec3::new(9, 0, -1), IVec3::new(22, 6, 7)) {
+            let is_inside = position.cmpge(IVec3::new(10, 0, 0)).all() && position.cmple(IVec3::new(21, 4, 6)).all();
+            let expected = if position.y <= 1 {
+                if is_inside { BlockIds::Soil } else { BlockIds::Stone }
+            } else if position.x == 20 && position.z == 5 {
+                BlockIds::Wood
+            } else {
+                BlockIds::Air
+            };
+            assert_eq!(id, expected as u8, "{}", position);
+        }
+        assert!(vorld.take_modified_chunks().contains(&IVec3::new(1, 0, 0)));
+    }
+
+    #[test]
+    fn voxels_in_sphere_tests_voxel_centers() {
+        let vorld = build_floor_vorld();
+        let center = Vec3::new(0.5, 1.5, 0.5);
+        let voxels: Vec<(IVec3, u8)> = vorld.voxels_in_sphere(center, 1.0).collect();
+        // The center voxel and its six face neighbours, edge neighbours are sqrt(2) away
+        assert_eq!(voxels.len(), 7);
+        assert!(voxels.iter().all(|(position, _)| (*position - IVec3::new(0, 1, 0)).abs().to_array().iter().sum::<i32>() <= 1));
+        assert_eq!(voxels.iter().filter(|(_, id)| *id == BlockIds::Stone as u8).count(), 6);
+
+        assert_eq!(vorld.voxels_in_sphere(center, 1.5).count(), 19);
+        assert_eq!(vorld.voxels_in_sphere(center, 0.4).count(), 1);
+    }
+
+    #[test]
+    fn surface_normal_points_away_from_solid() {
+        let vorld = build_floor_vorld();
+        let normal = vorld.estimate_surface_normal(Vec3::new(8.5, 2.1, 8.5)).unwrap();
+        assert!(normal.abs_diff_eq(Vec3::Y, 1e-5), "{}", normal);
+
+        // Beside the pillar at (3, 2..=6, 3), on its +x side
+        let normal = vorld.estimate_surface_normal(Vec3::new(4.1, 4.5, 3.5)).unwrap();
+        assert!(normal.abs_diff_eq(Vec3::X, 1e-5), "{}", normal);
+
+        assert!(vorld.estimate_surface_normal(Vec3::new(0.5, -8.5, 0.5)).is_none());
+        assert!(vorld.estimate_surface_normal(Vec3::new(0.5, 12.5, 0.5)).is_none());
+    }
+
+    #[test]
+    fn sparse_storage_is_smaller_than_dense() {
+        let vorld = build_floor_vorld();
+        let usage = vorld.get_memory_usage();
+        assert_eq!(usage.chunks, 18);
+        assert_eq!(usage.dense_voxel_bytes, 18 * CHUNK_ARRAY_SIZE);
+        // Only the floor layer holds more than one id, and none of it needs more than 2 bits per voxel
+        assert_eq!(usage.palette_chunks, 9);
+        assert_eq!(usage.full_chunks, 0);
+        assert_eq!(usage.uniform_chunks, 9);
+        assert!(usage.voxel_bytes * 4 < usage.dense_voxel_bytes, "{:?}", usage);
+    }
+
+    #[test]
+    fn slices_share_chunks_until_written() {
+        let mut vorld = build_floor_vorld();
+        let key = IVec3::ZERO;
+        let right_key = IVec3::X;
+        assert_eq!(Arc::strong_count(&vorld.chunks[&key]), 1);
+
+        let slices: Vec<VorldSlice> = (0..3).map(|_| vorld.get_slice_for_chunk(&key).unwrap()).collect();
+        assert!(Arc::ptr_eq(&slices[0].chunk, &vorld.chunks[&key]));
+        assert!(Arc::ptr_eq(slices[0].right_chunk.as_ref().unwrap(), &vorld.chunks[&right_key]));
+        assert_eq!(Arc::strong_count(&vorld.chunks[&key]), 4);
+        assert_eq!(Arc::strong_count(&vorld.chunks[&right_key]), 4);
+        // Taking a slice copies pointers and climate, never voxels
+        assert!(std::mem::size_of::<VorldSlice>() < CHUNK_ARRAY_SIZE);
+
+        // Writing copies only the chunk written to, slices keep the voxels they were taken with
+        vorld.add_voxel(BlockIds::Stone as u8, 4, 4, 4);
+        assert!(!Arc::ptr_eq(&slices[0].chunk, &vorld.chunks[&key]));
+        assert_eq!(Arc::strong_count(&vorld.chunks[&key]), 1);
+        assert_eq!(Arc::strong_count(&slices[0].chunk), 3);
+        assert_eq!(Arc::strong_count(&vorld.chunks[&right_key]), 4);
+        assert_eq!(slices[0].chunk.get_voxel(4, 4, 4), BlockIds::Air as u8);
+        assert_eq!(vorld.get_voxel(4, 4, 4), BlockIds::Stone as u8);
+
+        drop(slices);
+        assert_eq!(Arc::strong_count(&vorld.chunks[&right_key]), 1);
+    }
+}
@@ -0,0 +1,396 @@
+use bevy::{
+    prelude::*,
+    render::camera::Projection,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+use std::collections::{HashMap, HashSet};
+
+use super::character_controller::KinematicCharacterController;
+use super::health::DealDamageEvent;
+use super::player::{self, PlayerCamera};
+use super::voxel::atlas_loader::{AtlasTexture, OverlayMaterial};
+use super::voxel::prelude::*;
+use super::voxel::StartupVorld;
+
+pub struct WaterConfig {
+    /// Height of the water surface, columns whose ground is below it are flooded, None if the vorld has no water
+    pub sea_level: Option<f32>,
+    /// Atlas tile drawn on the surface, animated by the atlas config
+    pub surface_tile: u32,
+    /// Colour of the water when seen from underneath the surface
+    pub colour: Color,
+    /// Distance the camera can see while underwater, beyond it everything is cut off to the water colour
+    pub underwater_visibility: f32,
+}
+
+/// Box of water between min and max in world space, characters in it swim
+#[derive(Component)]
+pub struct WaterVolume {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl WaterVolume {
+    /// Depth of the point below the surface of the water, None if the point isn't in the volume
+    pub fn get_depth(&self, point: Vec3) -> Option<f32> {
+        (point.cmpge(self.min).all() && point.cmplt(self.max).all()).then_some(self.max.y - point.y)
+    }
+}
+
+/// Depth of the point below the surface of the deepest water volume containing it, zero if it isn't in water
+pub fn get_water_depth<'a>(volumes: impl IntoIterator<Item = &'a WaterVolume>, point: Vec3) -> f32 {
+    volumes.into_iter().filter_map(|volume| volume.get_depth(point)).fold(0.0, f32::max)
+}
+
+/// Time a character with a KinematicCharacterController can keep their head underwater before drowning
+#[derive(Component)]
+pub struct Breath {
+    /// Breath in seconds
+    pub max_breath: f32,
+    pub current_breath: f32,
+    /// Seconds of breath regained per second with the head above water
+    pub recovery_rate: f32,
+    /// Damage dealt each damage_interval once out of breath
+    pub damage: u32,
+    pub damage_timer: Timer,
+}
+
+impl Breath {
+    pub fn new(max_breath: f32, recovery_rate: f32, damage: u32, damage_interval: f32) -> Self {
+        Self {
+            max_breath,
+            current_breath: max_breath,
+            recovery_rate,
+            damage,
+            damage_timer: Timer::from_seconds(damage_interval, true),
+        }
+    }
+}
+
+/// Surface settings of the camera, restored when it leaves the water
+struct UnderwaterState {
+    is_underwater: bool,
+    surface_far: f32,
+    surface_clear_colour: Color,
+}
+
+/// Mesh of the top of the water volumes, given the surface tile's material once the atlas has loaded
+#[derive(Component)]
+struct WaterSurface;
+
+/// Revisions the water volumes were last built against, and the water entities built for each column of chunks
+#[derive(Default)]
+struct Flooding {
+    vorld_revision: Option<u32>,
+    chunk_revisions: HashMap<IVec3, u32>,
+    water: HashMap<IVec2, Vec<Entity>>,
+}
+
+impl Flooding {
+    /// Columns of chunks, by chunk x and z, containing a chunk written to since the last call
+    fn take_changed_chunk_columns(&mut self, vorld: &Vorld) -> HashSet<IVec2> {
+        let mut changed = HashSet::new();
+        let known_keys: Vec<IVec3> = self.chunk_revisions.keys().copied().collect();
+        for key in vorld.chunks.keys().copied().chain(known_keys) {
+            let revision = vorld.get_chunk_revision(&key);
+            if self.chunk_revisions.insert(key, revision) != Some(revision) {
+                changed.insert(IVec2::new(key.x, key.z));
+            }
+        }
+        changed
+    }
+}
+
+/// Full screen tint shown while the camera is underwater
+#[derive(Component)]
+struct UnderwaterOverlay;
+
+pub struct WaterPlugin;
+
+impl Plugin for WaterPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WaterConfig {
+            sea_level: None,
+            surface_tile: 12,
+            colour: Color::rgba(0.15, 0.35, 0.55, 0.65),
+            underwater_visibility: 24.0,
+        });
+        app.insert_resource(UnderwaterState {
+            is_underwater: false,
+            surface_far: 0.0,
+            surface_clear_colour: Color::BLACK,
+        });
+        app.init_resource::<Flooding>();
+        app.add_startup_system(setup)
+            .add_system(update_water_volumes)
+            .add_system(update_breath)
+            .add_system(apply_surface_material.after(update_water_volumes))
+            .add_system_to_stage(CoreStage::PostUpdate, update_underwater_effect.after(player::update_look));
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    startup_vorld: Res<StartupVorld>,
+    mut config: ResMut<WaterConfig>,
+) {
+    config.sea_level = startup_vorld.get_sea_level();
+    if config.sea_level.is_none() {
+        return;
+    }
+
+    let overlay_colour = Color::rgba(config.colour.r(), config.colour.g(), config.colour.b(), 0.35);
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            ..default()
+        },
+        color: UiColor(overlay_colour),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }).insert(UnderwaterOverlay);
+}
+
+/// Floods the columns from min_column to max_column whose ground is below sea level, with a volume for each run of flooded columns along x
+fn build_water_volumes(vorld: &Vorld, sea_level: f32, min_column: IVec2, max_column: IVec2) -> Vec<WaterVolume> {
+    let mut volumes = Vec::new();
+    for z in min_column.y..=max_column.y {
+        // Start of the current run and the lowest ground in it
+        let mut run: Option<(i32, i32)> = None;
+        for x in min_column.x..=max_column.x + 1 {
+            let ground = (x <= max_column.x)
+                .then(|| vorld.get_highest_solid_in_column(x, z))
+                .flatten()
+                .map(|y| y + 1)
+                .filter(|y| (*y as f32) < sea_level);
+            run = match (run, ground) {
+                (Some((start, bottom)), Some(ground)) => Some((start, bottom.min(ground))),
+                (None, Some(ground)) => Some((x, ground)),
+                (Some((start, bottom)), None) => {
+                    volumes.push(WaterVolume {
+                        min: Vec3::new(start as f32, bottom as f32, z as f32),
+                        max: Vec3::new(x as f32, sea_level, (z + 1) as f32),
+                    });
+                    None
+                },
+                (None, None) => None,
+            };
+        }
+    }
+    volumes
+}
+
+/// Mesh of the top of the volumes, only the surface is drawn as the sides of the volumes are against the ground
+/// A quad per column so the surface tile repeats once per voxel
+fn build_surface_mesh(volumes: &[WaterVolume]) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    for volume in volumes.iter() {
+        let (y, z) = (volume.max.y, volume.min.z);
+        for x in volume.min.x as i32..volume.max.x as i32 {
+            let (x, index) = (x as f32, positions.len() as u32);
+            positions.extend([[x, y, z], [x, y, z + 1.0], [x + 1.0, y, z + 1.0], [x + 1.0, y, z]]);
+            uvs.extend([[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]]);
+            indices.extend([index, index + 1, index + 2, index, index + 2, index + 3]);
+        }
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Rebuilds the water volumes and surface of each column of chunks written to, so digging below sea level floods and filling drains
+fn update_water_volumes(
+    mut commands: Commands,
+    config: Res<WaterConfig>,
+    vorld: Res<Vorld>,
+    atlas: Res<AtlasTexture>,
+    mut flooding: ResMut<Flooding>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let sea_level = match config.sea_level {
+        Some(sea_level) => sea_level,
+        None => return,
+    };
+    let vorld_revision = Some(vorld.get_revision());
+    if flooding.vorld_revision == vorld_revision {
+        return;
+    }
+    flooding.vorld_revision = vorld_revision;
+
+    for chunk_column in flooding.take_changed_chunk_columns(&vorld) {
+        for entity in flooding.water.remove(&chunk_column).unwrap_or_default() {
+            commands.entity(entity).despawn();
+        }
+        let min_column = chunk_column * CHUNK_SIZE_I32;
+        let max_column = min_column + IVec2::splat(CHUNK_SIZE_I32 - 1);
+        let volumes = build_water_volumes(&vorld, sea_level, min_column, max_column);
+        debug!("Flooded {} runs of columns below sea level in chunk column {}", volumes.len(), chunk_column);
+        if volumes.is_empty() {
+            continue;
+        }
+
+        let mut entities = vec![commands.spawn_bundle(MaterialMeshBundle::<OverlayMaterial> {
+            mesh: meshes.add(build_surface_mesh(&volumes)),
+            material: atlas.overlay_materials.get(&config.surface_tile).cloned().unwrap_or_default(),
+            ..default()
+        }).insert(WaterSurface).id()];
+        for volume in volumes {
+            entities.push(commands.spawn().insert(volume).id());
+        }
+        flooding.water.insert(chunk_column, entities);
+    }
+}
+
+fn apply_surface_material(
+    config: Res<WaterConfig>,
+    atlas: Res<AtlasTexture>,
+    mut surface_query: Query<&mut Handle<OverlayMaterial>, With<WaterSurface>>,
+) {
+    if let Some(material) = atlas.overlay_materials.get(&config.surface_tile) {
+        for mut handle in surface_query.iter_mut() {
+            if *handle != *material {
+                *handle = material.clone();
+            }
+        }
+    }
+}
+
+/// Uses up breath while the character's head is underwater, dealing damage once it runs out
+fn update_breath(
+    time: Res<Time>,
+    mut damage_events: EventWriter<DealDamageEvent>,
+    mut breath_query: Query<(Entity, &mut Breath, &KinematicCharacterController)>,
+) {
+    for (entity, mut breath, controller) in breath_query.iter_mut() {
+        if !controller.is_head_underwater {
+            breath.current_breath = (breath.current_breath + breath.recovery_rate * time.delta_seconds()).min(breath.max_breath);
+            breath.damage_timer.reset();
+            continue;
+        }
+        if breath.current_breath > 0.0 {
+            breath.current_breath = (breath.current_breath - time.delta_seconds()).max(0.0);
+        } else if breath.damage_timer.tick(time.delta()).just_finished() {
+            damage_events.send(DealDamageEvent { entity, damage: breath.damage });
+        }
+    }
+}
+
+/// Tints the screen and limits how far the camera can see while it is underwater
+/// Stands in for fog by pulling in the far plane and clearing to the water colour, so distant terrain is clipped rather than faded
+fn update_underwater_effect(
+    config: Res<WaterConfig>,
+    mut state: ResMut<UnderwaterState>,
+    mut clear_colour: ResMut<ClearColor>,
+    water_query: Query<&WaterVolume>,
+    mut camera_query: Query<(&Transform, &mut Projection), With<PlayerCamera>>,
+    mut overlay_query: Query<&mut Visibility, With<UnderwaterOverlay>>,
+) {
+    let (camera_transform, mut projection) = match camera_query.iter_mut().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+    let is_underwater = get_water_depth(water_query.iter(), camera_transform.translation) > 0.0;
+    if is_underwater == state.is_underwater {
+        return;
+    }
+    state.is_underwater = is_underwater;
+
+    if let Projection::Perspective(perspective) = projection.as_mut() {
+        if is_underwater {
+            state.surface_far = perspective.far;
+            state.surface_clear_colour = clear_colour.0;
+            perspective.far = config.underwater_visibility;
+            clear_colour.0 = Color::rgb(config.colour.r(), config.colour.g(), config.colour.b());
+        } else {
+            perspective.far = state.surface_far;
+            clear_colour.0 = state.surface_clear_colour;
+        }
+    }
+    for mut visibility in overlay_query.iter_mut() {
+        visibility.is_visible = is_underwater;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ground with its top at y 2 everywhere except a basin with its floor at y 0 from x 2 to 4 and z 2 to 3
+    fn build_basin_vorld() -> Vorld {
+        let mut vorld = Vorld::new();
+        vorld.fill_region(IVec3::new(0, -3, 0), IVec3::new(15, 1, 15), BlockIds::Stone as u8);
+        vorld.fill_region(IVec3::new(2, 0, 2), IVec3::new(4, 1, 3), BlockIds::Air as u8);
+        vorld
+    }
+
+    fn get_bounds(volumes: &[WaterVolume]) -> Vec<(Vec3, Vec3)> {
+        volumes.iter().map(|volume| (volume.min, volume.max)).collect()
+    }
+
+    #[test]
+    fn floods_columns_below_sea_level() {
+        let vorld = build_basin_vorld();
+        let volumes = build_water_volumes(&vorld, 1.5, IVec2::ZERO, IVec2::splat(15));
+        assert_eq!(get_bounds(&volumes), vec![
+            (Vec3::new(2.0, 0.0, 2.0), Vec3::new(5.0, 1.5, 3.0)),
+            (Vec3::new(2.0, 0.0, 3.0), Vec3::new(5.0, 1.5, 4.0)),
+        ]);
+        assert_eq!(get_water_depth(volumes.iter(), Vec3::new(3.5, 0.5, 2.5)), 1.0);
+        assert_eq!(get_water_depth(volumes.iter(), Vec3::new(5.5, 0.5, 2.5)), 0.0);
+
+        // Ground level with the sea isn't flooded
+        assert_eq!(build_water_volumes(&vorld, 2.0, IVec2::ZERO, IVec2::splat(15)).len(), 2);
+        assert!(build_water_volumes(&vorld, 0.0, IVec2::ZERO, IVec2::splat(15)).is_empty());
+
+        let mesh = build_surface_mesh(&volumes);
+        assert_eq!(mesh.count_vertices(), 4 * 6);
+    }
+
+    #[test]
+    fn edits_change_flooded_columns() {
+        let mut vorld = build_basin_vorld();
+        // Digging a channel out of the basin extends the run, a deeper hole lowers its bottom
+        vorld.fill_region(IVec3::new(5, 0, 2), IVec3::new(6, 1, 2), BlockIds::Air as u8);
+        vorld.add_voxel(BlockIds::Air as u8, 3, -1, 3);
+        let volumes = build_water_volumes(&vorld, 1.5, IVec2::ZERO, IVec2::splat(15));
+        assert_eq!(get_bounds(&volumes), vec![
+            (Vec3::new(2.0, 0.0, 2.0), Vec3::new(7.0, 1.5, 3.0)),
+            (Vec3::new(2.0, -1.0, 3.0), Vec3::new(5.0, 1.5, 4.0)),
+        ]);
+
+        // Filling the middle of a row splits it in two
+        vorld.fill_region(IVec3::new(4, 0, 2), IVec3::new(4, 1, 2), BlockIds::Stone as u8);
+        let volumes = build_water_volumes(&vorld, 1.5, IVec2::ZERO, IVec2::splat(15));
+        assert_eq!(get_bounds(&volumes), vec![
+            (Vec3::new(2.0, 0.0, 2.0), Vec3::new(4.0, 1.5, 3.0)),
+            (Vec3::new(5.0, 0.0, 2.0), Vec3::new(7.0, 1.5, 3.0)),
+            (Vec3::new(2.0, -1.0, 3.0), Vec3::new(5.0, 1.5, 4.0)),
+        ]);
+    }
+
+    #[test]
+    fn only_written_chunk_columns_change() {
+        let mut vorld = build_basin_vorld();
+        vorld.fill_region(IVec3::new(16, -3, 0), IVec3::new(31, 1, 15), BlockIds::Stone as u8);
+        let mut flooding = Flooding::default();
+        assert_eq!(flooding.take_changed_chunk_columns(&vorld), HashSet::from_iter([IVec2::ZERO, IVec2::X]));
+        assert!(flooding.take_changed_chunk_columns(&vorld).is_empty());
+
+        // A write marks its column, even in a new chunk beneath the others
+        vorld.add_voxel(BlockIds::Stone as u8, 20, -20, 4);
+        assert_eq!(flooding.take_changed_chunk_columns(&vorld), HashSet::from_iter([IVec2::new(1, 0)]));
+        vorld.add_voxel(BlockIds::Air as u8, 3, 1, 3);
+        assert_eq!(flooding.take_changed_chunk_columns(&vorld), HashSet::from_iter([IVec2::ZERO]));
+
+        // Runs stop at the edge of the columns they're built for
+        vorld.fill_region(IVec3::new(14, 0, 8), IVec3::new(17, 1, 8), BlockIds::Air as u8);
+        let volumes = build_water_volumes(&vorld, 1.5, IVec2::ZERO, IVec2::splat(15));
+        assert_eq!(volumes.last().map(|volume| (volume.min, volume.max)), Some((Vec3::new(14.0, 0.0, 8.0), Vec3::new(16.0, 1.5, 9.0))));
+    }
+}
@@ -1,7 +1,9 @@
 use bevy::prelude::*;
+use crate::navigation::{NavConfig, NavGrid};
 use crate::npc_spawner::NpcAssets;
+use crate::voxel::prelude::Vorld;
 
-use super::player::Player;
+use super::player::{Player, PlayerDeath};
 
 #[derive(Debug, PartialEq, Eq)]
 enum ZombieState {
@@ -12,12 +14,20 @@ enum ZombieState {
 #[derive(Component)]
 pub struct Zombie {
     state: ZombieState,
+    /// Remaining waypoints to the target, nearest first
+    path: Vec<Vec3>,
+    /// Cell the current path leads to
+    path_goal: Option<IVec3>,
+    repath_cooldown: f32,
 }
 
 impl Zombie {
     pub fn new() -> Self {
         Self { 
             state: ZombieState::Idle,
+            path: Vec::new(),
+            path_goal: None,
+            repath_cooldown: 0.0,
         }
     }
 }
@@ -30,24 +40,67 @@ impl Plugin for NpcAiPlugin {
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn seek_brains(
     time: Res<Time>,
     npc_assets: Res<NpcAssets>,
-    player_query: Query<&Transform, (With<Player>, Without<Zombie>)>,
+    vorld: Res<Vorld>,
+    nav_config: Res<NavConfig>,
+    nav_grid: Res<NavGrid>,
+    player_query: Query<&Transform, (With<Player>, Without<PlayerDeath>, Without<Zombie>)>,
     mut zombie_query: Query<(&mut Transform, &mut Zombie, &super::npc_spawner::Npc)>,
     mut animation_query: Query<&mut AnimationPlayer>,
 ) {
+    let move_speed = 1.0;
+    let climb_speed = 4.0;
+    let repath_interval = 0.5;
+    // Horizontal distance within which zombies notice the player
+    let seek_range = 8.0;
+
     if let Some(player_transform) = player_query.iter().last() {
+        let player_cell = nav_grid.find_ground_cell(&nav_config, player_transform.translation);
         for (mut transform, mut zombie, npc) in zombie_query.iter_mut() {
-            let target_position = Vec3::new(player_transform.translation.x, transform.translation.y, player_transform.translation.z);
-            let target_direction = target_position - transform.translation;
-            let distance_sqr = target_direction.length_squared();
+            let target_direction = player_transform.translation - transform.translation;
+            let distance_sqr = Vec3::new(target_direction.x, 0.0, target_direction.z).length_squared();
             let mut is_moving = false;
 
-            if distance_sqr < 8.0 * 8.0 && distance_sqr > 1.0 {
-                transform.look_at(target_position, Vec3::Y);
-                transform.translation += target_direction.normalize() * time.delta_seconds();
-                is_moving = true;
+            zombie.repath_cooldown -= time.delta_seconds();
+            if distance_sqr < seek_range * seek_range && distance_sqr > 1.0 {
+                if zombie.path_goal != player_cell || zombie.repath_cooldown <= 0.0 {
+                    zombie.repath_cooldown = repath_interval;
+                    zombie.path_goal = player_cell;
+                    zombie.path.clear();
+                    let zombie_cell = nav_grid.find_ground_cell(&nav_config, transform.translation + 0.5 * Vec3::Y);
+                    if let (Some(start), Some(goal)) = (zombie_cell, player_cell) {
+                        if let Some(path) = nav_grid.find_path(&vorld, &nav_config, start, goal) {
+                            zombie.path = nav_grid.smooth_path(&nav_config, &path);
+                            zombie.path.reverse();
+                            // First waypoint is the cell we're standing in
+                            zombie.path.pop();
+                        }
+                    }
+                }
+
+                if let Some(waypoint) = zombie.path.last().copied() {
+                    let to_waypoint = Vec3::new(waypoint.x - transform.translation.x, 0.0, waypoint.z - transform.translation.z);
+                    let step = move_speed * time.delta_seconds();
+                    if to_waypoint.length() <= step {
+                        transform.translation.x = waypoint.x;
+                        transform.translation.z = waypoint.z;
+                        zombie.path.pop();
+                    } else {
+                        let look_target = Vec3::new(waypoint.x, transform.translation.y, waypoint.z);
+                        transform.look_at(look_target, Vec3::Y);
+                        transform.translation += to_waypoint.normalize() * step;
+                    }
+                    // Climb or drop towards the level of the waypoint
+                    let dy = waypoint.y - transform.translation.y;
+                    transform.translation.y += dy.clamp(-climb_speed * time.delta_seconds(), climb_speed * time.delta_seconds());
+                    is_moving = true;
+                }
+            } else {
+                zombie.path.clear();
+                zombie.path_goal = None;
             }
 
             let new_state = match is_moving {